@@ -0,0 +1,1583 @@
+//! The `sf` command line: argument parsing, wiring the flags into the search pipeline, and
+//! exit statuses.
+
+use std::{
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::LazyLock,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    completions, criteria, exec, filter, kind, ls_colors, mdfind, output, owner, query,
+    search::make_absolute_dir, stats, volume,
+};
+use anyhow::{Context, Result};
+use clap::{CommandFactory, Parser};
+
+/// The fd release whose filtering semantics `sf` follows (and the parity tests port).
+const FD_PARITY_VERSION: &str = "10.3.0";
+
+static VERSION: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "{} (fd {FD_PARITY_VERSION} semantics)",
+        env!("CARGO_PKG_VERSION")
+    )
+});
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "sf",
+    about = "Spotlight-powered file finding with fd-like ignore semantics (macOS only).",
+    version = VERSION.as_str(),
+    group = clap::ArgGroup::new("exec_any")
+        .args(["exec", "exec_batch", "exec_batch_stdin"])
+        .multiple(false)
+)]
+struct Args {
+    /// Glob (contains '*' or '?') or substring match; force either with `-g`/`-F`.
+    ///
+    /// If omitted, lists all files under the search path.
+    ///
+    /// Matching is fd-like "smart case": case-insensitive unless the pattern contains any
+    /// uppercase character. Use `-s`/`-i` to force either mode.
+    #[arg(value_name = "pattern")]
+    pattern: Option<String>,
+
+    /// Directories to scope search (default: current directory). Each result is printed
+    /// relative to the path it was found under.
+    #[arg(value_name = "path")]
+    paths: Vec<PathBuf>,
+
+    /// Print paths relative to this directory instead of the current one. The search
+    /// paths don't change.
+    ///
+    /// It also takes precedence over an explicit search path for the relative root;
+    /// `--absolute-path` still prints absolute paths. `--exec` commands run in the current
+    /// directory, so they can't take these paths.
+    #[arg(
+        long = "base-directory",
+        value_name = "path",
+        conflicts_with_all = ["relative_to_repo", "exec_any"]
+    )]
+    base_directory: Option<PathBuf>,
+
+    /// Filter paths read from standard input (one per line) instead of asking Spotlight.
+    ///
+    /// Every other filter, including the pattern, still applies. Relative paths are
+    /// resolved against the current directory, and paths outside the search path are
+    /// skipped.
+    #[arg(
+        long = "from-stdin",
+        conflicts_with_all = ["content_types", "kinds", "tags", "content", "query"]
+    )]
+    from_stdin: bool,
+
+    /// If Spotlight finds nothing at all (e.g. indexing is off for the volume), walk the
+    /// search path instead. Slower; a note on stderr says when it happens.
+    #[arg(
+        long = "walk",
+        conflicts_with_all = ["from_stdin", "content_types", "kinds", "tags", "content", "query"]
+    )]
+    walk: bool,
+
+    /// Include hidden files and directories (names starting with '.').
+    #[arg(short = 'H', long = "hidden")]
+    hidden: bool,
+
+    /// Don't respect ignore files (.gitignore/.ignore/.fdignore/global ignores).
+    ///
+    /// Does not imply `--hidden`.
+    #[arg(short = 'I', long = "no-ignore")]
+    no_ignore: bool,
+
+    /// Unrestricted search: alias for `--no-ignore --hidden`.
+    ///
+    /// May be repeated (`-uu`) for compatibility with fd; extra occurrences change nothing.
+    #[arg(short = 'u', long = "unrestricted", action = clap::ArgAction::Count)]
+    unrestricted: u8,
+
+    /// Don't respect git's ignore files (.gitignore, .git/info/exclude and the global
+    /// gitignore). `.ignore` and `.fdignore` still apply.
+    #[arg(long = "no-ignore-vcs")]
+    no_ignore_vcs: bool,
+
+    /// Don't report anything below a directory that matched (fd's `--prune`).
+    ///
+    /// Results are sorted by path and only printed once Spotlight has answered.
+    #[arg(long = "prune")]
+    prune: bool,
+
+    /// Exclude entries matching this glob, even if ignore files would include them.
+    ///
+    /// Uses `.gitignore` syntax relative to the search path: `*.log` matches at any depth,
+    /// `/build/` only the top-level directory (and everything in it). Repeatable, and
+    /// applies even with `--no-ignore`.
+    #[arg(short = 'E', long = "exclude", value_name = "glob")]
+    excludes: Vec<String>,
+
+    /// Read `--exclude` globs from a file, one per line. Blank lines and lines starting
+    /// with '#' are skipped. Repeatable.
+    #[arg(long = "exclude-from", value_name = "path")]
+    exclude_files: Vec<PathBuf>,
+
+    /// Add a custom ignore file in '.gitignore' format. Repeat to add several; later
+    /// files take precedence.
+    ///
+    /// Applies even with `--no-ignore`.
+    #[arg(long = "ignore-file", value_name = "path")]
+    ignore_files: Vec<PathBuf>,
+
+    /// Also read ignore files with this name in every directory, like '.ignore', e.g.
+    /// '.rgignore'. Repeat to add several; later names take precedence.
+    ///
+    /// They rank just below '.fdignore' and are skipped by `--no-ignore`.
+    #[arg(
+        long = "ignore-name",
+        value_name = "name",
+        value_parser = filter::parse_ignore_name
+    )]
+    ignore_names: Vec<String>,
+
+    /// Don't respect ignore files in directories above the search path.
+    #[arg(long = "no-ignore-parent")]
+    no_ignore_parent: bool,
+
+    /// Only show results at most this many levels below the search path (1 = direct
+    /// children). 0 allows only the search path itself, which is never printed, so
+    /// nothing matches. Unlimited by default.
+    #[arg(short = 'd', long = "max-depth", value_name = "depth")]
+    max_depth: Option<usize>,
+
+    /// Only show results at least this many levels below the search path.
+    #[arg(long = "min-depth", value_name = "depth")]
+    min_depth: Option<usize>,
+
+    /// Only show results exactly this many levels below the search path. Shorthand for
+    /// the same `--min-depth` and `--max-depth`, so it can't be combined with either.
+    #[arg(
+        long = "exact-depth",
+        value_name = "depth",
+        conflicts_with_all = ["min_depth", "max_depth"]
+    )]
+    exact_depth: Option<usize>,
+
+    /// Leave out results whose name matches this pattern, e.g. `sf report --not draft`.
+    ///
+    /// Interpreted like the main pattern (glob or substring, `-p`, `-g`, `-F`), with its
+    /// own smart case. Repeat to exclude several.
+    #[arg(long = "not", value_name = "pattern")]
+    not_patterns: Vec<String>,
+
+    /// Only keep results whose name also matches this pattern, e.g. `sf foo --and bar`.
+    ///
+    /// Interpreted like the main pattern (glob or substring, `-p`, `-g`, `-F`), with its
+    /// own smart case. Repeat to require several.
+    #[arg(long = "and", value_name = "pattern")]
+    and_patterns: Vec<String>,
+
+    /// Treat the pattern as a literal substring, even if it contains '*' or '?'.
+    #[arg(short = 'F', long = "fixed-strings")]
+    fixed_strings: bool,
+
+    /// Treat the pattern as a glob even without wildcards, matched against the whole file
+    /// name: `-g config` finds 'config' but not 'config.toml'.
+    #[arg(short = 'g', long = "glob", conflicts_with = "fixed_strings")]
+    glob: bool,
+
+    /// Match whole words only: `cat` finds 'my-cat.txt' but not 'category'.
+    ///
+    /// Applies to plain name patterns and, via Spotlight's word matching, to `--content`.
+    /// Globs already match the whole name.
+    #[arg(short = 'w', long = "word")]
+    word: bool,
+
+    /// Ignore accents and other diacritics when matching (e.g. 'cafe' matches 'café').
+    #[arg(long = "ignore-diacritics")]
+    ignore_diacritics: bool,
+
+    /// Case-sensitive matching (overrides smart case).
+    #[arg(short = 's', long = "case-sensitive", conflicts_with = "ignore_case")]
+    case_sensitive: bool,
+
+    /// Case-insensitive matching (overrides smart case).
+    #[arg(short = 'i', long = "ignore-case")]
+    ignore_case: bool,
+
+    /// Match the pattern against the path relative to the search path, not just the
+    /// file name.
+    ///
+    /// Globs containing '/' always match against the relative path.
+    #[arg(short = 'p', long = "full-path")]
+    full_path: bool,
+
+    /// Match the pattern against the file name without its extension, e.g. `-g mod -e rs
+    /// --match-stem` finds `mod.rs` but not `module.rs` or `mod.rs.bak`.
+    ///
+    /// Only the last extension is dropped (`a.tar.gz` has the stem `a.tar`). Smart case
+    /// still follows the pattern. Without this flag the pattern sees the whole name.
+    #[arg(long = "match-stem", conflicts_with = "full_path")]
+    match_stem: bool,
+
+    /// Filter by type: file (f), directory (d), symlink (l), executable (x), empty (e),
+    /// socket (s), pipe (p).
+    ///
+    /// Repeat to match any of several types.
+    #[arg(short = 't', long = "type", value_name = "filetype", value_enum)]
+    file_types: Vec<criteria::FileType>,
+
+    /// Filter by file extension. Repeat to match any of several.
+    ///
+    /// Case-insensitive unless the search path is on a case-sensitive volume (or
+    /// `-s`/`-i` is given). Never matches directories unless `--type d` is also given.
+    #[arg(short = 'e', long = "extension", value_name = "ext")]
+    extensions: Vec<String>,
+
+    /// Only match items of this Spotlight content type (UTI), e.g. 'public.image'.
+    ///
+    /// Subtypes match too. Repeat to match any of several.
+    #[arg(long = "content-type", value_name = "uti")]
+    content_types: Vec<String>,
+
+    /// Only match items of a kind: document, archive, image, audio, video, source, pdf.
+    ///
+    /// Shorthand for a set of `--content-type` values. Repeat to match any of several.
+    #[arg(long = "kind", value_name = "kind", value_parser = kind::parse_kind)]
+    kinds: Vec<&'static kind::Kind>,
+
+    /// Filter by owning user and/or group: 'user', 'user:group' or ':group'.
+    ///
+    /// Names or numeric ids; prefix either side with '!' to exclude it (e.g. 'alice:!staff').
+    #[arg(short = 'o', long = "owner", value_name = "user:group")]
+    owner: Option<owner::OwnerFilter>,
+
+    /// Only match items with this Finder tag. Repeat to require all of several.
+    #[arg(long = "tag", value_name = "name")]
+    tags: Vec<String>,
+
+    /// Only match files whose contents contain this text (case-insensitive).
+    ///
+    /// Relies on Spotlight having indexed the file's text; unindexed files never match.
+    #[arg(short = 'c', long = "content", value_name = "text")]
+    content: Option<String>,
+
+    /// Use this Spotlight query predicate instead of a pattern, e.g.
+    /// 'kMDItemContentType == "public.source-code"'.
+    ///
+    /// The predicate is passed to `mdfind` as is. Results are still limited to the search
+    /// path and go through every other filter, including ignore files and hidden handling.
+    /// There is no pattern then: every positional argument is a search path.
+    #[arg(long = "query", value_name = "predicate")]
+    query: Option<String>,
+
+    /// With `--content`, skip files that look binary (a NUL byte in the first 8 KiB), like
+    /// ripgrep does by default.
+    #[arg(long = "no-binary", requires = "content")]
+    no_binary: bool,
+
+    /// Limit results by file size: `+N` (at least), `-N` (at most) or `N` (exactly).
+    ///
+    /// Units: b, k, m, g, t (powers of 1000, as in fd, so 'k' is not 1024) and ki, mi, gi,
+    /// ti (powers of 1024), with an optional trailing b (kb, GiB). Repeat to form a range.
+    /// Only regular files can match.
+    #[arg(
+        short = 'S',
+        long = "size",
+        value_name = "size",
+        allow_hyphen_values = true
+    )]
+    sizes: Vec<criteria::SizeFilter>,
+
+    /// Only show entries modified within the given duration (e.g. 10min, 2h, 3d, 1w) or
+    /// since the given date.
+    ///
+    /// Dates look like '2024-01-31' or '2024-01-31 12:00:00' and are UTC (unlike fd, which
+    /// uses local time) unless they end in an offset like '+02:00'; '@1700000000' is a
+    /// Unix timestamp.
+    #[arg(
+        long = "changed-within",
+        visible_alias = "changed-after",
+        alias = "change-newer-than",
+        alias = "newer",
+        value_name = "date|duration",
+        value_parser = criteria::parse_time
+    )]
+    changed_within: Option<SystemTime>,
+
+    /// Only show entries modified before the given date, or longer ago than the given
+    /// duration. Takes the same values as `--changed-within`.
+    #[arg(
+        long = "changed-before",
+        alias = "change-older-than",
+        alias = "older",
+        value_name = "date|duration",
+        value_parser = criteria::parse_time
+    )]
+    changed_before: Option<SystemTime>,
+
+    /// Widen the modification-time window Spotlight is asked for by this much on each
+    /// side, e.g. `--time-slop 5s`.
+    ///
+    /// `--changed-within` and `--changed-before` narrow the Spotlight query by date, but
+    /// the index can lag behind recent writes. Every candidate's modification time is
+    /// checked exactly with lstat anyway, so a larger slop only costs more candidates,
+    /// while a smaller one risks missing files whose index entry is stale.
+    #[arg(
+        long = "time-slop",
+        value_name = "duration",
+        default_value = "1min",
+        value_parser = criteria::parse_duration
+    )]
+    time_slop: Duration,
+
+    /// Print a summary of how many candidates were matched or rejected (and why) to stderr.
+    #[arg(long = "stats")]
+    stats: bool,
+
+    /// Print each file once, even if Spotlight reports it twice, under two search paths or
+    /// by another name (a hard link, or different case on a case-insensitive volume).
+    ///
+    /// Results still stream; `sf` remembers a small identity for each distinct result.
+    /// `--stats` counts the repeats under `duplicate`.
+    #[arg(long = "unique")]
+    unique: bool,
+
+    /// Don't print warnings to stderr, such as when nothing was found in a search path
+    /// that Spotlight doesn't index, or the note that `--walk` is walking instead.
+    #[arg(long = "no-warnings")]
+    no_warnings: bool,
+
+    /// Don't print `sf`'s own messages to stderr: the `--exec-echo` lines and the summary
+    /// of how many commands ran and failed after `--exec`, `--exec-batch` or
+    /// `--exec-batch-stdin`. Errors are still printed.
+    #[arg(long = "no-messages", alias = "no-exec-summary")]
+    no_messages: bool,
+
+    /// Print absolute paths instead of paths relative to the current directory.
+    #[arg(short = 'a', long = "absolute-path")]
+    absolute_path: bool,
+
+    /// Print paths relative to the root of the git repository containing them, wherever
+    /// `sf` runs from. Results outside any repository are printed as usual.
+    ///
+    /// `--exec` commands run in the current directory, so they can't take these paths.
+    #[arg(long = "relative-to-repo", conflicts_with_all = ["absolute_path", "exec_any"])]
+    relative_to_repo: bool,
+
+    /// Stop after the first N results (and stop Spotlight's scan early).
+    #[arg(
+        long = "max-results",
+        value_name = "count",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    max_results: Option<u64>,
+
+    /// Buffer output and write it out after every N results instead of one at a time.
+    ///
+    /// With `--max-buffer-time`, whichever comes first; the rest is written at the end.
+    #[arg(
+        long = "flush-every",
+        value_name = "count",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    flush_every: Option<u64>,
+
+    /// Buffer output, but never hold a result back longer than this (e.g. 50ms).
+    ///
+    /// Results are grouped into fewer, larger writes during a burst, e.g. for a TUI
+    /// reading `sf`'s output, and still show up within this time when the search stalls.
+    #[arg(
+        long = "max-buffer-time",
+        value_name = "duration",
+        value_parser = criteria::parse_duration
+    )]
+    max_buffer_time: Option<Duration>,
+
+    /// Print only the number of results.
+    ///
+    /// With `--max-results N`, counting stops at N, so the output answers "are there at
+    /// least N?" without a full scan.
+    #[arg(long = "count", conflicts_with = "exec_batch_stdin")]
+    count: bool,
+
+    /// Print nothing; exit 0 if there is at least one result and 1 otherwise.
+    ///
+    /// Stops at the first result that passes every filter.
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        conflicts_with_all = ["count", "exec_any"]
+    )]
+    quiet: bool,
+
+    /// Exit with status 1 if nothing matched, like grep. Output is unchanged; without this
+    /// flag an empty result is not an error.
+    #[arg(long = "exit-code")]
+    exit_code: bool,
+
+    /// Append '/' to directory results, like fd.
+    #[arg(long = "trailing-slash")]
+    trailing_slash: bool,
+
+    /// Whether relative results start with './': auto, always (strip it) or never.
+    ///
+    /// `auto` strips it only when no path is given, so `sf x .` prints './a' but `sf x`
+    /// prints 'a'. `never` adds it to every path below the current directory. Bare
+    /// `--strip-cwd-prefix` means `always`, like fd.
+    #[arg(
+        long = "strip-cwd-prefix",
+        value_name = "when",
+        value_enum,
+        default_value_t,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always",
+        conflicts_with = "absolute_path"
+    )]
+    strip_cwd_prefix: output::StripCwdPrefix,
+
+    /// When to color results by type and extension: auto, always or never.
+    ///
+    /// Styles come from `LS_COLORS`, like `ls` and fd (with defaults when it's unset).
+    /// `auto` colors only when stdout is a terminal and `NO_COLOR` is unset.
+    #[arg(long = "color", value_name = "when", value_enum, default_value_t)]
+    color: output::ColorWhen,
+
+    /// Make each result a terminal hyperlink (OSC 8) to its `file://` URI: auto, always or
+    /// never.
+    ///
+    /// Bare `--hyperlink` means `auto`, which links only when stdout is a terminal and
+    /// `NO_COLOR` is unset, like fd.
+    #[arg(
+        long = "hyperlink",
+        value_name = "when",
+        value_enum,
+        default_value = "never",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "auto"
+    )]
+    hyperlink: output::ColorWhen,
+
+    /// Print each result through a template instead of as a bare path.
+    ///
+    /// Uses the `--exec` placeholders: '{}' (path), '{/}' (file name), '{//}' (parent
+    /// directory), '{.}' (path without extension), '{/.}' (file name without extension),
+    /// and sf's '{..}' (parent of '{//}'). They expand from the path as it would be
+    /// printed, so '{}' and '{//}' are absolute under '--absolute-path'. Write '{{' and
+    /// '}}' for literal braces. Output isn't colored.
+    #[arg(
+        long = "format",
+        value_name = "fmt",
+        value_parser = output::Format::parse,
+        conflicts_with_all = ["count", "quiet", "exec_any"]
+    )]
+    format: Option<output::Format>,
+
+    /// Print results sorted by path, name, size or modification time.
+    ///
+    /// Results are collected until the search ends before anything is printed or run,
+    /// so output no longer streams.
+    #[arg(
+        long = "sort",
+        value_name = "key",
+        value_enum,
+        conflicts_with_all = ["count", "quiet", "count_by", "max_results"]
+    )]
+    sort: Option<output::SortKey>,
+
+    /// Reverse the `--sort` order.
+    #[arg(long = "reverse", requires = "sort")]
+    reverse: bool,
+
+    /// Print how many results there are per extension or type instead of the results,
+    /// most frequent first.
+    #[arg(
+        long = "count-by",
+        value_name = "key",
+        value_enum,
+        conflicts_with_all = ["count", "quiet", "exec_any", "format", "json", "json_array"]
+    )]
+    count_by: Option<output::CountKey>,
+
+    /// How to print the `--count-by` tally: human ('key: count'), tsv ('key<TAB>count',
+    /// with tabs, line breaks and backslashes in keys escaped) or json (one array of
+    /// `{"key", "count"}` objects).
+    ///
+    /// With `--print0`, human and tsv records end in NUL instead of a newline.
+    #[arg(
+        long = "tally-format",
+        value_name = "format",
+        value_enum,
+        default_value_t,
+        requires = "count_by"
+    )]
+    tally_format: output::TallyFormat,
+
+    /// Print one JSON object per line for each result: `path` (as it would be printed),
+    /// `absolute` and `is_dir`. Paths that aren't UTF-8 are converted lossily and get
+    /// `"lossy": true`.
+    #[arg(
+        long = "json",
+        conflicts_with_all = ["count", "quiet", "exec_any", "format", "json_array"]
+    )]
+    json: bool,
+
+    /// Like `--json`, but print a single JSON array once the search has finished.
+    #[arg(
+        long = "json-array",
+        conflicts_with_all = ["count", "quiet", "exec_any", "format"]
+    )]
+    json_array: bool,
+
+    /// Print this string instead of '/' between path components, e.g. '\\'.
+    ///
+    /// Only changes printed results; `--exec` commands still get real paths.
+    #[arg(
+        long = "path-separator",
+        value_name = "separator",
+        value_parser = clap::builder::NonEmptyStringValueParser::new()
+    )]
+    path_separator: Option<String>,
+
+    /// Print NUL ('\\0') after each result instead of '\\n'.
+    #[arg(short = '0', long = "print0")]
+    print0: bool,
+
+    /// Print each result's permissions, size in bytes and modification time (UTC) before
+    /// its path, like `ls -l`.
+    #[arg(
+        short = 'l',
+        long = "list-details",
+        conflicts_with_all = [
+            "print0", "format", "json", "json_array", "count", "quiet", "count_by", "exec_any",
+        ]
+    )]
+    list_details: bool,
+
+    /// Run a command for each result.
+    ///
+    /// Placeholders: '{}' (path), '{/}' (file name), '{//}' (parent directory), '{.}'
+    /// (path without extension), '{/.}' (file name without extension). sf adds '{..}'
+    /// (the grandparent directory: the parent of '{//}'). Without any, the path is
+    /// appended. Paths are passed as they would be printed, so relative ones work from the
+    /// current directory, which the command inherits. Terminate the command with ';' to
+    /// pass more `sf` options after it.
+    #[arg(
+        short = 'x',
+        long = "exec",
+        value_name = "cmd",
+        num_args = 1..,
+        allow_hyphen_values = true,
+        value_terminator = ";",
+        conflicts_with_all = ["exec_batch", "exec_batch_stdin", "count"]
+    )]
+    exec: Option<Vec<String>>,
+
+    /// Filter results on this many threads, or with `--exec`, run up to this many commands
+    /// at once (default 1).
+    ///
+    /// With more than one, results are printed as they pass the filters, in no particular
+    /// order; add `--sort` for a stable one. With `--exec`, `--exec-batch` or
+    /// `--exec-batch-stdin`, filtering stays on one thread so commands see results in
+    /// Spotlight's order. Each command's output is buffered and printed whole when it
+    /// exits, in completion order unless `--exec-ordered` is given.
+    #[arg(
+        short = 'j',
+        long = "threads",
+        value_name = "num",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    threads: u32,
+
+    /// With `--threads`, print each command's output in result order instead of as soon as
+    /// it finishes.
+    #[arg(long = "exec-ordered", requires = "exec")]
+    exec_ordered: bool,
+
+    /// Print each path to stderr just before its `--exec` command runs, unless
+    /// `--no-messages` is given.
+    #[arg(long = "exec-echo", requires = "exec")]
+    exec_echo: bool,
+
+    /// Run each `--exec` command as one script with `$SHELL -c` (default /bin/sh), so pipes
+    /// and other shell syntax work. Placeholders expand to single quoted words.
+    #[arg(long = "exec-shell", requires = "exec")]
+    exec_shell: bool,
+
+    /// Rerun a failing `--exec` command up to this many more times.
+    #[arg(
+        long = "exec-retries",
+        value_name = "count",
+        default_value_t = 0,
+        requires = "exec",
+        conflicts_with_all = ["exec_batch", "exec_batch_stdin"]
+    )]
+    exec_retries: u32,
+
+    /// Wait this long before retrying a failed `--exec` command (e.g. 500ms, 2s), doubling
+    /// the wait after each retry.
+    #[arg(
+        long = "exec-retry-delay",
+        value_name = "duration",
+        value_parser = criteria::parse_duration,
+        requires = "exec"
+    )]
+    exec_retry_delay: Option<Duration>,
+
+    /// Run a command once with all results as arguments.
+    ///
+    /// The same placeholders as `--exec` are allowed in one argument, which is repeated
+    /// for each result; without any, the results are appended. The command is not run if
+    /// there are no results. Terminate the command with ';' to pass more `sf` options
+    /// after it.
+    #[arg(
+        short = 'X',
+        long = "exec-batch",
+        value_name = "cmd",
+        num_args = 1..,
+        allow_hyphen_values = true,
+        value_terminator = ";",
+        conflicts_with_all = ["exec_batch_stdin", "count"]
+    )]
+    exec_batch: Option<Vec<String>>,
+
+    /// Run a command once and write all results to its standard input.
+    ///
+    /// The command is not run if there are no results. Terminate the command with ';' to
+    /// pass more `sf` options after it.
+    #[arg(
+        long = "exec-batch-stdin",
+        value_name = "cmd",
+        num_args = 1..,
+        allow_hyphen_values = true,
+        value_terminator = ";"
+    )]
+    exec_batch_stdin: Option<Vec<String>>,
+
+    /// Separate results written to `--exec-batch-stdin` with NUL (default) or, with
+    /// `--exec-null=false`, newlines. Independent of `--print0`.
+    #[arg(
+        long = "exec-null",
+        value_name = "bool",
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = true,
+        default_missing_value = "true",
+        action = clap::ArgAction::Set,
+        requires = "exec_batch_stdin"
+    )]
+    exec_null: bool,
+
+    /// Print the commands `--exec`, `--exec-batch` or `--exec-batch-stdin` would run
+    /// (shell-quoted, one per line) instead of running them.
+    #[arg(long = "dry-run", requires = "exec_any")]
+    dry_run: bool,
+
+    /// Print a completion script for bash or zsh and exit.
+    #[arg(
+        long = "completions",
+        value_name = "shell",
+        value_enum,
+        hide = true,
+        exclusive = true
+    )]
+    completions: Option<completions::Shell>,
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn main() {
+    eprintln!("sf is macOS-only (requires Spotlight)");
+    process::exit(1);
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn main() {
+    if let Err(err) = run() {
+        // Match common Unix CLI behavior: don't print scary errors on broken pipes
+        // (e.g. `sf "*.rs" | head`).
+        if is_broken_pipe(&err) {
+            process::exit(0);
+        }
+
+        if is_exec_failed(&err) || err.is::<NoResults>() {
+            process::exit(1);
+        }
+
+        if is_mdfind_not_found(&err) {
+            eprintln!("sf requires macOS Spotlight");
+            process::exit(1);
+        }
+
+        eprintln!("{err:#}");
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let mut args = Args::parse();
+    patternless_query(&mut args);
+    if let Some(shell) = args.completions {
+        completions::generate(shell, &Args::command(), &mut io::stdout().lock())?;
+        return Ok(());
+    }
+
+    let threads = filter_threads(&args);
+    let warnings = warnings(&args);
+    let cwd = std::env::current_dir().context("failed to read current directory")?;
+    let base_directory = args
+        .base_directory
+        .as_deref()
+        .map(|dir| make_absolute_dir(&cwd, Some(dir)))
+        .transpose()?;
+    let path_args: Vec<Option<&Path>> = if args.paths.is_empty() {
+        vec![None]
+    } else {
+        args.paths.iter().map(|p| Some(p.as_path())).collect()
+    };
+    let bases = path_args
+        .iter()
+        .map(|path| make_absolute_dir(&cwd, *path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let query_opts = query::QueryOptions {
+        full_path: args.full_path,
+        match_stem: args.match_stem,
+        case: case_mode(&args),
+        syntax: pattern_syntax(&args),
+        ignore_diacritics: args.ignore_diacritics,
+        content_types: content_types(&args),
+        owner: args.owner,
+        tags: args.tags.clone(),
+        content: args.content.clone(),
+        raw_query: args.query.clone(),
+        not_patterns: args.not_patterns.clone(),
+        and_patterns: args.and_patterns.clone(),
+        changed_after: args.changed_within,
+        changed_before: args.changed_before,
+        time_slop: args.time_slop,
+        word: args.word,
+    };
+    let walk_plan = if args.walk {
+        Some(query::build_stdin_plan(
+            &bases,
+            args.pattern.as_deref(),
+            query_opts.clone(),
+        )?)
+    } else {
+        None
+    };
+    let query_plan = if args.from_stdin {
+        query::build_stdin_plan(&bases, args.pattern.as_deref(), query_opts)?
+    } else {
+        query::build_mdfind_plan(&bases, args.pattern.as_deref(), query_opts)?
+    };
+    let excludes = exclude_globs(&args, &cwd)?;
+    let mut filters = bases
+        .iter()
+        .map(|base| {
+            filter::Filter::new(
+                filter::FilterConfig {
+                    cwd: cwd.clone(),
+                    search_base: base.clone(),
+                    include_hidden: include_hidden(&args),
+                    ignores: ignore_sources(&args),
+                    parent_ignores: !args.no_ignore_parent,
+                    depth: depth_range(&args),
+                },
+                &args.ignore_files,
+            )
+            .and_then(|filter| filter.with_excludes(&excludes))
+            .map(|filter| filter.with_ignore_names(&args.ignore_names))
+            .map(|filter| filter.with_prune(args.prune))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // Criteria are shared by every search path, so the first one decides.
+    let extensions_case_sensitive = !args.extensions.is_empty()
+        && criteria::extensions_case_sensitive(case_mode(&args), || {
+            volume::is_case_sensitive(&bases[0])
+        });
+    let criteria = criteria::Criteria::new(criteria::CriteriaConfig {
+        file_types: args.file_types,
+        extensions_case_sensitive,
+        extensions: args.extensions,
+        sizes: args.sizes,
+        changed_after: args.changed_within,
+        changed_before: args.changed_before,
+        owner: args.owner,
+        skip_binary: args.no_binary,
+    });
+    let path_mode = if args.absolute_path {
+        output::PathMode::Absolute
+    } else if args.relative_to_repo {
+        output::PathMode::RepoRelative
+    } else {
+        output::PathMode::Relative
+    };
+    let out_styles: Vec<output::OutputStyle> = bases
+        .iter()
+        .zip(&path_args)
+        .map(|(base, path)| {
+            output::OutputStyle::new(cwd.clone(), base.clone(), *path, path_mode)
+                .with_trailing_slash(args.trailing_slash)
+                .with_strip_cwd_prefix(args.strip_cwd_prefix)
+                .with_base_directory(base_directory.clone())
+        })
+        .collect();
+    let mut roots: Vec<mdfind::Root> = filters
+        .iter_mut()
+        .zip(&out_styles)
+        .map(|(filter, out_style)| mdfind::Root { filter, out_style })
+        .collect();
+    let delimiter = if args.print0 {
+        output::Delimiter::Nul
+    } else {
+        output::Delimiter::Newline
+    };
+
+    let mut out: Box<dyn Write> = if let Some(max_wait) = args.max_buffer_time {
+        Box::new(output::TimedBuffer::new(io::stdout(), max_wait))
+    } else if args.flush_every.is_some() {
+        Box::new(io::BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(io::stdout().lock())
+    };
+    let mut sink: Box<dyn output::Sink> = if args.count || args.quiet {
+        Box::new(output::Discard)
+    } else if let Some(key) = args.count_by {
+        Box::new(output::CountBy::new(&mut out, key, delimiter).with_format(args.tally_format))
+    } else if args.json || args.json_array {
+        Box::new(output::JsonWriter::new(&mut out, args.json_array))
+    } else if let Some(argv) = &args.exec {
+        Box::new(
+            exec::PerResult::new(
+                exec_template(argv, args.exec_shell)?,
+                launcher(args.dry_run),
+            )
+            .with_retries(
+                args.exec_retries,
+                args.exec_retry_delay.unwrap_or(Duration::ZERO),
+            )
+            .with_jobs(args.threads as usize, args.exec_ordered)
+            .with_echo(args.exec_echo && !args.no_messages)
+            .with_summary(!args.no_messages),
+        )
+    } else if let Some(argv) = &args.exec_batch {
+        Box::new(
+            exec::Batch::new(
+                exec::CommandTemplate::new_batch(argv)?,
+                launcher(args.dry_run),
+            )
+            .with_summary(!args.no_messages),
+        )
+    } else if let Some(argv) = args.exec_batch_stdin {
+        let stdin_delimiter = if args.exec_null {
+            output::Delimiter::Nul
+        } else {
+            output::Delimiter::Newline
+        };
+        Box::new(
+            exec::BatchStdin::new(argv, stdin_delimiter, launcher(args.dry_run))
+                .with_summary(!args.no_messages),
+        )
+    } else {
+        let is_terminal = io::stdout().is_terminal();
+        let colors = args
+            .color
+            .enabled(is_terminal)
+            .then(ls_colors::LsColors::from_env);
+        Box::new(
+            output::PathWriter::new(&mut out, delimiter)
+                .with_colors(colors)
+                .with_path_separator(args.path_separator.map(Into::into))
+                .with_format(args.format)
+                .with_details(args.list_details)
+                .with_hyperlinks(args.hyperlink.enabled(is_terminal)),
+        )
+    };
+    if let Some(every) = args.flush_every {
+        sink = Box::new(output::Coalesced::new(sink, every));
+    }
+    if let Some(key) = args.sort {
+        sink = Box::new(output::Sorted::new(sink, key, args.reverse));
+    }
+    let opts = mdfind::RunOptions::default()
+        .with_limit(if args.quiet {
+            Some(1)
+        } else {
+            args.max_results
+        })
+        .with_threads(threads)
+        .with_unique(args.unique)
+        .with_warnings(warnings);
+    let stats = if args.from_stdin {
+        mdfind::run_stdin(
+            &mut io::stdin().lock(),
+            &query_plan,
+            &mut roots,
+            &criteria,
+            opts,
+            &mut *sink,
+        )?
+    } else {
+        mdfind::run(
+            &query_plan,
+            walk_plan.as_ref(),
+            &mut roots,
+            &criteria,
+            opts,
+            &mut *sink,
+        )?
+    };
+    drop(sink);
+
+    if args.count {
+        writeln!(out, "{}", stats.matched)?;
+    }
+    out.flush()?;
+
+    if args.stats {
+        stats
+            .write_report(&mut io::stderr())
+            .context("failed to write stats")?;
+    }
+    // Nothing at all might just mean Spotlight isn't looking there. Only asked when it
+    // matters, since `mdutil` is another process per search path.
+    if stats.candidates == 0 && !args.from_stdin && warnings {
+        for base in bases.iter().filter(|b| mdfind::indexing_disabled(b)) {
+            eprintln!(
+                "warning: Spotlight indexing appears disabled for {}; results may be incomplete.",
+                base.display()
+            );
+        }
+    }
+    found_anything(args.quiet || args.exit_code, &stats)
+}
+
+/// `NoResults` if nothing matched and `required` (`--quiet`, `--exit-code`) makes that a
+/// failure.
+fn found_anything(required: bool, stats: &stats::Stats) -> Result<()> {
+    if required && stats.matched == 0 {
+        return Err(anyhow::Error::new(NoResults));
+    }
+    Ok(())
+}
+
+/// `--quiet` or `--exit-code` found nothing: exit 1 without a message.
+#[derive(Debug)]
+struct NoResults;
+
+impl std::fmt::Display for NoResults {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no results")
+    }
+}
+
+impl std::error::Error for NoResults {}
+
+fn exec_template(argv: &[String], shell: bool) -> Result<exec::CommandTemplate> {
+    if !shell {
+        return exec::CommandTemplate::new(argv);
+    }
+    let shell = std::env::var_os("SHELL")
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "/bin/sh".into());
+    exec::CommandTemplate::new_shell(argv, shell)
+}
+
+fn case_mode(args: &Args) -> query::CaseMode {
+    if args.case_sensitive {
+        query::CaseMode::Sensitive
+    } else if args.ignore_case {
+        query::CaseMode::Insensitive
+    } else {
+        query::CaseMode::Smart
+    }
+}
+
+fn pattern_syntax(args: &Args) -> query::PatternSyntax {
+    if args.glob {
+        query::PatternSyntax::Glob
+    } else if args.fixed_strings {
+        query::PatternSyntax::Fixed
+    } else {
+        query::PatternSyntax::Auto
+    }
+}
+
+fn depth_range(args: &Args) -> filter::DepthRange {
+    match args.exact_depth {
+        Some(depth) => filter::DepthRange {
+            min: depth,
+            max: Some(depth),
+        },
+        None => filter::DepthRange {
+            min: args.min_depth.unwrap_or(0),
+            max: args.max_depth,
+        },
+    }
+}
+
+fn include_hidden(args: &Args) -> bool {
+    args.hidden || args.unrestricted > 0
+}
+
+fn ignore_sources(args: &Args) -> filter::IgnoreSources {
+    if args.no_ignore || args.unrestricted > 0 {
+        return filter::IgnoreSources::NONE;
+    }
+    filter::IgnoreSources {
+        git_ignore: !args.no_ignore_vcs,
+        ..filter::IgnoreSources::ALL
+    }
+}
+
+fn launcher(dry_run: bool) -> exec::Launcher {
+    if dry_run {
+        exec::Launcher::DryRun(Box::new(io::stdout()))
+    } else {
+        exec::Launcher::Spawn
+    }
+}
+
+/// `-j` runs `--exec` commands in parallel, but filters on one thread then, so commands
+/// (and `--exec-ordered` output) keep the order results arrive in.
+fn filter_threads(args: &Args) -> usize {
+    if args.exec.is_some() || args.exec_batch.is_some() || args.exec_batch_stdin.is_some() {
+        1
+    } else {
+        args.threads as usize
+    }
+}
+
+/// Whether to print warnings and notes to stderr: `--quiet` and `--no-warnings` both turn
+/// them off.
+fn warnings(args: &Args) -> bool {
+    !args.quiet && !args.no_warnings
+}
+
+/// `--exclude` globs followed by those read from each `--exclude-from` file (relative to
+/// `cwd`).
+fn exclude_globs(args: &Args, cwd: &Path) -> Result<Vec<String>> {
+    let mut globs = args.excludes.clone();
+    for file in &args.exclude_files {
+        let path = cwd.join(file);
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read exclude file {}", path.display()))?;
+        globs.extend(
+            text.lines()
+                .map(|line| line.trim_end_matches('\r'))
+                .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+                .map(str::to_owned),
+        );
+    }
+    Ok(globs)
+}
+
+/// `--query` replaces the pattern, so what clap took for one is the first search path.
+fn patternless_query(args: &mut Args) {
+    if args.query.is_some()
+        && let Some(pattern) = args.pattern.take()
+    {
+        args.paths.insert(0, pattern.into());
+    }
+}
+
+/// `--content-type` values followed by the UTIs of every `--kind`.
+fn content_types(args: &Args) -> Vec<String> {
+    let kinds = args.kinds.iter().flat_map(|k| k.utis.iter());
+    args.content_types
+        .iter()
+        .map(String::as_str)
+        .chain(kinds.copied())
+        .map(String::from)
+        .collect()
+}
+
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|ioe| ioe.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
+
+fn is_exec_failed(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(|cause| cause.downcast_ref::<exec::ExecFailed>().is_some())
+}
+
+fn is_mdfind_not_found(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(|cause| cause.downcast_ref::<mdfind::MdfindNotFound>().is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(argv: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(std::iter::once("sf").chain(argv.iter().copied()))
+    }
+
+    #[test]
+    fn case_flags_override_smart_case() {
+        assert_eq!(case_mode(&parse(&["foo"]).unwrap()), query::CaseMode::Smart);
+        assert_eq!(
+            case_mode(&parse(&["-s", "foo"]).unwrap()),
+            query::CaseMode::Sensitive
+        );
+        assert_eq!(
+            case_mode(&parse(&["--ignore-case", "Foo"]).unwrap()),
+            query::CaseMode::Insensitive
+        );
+    }
+
+    #[test]
+    fn exec_null_defaults_to_nul_and_accepts_false() {
+        let args = parse(&["--exec-batch-stdin", "xargs", "-0", ";", "foo"]).unwrap();
+        assert_eq!(
+            args.exec_batch_stdin,
+            Some(vec!["xargs".to_string(), "-0".to_string()])
+        );
+        assert_eq!(args.pattern.as_deref(), Some("foo"));
+        assert!(args.exec_null);
+
+        let args = parse(&["--exec-null=false", "--exec-batch-stdin", "wc", "-l"]).unwrap();
+        assert!(!args.exec_null);
+    }
+
+    #[test]
+    fn exec_null_requires_exec_batch_stdin() {
+        let err = parse(&["--exec-null", "foo"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn case_flags_are_mutually_exclusive() {
+        let err = parse(&["-s", "-i", "foo"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn max_results_must_be_positive() {
+        assert_eq!(
+            parse(&["--max-results", "5", "--count"])
+                .unwrap()
+                .max_results,
+            Some(5)
+        );
+        let err = parse(&["--max-results", "0"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn exec_takes_the_rest_of_the_command_line_until_semicolon() {
+        let args = parse(&["-x", "wc", "-l", "{}", ";", "-e", "rs", "foo"]).unwrap();
+        assert_eq!(
+            args.exec,
+            Some(vec!["wc".to_string(), "-l".to_string(), "{}".to_string()])
+        );
+        assert_eq!(args.extensions, ["rs"]);
+        assert_eq!(args.pattern.as_deref(), Some("foo"));
+
+        let err = parse(&["-x", "echo", ";", "--exec-batch-stdin", "cat"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn kinds_expand_after_explicit_content_types() {
+        let args = parse(&["--kind", "image", "--content-type", "com.adobe.pdf"]).unwrap();
+        assert_eq!(content_types(&args), ["com.adobe.pdf", "public.image"]);
+
+        let err = parse(&["--kind", "nope"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn exec_variants_are_mutually_exclusive() {
+        let err = parse(&["-x", "echo", ";", "-X", "echo"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        let err = parse(&["-X", "echo", ";", "--exec-batch-stdin", "cat"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn dry_run_requires_an_exec_variant() {
+        let err = parse(&["--dry-run", "foo"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+        for argv in [
+            ["--dry-run", "-x", "echo"],
+            ["--dry-run", "-X", "echo"],
+            ["--dry-run", "--exec-batch-stdin", "cat"],
+        ] {
+            assert!(parse(&argv).unwrap().dry_run);
+        }
+    }
+
+    #[test]
+    fn no_exec_summary_is_an_alias_for_no_messages() {
+        let args = parse(&["--no-exec-summary", "-x", "echo", ";", "foo"]).unwrap();
+        assert!(args.no_messages);
+    }
+
+    #[test]
+    fn threads_only_filter_in_parallel_without_exec() {
+        assert_eq!(filter_threads(&parse(&["-j", "4", "foo"]).unwrap()), 4);
+        for argv in [
+            &["-j", "4", "-x", "echo", ";", "--exec-ordered", "foo"][..],
+            &["-j", "4", "-X", "echo", ";", "foo"],
+        ] {
+            assert_eq!(filter_threads(&parse(argv).unwrap()), 1, "{argv:?}");
+        }
+    }
+
+    #[test]
+    fn quiet_and_no_warnings_silence_warnings() {
+        assert!(warnings(&parse(&["foo"]).unwrap()));
+        for argv in [&["-q", "foo"][..], &["--no-warnings", "foo"]] {
+            assert!(!warnings(&parse(argv).unwrap()), "{argv:?}");
+        }
+    }
+
+    #[test]
+    fn exec_retries_only_apply_to_per_result_exec() {
+        let args = parse(&[
+            "--exec-retries",
+            "2",
+            "--exec-retry-delay",
+            "1s",
+            "-x",
+            "curl",
+        ])
+        .unwrap();
+        assert_eq!(args.exec_retries, 2);
+        assert_eq!(args.exec_retry_delay, Some(Duration::from_secs(1)));
+
+        let err = parse(&["--exec-retries", "2", "foo"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+        let err = parse(&["--exec-retries", "2", "-X", "curl"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn exclude_from_adds_each_glob_line_after_the_inline_ones() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            tmp.path().join("excludes.txt"),
+            "# build output\r\n/target/\r\n\n  \n*.log\n",
+        )
+        .unwrap();
+        let args = parse(&["-E", "*.tmp", "--exclude-from", "excludes.txt", "x"]).unwrap();
+        assert_eq!(
+            exclude_globs(&args, tmp.path()).unwrap(),
+            ["*.tmp", "/target/", "*.log"]
+        );
+
+        let args = parse(&["--exclude-from", "missing.txt"]).unwrap();
+        let err = exclude_globs(&args, tmp.path()).unwrap_err();
+        assert!(
+            err.to_string().starts_with("failed to read exclude file "),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn exit_code_fails_only_when_nothing_matched() {
+        let mut none = stats::Stats::default();
+        none.record(Err(stats::Rejection::Hidden));
+        let mut some = stats::Stats::default();
+        some.record(Ok(()));
+
+        assert!(found_anything(false, &none).is_ok());
+        assert!(found_anything(true, &some).is_ok());
+        assert!(found_anything(true, &none).unwrap_err().is::<NoResults>());
+        assert!(parse(&["--exit-code", "x"]).unwrap().exit_code);
+    }
+
+    #[test]
+    fn query_takes_every_positional_as_a_search_path() {
+        let mut args = parse(&["--query", "kMDItemFSSize > 0", "src", "tests"]).unwrap();
+        patternless_query(&mut args);
+        assert_eq!(args.pattern, None);
+        assert_eq!(args.paths, [PathBuf::from("src"), PathBuf::from("tests")]);
+
+        let mut args = parse(&["src"]).unwrap();
+        patternless_query(&mut args);
+        assert_eq!(args.pattern.as_deref(), Some("src"));
+    }
+
+    #[test]
+    fn from_stdin_rejects_spotlight_only_filters() {
+        assert!(
+            parse(&["--from-stdin", "-e", "rs", "foo"])
+                .unwrap()
+                .from_stdin
+        );
+        // The owner is checked against each candidate's metadata, too.
+        assert!(parse(&["--from-stdin", "-o", "0"]).unwrap().from_stdin);
+        for argv in [
+            ["--from-stdin", "--kind", "image"],
+            ["--from-stdin", "--content-type", "public.image"],
+            ["--from-stdin", "--tag", "Red"],
+            ["--from-stdin", "--content", "x"],
+            ["--from-stdin", "--query", "kMDItemFSSize > 0"],
+            ["--from-stdin", "--walk", "x"],
+            ["--walk", "--kind", "image"],
+        ] {
+            let err = parse(&argv).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn short_flags_cluster_like_fd() {
+        for argv in [&["-HI", "foo"][..], &["-IH", "foo"], &["-H", "-I", "foo"]] {
+            let args = parse(argv).unwrap();
+            assert!(include_hidden(&args), "{argv:?}");
+            assert_eq!(
+                ignore_sources(&args),
+                filter::IgnoreSources::NONE,
+                "{argv:?}"
+            );
+            assert_eq!(args.pattern.as_deref(), Some("foo"), "{argv:?}");
+        }
+        assert_eq!(parse(&["-uu", "foo"]).unwrap().unrestricted, 2);
+        assert_eq!(
+            parse(&["-c", "TODO", "foo"]).unwrap().content.as_deref(),
+            Some("TODO")
+        );
+
+        // A value-taking flag may end a cluster, with its value attached or separate.
+        for argv in [&["-Htf", "foo"][..], &["-Ht", "f", "foo"], &["-aHtf"]] {
+            let args = parse(argv).unwrap();
+            assert!(args.hidden, "{argv:?}");
+            assert_eq!(args.file_types, [criteria::FileType::File], "{argv:?}");
+        }
+        let args = parse(&["-Hd2", "-Ie", "rs", "foo"]).unwrap();
+        assert_eq!(
+            (args.max_depth, args.extensions),
+            (Some(2), vec!["rs".into()])
+        );
+        let args = parse(&["-HIx", "echo", ";", "foo"]).unwrap();
+        assert_eq!(args.exec, Some(vec!["echo".into()]));
+        assert_eq!(args.pattern.as_deref(), Some("foo"));
+
+        // Anything after a value-taking flag is its value, so these fail loudly instead of
+        // silently setting the trailing flags.
+        for argv in [&["-tfH", "foo"][..], &["-d2H", "foo"]] {
+            let err = parse(argv).unwrap_err();
+            assert!(
+                matches!(
+                    err.kind(),
+                    clap::error::ErrorKind::InvalidValue | clap::error::ErrorKind::ValueValidation
+                ),
+                "{argv:?}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn bare_strip_cwd_prefix_means_always() {
+        let when = |argv: &[&str]| parse(argv).unwrap().strip_cwd_prefix;
+        assert_eq!(when(&["x"]), output::StripCwdPrefix::Auto);
+        assert_eq!(
+            when(&["--strip-cwd-prefix", "x"]),
+            output::StripCwdPrefix::Always
+        );
+        assert_eq!(
+            when(&["--strip-cwd-prefix=never", "x"]),
+            output::StripCwdPrefix::Never
+        );
+        // The value must be attached, so a following word stays the pattern.
+        let args = parse(&["--strip-cwd-prefix", "never"]).unwrap();
+        assert_eq!(args.pattern.as_deref(), Some("never"));
+        assert!(parse(&["-a", "--strip-cwd-prefix", "x"]).is_err());
+    }
+
+    #[test]
+    fn completions_cover_the_real_flags_and_stand_alone() {
+        assert_eq!(
+            parse(&["--completions", "zsh"]).unwrap().completions,
+            Some(completions::Shell::Zsh)
+        );
+        assert!(parse(&["--completions", "zsh", "foo"]).is_err());
+
+        let mut buf = Vec::new();
+        completions::generate(completions::Shell::Bash, &Args::command(), &mut buf).unwrap();
+        let script = String::from_utf8(buf).unwrap();
+        for flag in ["--hidden", "--changed-before", "--strip-cwd-prefix", "-X"] {
+            assert!(script.contains(flag), "{flag}");
+        }
+        assert!(script.contains("compgen -W \"auto always never\""));
+        assert!(!script.contains("--completions"));
+    }
+
+    #[test]
+    fn hyperlinks_are_off_unless_asked_for() {
+        let when = |argv: &[&str]| parse(argv).unwrap().hyperlink;
+        assert_eq!(when(&["x"]), output::ColorWhen::Never);
+        assert_eq!(when(&["--hyperlink", "x"]), output::ColorWhen::Auto);
+        assert_eq!(
+            when(&["--hyperlink=always", "x"]),
+            output::ColorWhen::Always
+        );
+    }
+
+    #[test]
+    fn format_is_validated_while_parsing() {
+        assert!(
+            parse(&["--format", "{/} -> {}", "foo"])
+                .unwrap()
+                .format
+                .is_some()
+        );
+        let err = parse(&["--format", "{name}", "foo"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+        assert!(err.to_string().contains("`{name}`"));
+        // Placeholders follow the path mode, so this combination is meaningful.
+        assert!(parse(&["-a", "--format", "{//}", "foo"]).is_ok());
+    }
+
+    #[test]
+    fn depth_flags_validate_and_exact_depth_sets_both_bounds() {
+        let range = |argv: &[&str]| depth_range(&parse(argv).unwrap());
+        assert_eq!(range(&["x"]), filter::DepthRange::ANY);
+        assert_eq!(
+            range(&["-d", "0"]),
+            filter::DepthRange {
+                min: 0,
+                max: Some(0)
+            }
+        );
+        assert_eq!(
+            range(&["--exact-depth", "2"]),
+            filter::DepthRange {
+                min: 2,
+                max: Some(2)
+            }
+        );
+
+        for argv in ["--max-depth=-1", "--min-depth=x", "--exact-depth=-3"] {
+            let err = parse(&[argv]).unwrap_err();
+            assert_eq!(
+                err.kind(),
+                clap::error::ErrorKind::ValueValidation,
+                "{argv:?}"
+            );
+        }
+        for argv in [
+            ["--exact-depth", "2", "--min-depth", "1"],
+            ["--exact-depth", "2", "--max-depth", "3"],
+        ] {
+            let err = parse(&argv).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn unrestricted_implies_hidden_and_no_ignore() {
+        let args = parse(&["foo"]).unwrap();
+        assert!(!include_hidden(&args));
+        assert_eq!(ignore_sources(&args), filter::IgnoreSources::ALL);
+
+        for argv in [
+            &["-u", "foo"][..],
+            &["-uu", "foo"],
+            &["-u", "-H", "-I", "foo"],
+        ] {
+            let args = parse(argv).unwrap();
+            assert!(include_hidden(&args), "{argv:?}");
+            assert_eq!(
+                ignore_sources(&args),
+                filter::IgnoreSources::NONE,
+                "{argv:?}"
+            );
+        }
+        assert_eq!(parse(&["-uu"]).unwrap().unrestricted, 2);
+
+        // The explicit flags keep working on their own.
+        let args = parse(&["-H", "foo"]).unwrap();
+        assert!(include_hidden(&args));
+        assert_eq!(ignore_sources(&args), filter::IgnoreSources::ALL);
+        let args = parse(&["--no-ignore", "foo"]).unwrap();
+        assert!(!include_hidden(&args));
+        assert_eq!(ignore_sources(&args), filter::IgnoreSources::NONE);
+    }
+
+    #[test]
+    fn quiet_conflicts_with_other_outputs() {
+        assert!(parse(&["-q", "--stats", "foo"]).unwrap().quiet);
+        for argv in [["-q", "--count"], ["-q", "-X"], ["-q", "-x"]] {
+            let err = parse(&[argv[0], argv[1], "echo"]).unwrap_err();
+            assert_eq!(
+                err.kind(),
+                clap::error::ErrorKind::ArgumentConflict,
+                "{argv:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn glob_and_fixed_strings_are_mutually_exclusive() {
+        assert_eq!(
+            pattern_syntax(&parse(&["a*"]).unwrap()),
+            query::PatternSyntax::Auto
+        );
+        assert_eq!(
+            pattern_syntax(&parse(&["-g", "config"]).unwrap()),
+            query::PatternSyntax::Glob
+        );
+        assert_eq!(
+            pattern_syntax(&parse(&["-F", "a*"]).unwrap()),
+            query::PatternSyntax::Fixed
+        );
+        let err = parse(&["-g", "-F", "config"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn version_names_the_fd_release_it_follows() {
+        let err = parse(&["--version"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::DisplayVersion);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "sf {} (fd {FD_PARITY_VERSION} semantics)\n",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn search_base_is_canonical_but_output_keeps_the_typed_path() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let real = tmp.path().join("real");
+        std::fs::create_dir_all(real.join("sub")).unwrap();
+        std::os::unix::fs::symlink(&real, tmp.path().join("link")).unwrap();
+        let cwd = std::fs::canonicalize(tmp.path()).unwrap();
+        let real = cwd.join("real");
+
+        let base = make_absolute_dir(&cwd, Some(Path::new("link/sub"))).unwrap();
+        assert_eq!(base, real.join("sub"));
+        let style = output::OutputStyle::new(
+            cwd.clone(),
+            base.clone(),
+            Some(Path::new("link/sub")),
+            output::PathMode::Relative,
+        );
+        assert_eq!(
+            style.render(&real.join("sub/a.txt")),
+            Path::new("link/sub/a.txt")
+        );
+
+        let link = cwd.join("link");
+        let base = make_absolute_dir(&cwd, Some(&link)).unwrap();
+        assert_eq!(base, real);
+        let style = output::OutputStyle::new(cwd, base, Some(&link), output::PathMode::Relative);
+        assert_eq!(style.render(&real.join("a.txt")), link.join("a.txt"));
+    }
+}
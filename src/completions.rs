@@ -0,0 +1,231 @@
+//! Shell completion scripts for `sf --completions <shell>`, generated from the `clap`
+//! command definition so they never drift from the real flags.
+
+use std::io::{self, Write};
+
+use clap::{Arg, Command};
+
+/// A shell `--completions` can write a script for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, clap::ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+}
+
+/// One visible option, as the generators need it.
+struct Opt {
+    shorts: Vec<char>,
+    longs: Vec<String>,
+    help: String,
+    takes_value: bool,
+    /// The value, if any, has to be attached: `--strip-cwd-prefix=never`.
+    attached: bool,
+    repeatable: bool,
+    /// Possible values to offer, if the option has a fixed set.
+    values: Vec<String>,
+}
+
+impl Opt {
+    fn from_arg(arg: &Arg) -> Option<Self> {
+        if arg.is_positional() || arg.is_hide_set() {
+            return None;
+        }
+        let mut shorts: Vec<char> = arg.get_short().into_iter().collect();
+        shorts.extend(arg.get_visible_short_aliases().unwrap_or_default());
+        let mut longs: Vec<String> = arg.get_long().map(str::to_owned).into_iter().collect();
+        longs.extend(
+            arg.get_visible_aliases()
+                .unwrap_or_default()
+                .into_iter()
+                .map(str::to_owned),
+        );
+        let takes_value = arg.get_action().takes_values();
+        let values = if takes_value {
+            arg.get_possible_values()
+                .iter()
+                .filter(|v| !v.is_hide_set())
+                .map(|v| v.get_name().to_owned())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Some(Opt {
+            shorts,
+            longs,
+            // The first line is the summary; the rest is `--help` detail.
+            help: arg
+                .get_help()
+                .map(|h| h.to_string().lines().next().unwrap_or_default().to_owned())
+                .unwrap_or_default(),
+            takes_value,
+            attached: arg.is_require_equals_set(),
+            repeatable: matches!(
+                arg.get_action(),
+                clap::ArgAction::Append | clap::ArgAction::Count
+            ),
+            values,
+        })
+    }
+
+    /// Every spelling, e.g. `-t` and `--type`.
+    fn names(&self) -> impl Iterator<Item = String> + '_ {
+        self.shorts
+            .iter()
+            .map(|s| format!("-{s}"))
+            .chain(self.longs.iter().map(|l| format!("--{l}")))
+    }
+}
+
+/// Write the completion script for `shell` to `out`.
+pub fn generate(shell: Shell, cmd: &Command, out: &mut dyn Write) -> io::Result<()> {
+    let name = cmd.get_name();
+    let opts: Vec<Opt> = cmd.get_arguments().filter_map(Opt::from_arg).collect();
+    match shell {
+        Shell::Bash => bash(name, &opts, out),
+        Shell::Zsh => zsh(name, &opts, out),
+    }
+}
+
+fn bash(name: &str, opts: &[Opt], out: &mut dyn Write) -> io::Result<()> {
+    let func = format!("_{}", name.replace('-', "_"));
+    let mut all: Vec<String> = opts.iter().flat_map(Opt::names).collect();
+    for opt in opts.iter().filter(|o| o.attached) {
+        for long in &opt.longs {
+            all.extend(opt.values.iter().map(|v| format!("--{long}={v}")));
+        }
+    }
+    writeln!(out, "{func}() {{")?;
+    writeln!(out, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"")?;
+    writeln!(out, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"")?;
+    writeln!(out, "    case \"$prev\" in")?;
+    for opt in opts.iter().filter(|o| !o.values.is_empty() && !o.attached) {
+        let names: Vec<String> = opt.names().collect();
+        writeln!(
+            out,
+            "        {})\n            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n            return\n            ;;",
+            names.join("|"),
+            opt.values.join(" ")
+        )?;
+    }
+    writeln!(out, "    esac")?;
+    writeln!(out, "    if [[ \"$cur\" == -* ]]; then")?;
+    writeln!(
+        out,
+        "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+        all.join(" ")
+    )?;
+    writeln!(out, "        return")?;
+    writeln!(out, "    fi")?;
+    writeln!(out, "    COMPREPLY=($(compgen -f -- \"$cur\"))")?;
+    writeln!(out, "}}")?;
+    writeln!(out, "complete -F {func} -o bashdefault -o default {name}")
+}
+
+fn zsh(name: &str, opts: &[Opt], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "#compdef {name}")?;
+    writeln!(out)?;
+    writeln!(out, "_arguments -s -S \\")?;
+    for opt in opts {
+        let help = zsh_quote(&opt.help.replace('[', "\\[").replace(']', "\\]"));
+        let action = if !opt.values.is_empty() {
+            format!(":value:({})", opt.values.join(" "))
+        } else if opt.takes_value {
+            ":value:_files".to_owned()
+        } else {
+            String::new()
+        };
+        let names: Vec<String> = opt.names().collect();
+        // Alternative spellings exclude each other unless the option repeats.
+        let exclusive = if opt.repeatable || names.len() == 1 {
+            String::new()
+        } else {
+            format!("({})", names.join(" "))
+        };
+        for n in &names {
+            let repeat = if opt.repeatable { "*" } else { "" };
+            let suffix = match (opt.takes_value, n.starts_with("--")) {
+                (false, _) => "",
+                (true, true) if opt.attached => "=-",
+                (true, true) => "=",
+                (true, false) => "+",
+            };
+            writeln!(out, "  '{exclusive}{repeat}{n}{suffix}[{help}]{action}' \\")?;
+        }
+    }
+    writeln!(out, "  '*::path:_files'")
+}
+
+/// The body of a single-quoted zsh string.
+fn zsh_quote(s: &str) -> String {
+    s.replace('\'', "'\\''")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> Command {
+        Command::new("sf")
+            .arg(Arg::new("pattern"))
+            .arg(
+                Arg::new("hidden")
+                    .short('H')
+                    .long("hidden")
+                    .help("Include hidden files [like fd]")
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("color")
+                    .long("color")
+                    .help("When to color, it's up to you\nMore detail.")
+                    .value_parser(["auto", "always", "never"]),
+            )
+            .arg(
+                Arg::new("exclude")
+                    .short('E')
+                    .long("exclude")
+                    .action(clap::ArgAction::Append),
+            )
+            .arg(
+                Arg::new("strip")
+                    .long("strip")
+                    .num_args(0..=1)
+                    .require_equals(true)
+                    .value_parser(["always", "never"]),
+            )
+            .arg(Arg::new("secret").long("secret").hide(true))
+    }
+
+    fn script(shell: Shell) -> String {
+        let mut buf = Vec::new();
+        generate(shell, &command(), &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn scripts_offer_visible_flags_and_their_values() {
+        let bash = script(Shell::Bash);
+        assert!(bash.contains(
+            "compgen -W \"-H --hidden --color -E --exclude --strip --strip=always --strip=never\""
+        ));
+        // Attached values are only offered with their `=`.
+        assert!(!bash.contains("--strip)"));
+        assert!(bash.contains(
+            "        --color)\n            COMPREPLY=($(compgen -W \"auto always never\""
+        ));
+        assert!(bash.ends_with("complete -F _sf -o bashdefault -o default sf\n"));
+
+        let zsh = script(Shell::Zsh);
+        assert!(zsh.starts_with("#compdef sf\n"));
+        assert!(zsh.contains("'(-H --hidden)-H[Include hidden files \\[like fd\\]]' \\\n"));
+        assert!(zsh.contains(
+            "'--color=[When to color, it'\\''s up to you]:value:(auto always never)' \\\n"
+        ));
+        assert!(zsh.contains("'*-E+[]:value:_files' \\\n"));
+        assert!(zsh.contains("'--strip=-[]:value:(always never)' \\\n"));
+
+        for shell in [Shell::Bash, Shell::Zsh] {
+            assert!(!script(shell).contains("secret"), "{shell:?}");
+        }
+    }
+}
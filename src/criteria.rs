@@ -0,0 +1,959 @@
+use std::{
+    borrow::Cow,
+    fs::{self, Metadata},
+    io::Read,
+    path::Path,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use crate::{owner::OwnerFilter, query::CaseMode, stats::Rejection};
+
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+/// fd-style `--type` values.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, clap::ValueEnum)]
+pub enum FileType {
+    #[value(name = "f", alias = "file")]
+    File,
+    #[value(name = "d", alias = "dir", alias = "directory")]
+    Directory,
+    #[value(name = "l", alias = "symlink")]
+    Symlink,
+    #[value(name = "x", alias = "executable")]
+    Executable,
+    #[value(name = "e", alias = "empty")]
+    Empty,
+    #[value(name = "s", alias = "socket")]
+    Socket,
+    #[value(name = "p", alias = "pipe")]
+    Pipe,
+}
+
+/// Union of the requested `--type` values (fd semantics).
+///
+/// `f`/`d`/`l`/`s`/`p` select entry kinds and are OR-ed together. `x` and `e` are extra
+/// constraints on top of that: `x` implies `f`, and `e` alone implies `f` + `d`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TypeFilter {
+    files: bool,
+    directories: bool,
+    symlinks: bool,
+    sockets: bool,
+    pipes: bool,
+    executables_only: bool,
+    empty_only: bool,
+}
+
+impl TypeFilter {
+    pub fn new(types: &[FileType]) -> Self {
+        let mut tf = Self::default();
+        for t in types {
+            match t {
+                FileType::File => tf.files = true,
+                FileType::Directory => tf.directories = true,
+                FileType::Symlink => tf.symlinks = true,
+                FileType::Executable => {
+                    tf.executables_only = true;
+                    tf.files = true;
+                }
+                FileType::Empty => tf.empty_only = true,
+                FileType::Socket => tf.sockets = true,
+                FileType::Pipe => tf.pipes = true,
+            }
+        }
+        if tf.empty_only && !(tf.files || tf.directories) {
+            tf.files = true;
+            tf.directories = true;
+        }
+        tf
+    }
+
+    fn matches(&self, path: &Path, meta: &Metadata) -> bool {
+        let ft = meta.file_type();
+        let kind_ok = (ft.is_file() && self.files)
+            || (ft.is_dir() && self.directories)
+            || (ft.is_symlink() && self.symlinks)
+            || (is_socket(&ft) && self.sockets)
+            || (is_pipe(&ft) && self.pipes);
+        if !kind_ok {
+            return false;
+        }
+        if self.executables_only && !is_executable(meta) {
+            return false;
+        }
+        if self.empty_only && !is_empty(path, meta) {
+            return false;
+        }
+        true
+    }
+}
+
+/// fd-style `--size` constraint: `+N` (at least), `-N` (at most) or `N` (exactly), with an
+/// optional unit suffix.
+///
+/// Units follow fd: `b`, decimal `k`/`m`/`g`/`t` (powers of 1000) and binary
+/// `ki`/`mi`/`gi`/`ti` (powers of 1024), case-insensitive and optionally ending in `b`
+/// (`10kb`, `1GiB`). A bare number is bytes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SizeFilter {
+    Min(u64),
+    Max(u64),
+    Equals(u64),
+}
+
+impl SizeFilter {
+    fn matches(self, len: u64) -> bool {
+        match self {
+            SizeFilter::Min(n) => len >= n,
+            SizeFilter::Max(n) => len <= n,
+            SizeFilter::Equals(n) => len == n,
+        }
+    }
+}
+
+impl FromStr for SizeFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid size '{s}' (expected e.g. +10k, -1Mi, 500b)");
+
+        let (ctor, rest): (fn(u64) -> SizeFilter, &str) = match s.as_bytes().first() {
+            Some(b'+') => (SizeFilter::Min, &s[1..]),
+            Some(b'-') => (SizeFilter::Max, &s[1..]),
+            _ => (SizeFilter::Equals, s),
+        };
+
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (digits, unit) = rest.split_at(split);
+        let n: u64 = digits.parse().map_err(|_| invalid())?;
+        let unit = unit.to_ascii_lowercase();
+        // fd also takes a trailing `b` on any unit: `10kb`, `1GiB`.
+        let unit = match unit.strip_suffix('b') {
+            Some(prefix) if !matches!(prefix, "" | "b") => prefix,
+            _ => &unit,
+        };
+        let multiplier: u64 = match unit {
+            "" | "b" => 1,
+            "k" => 1000,
+            "m" => 1000u64.pow(2),
+            "g" => 1000u64.pow(3),
+            "t" => 1000u64.pow(4),
+            "ki" => 1024,
+            "mi" => 1024u64.pow(2),
+            "gi" => 1024u64.pow(3),
+            "ti" => 1024u64.pow(4),
+            _ => return Err(invalid()),
+        };
+        let bytes = n.checked_mul(multiplier).ok_or_else(invalid)?;
+        Ok(ctor(bytes))
+    }
+}
+
+/// Parse a `--changed-within`/`--changed-before` threshold: a duration back from now
+/// (`2d`), a date (`2024-01-31`, midnight), a date and time (`2024-01-31 12:00` or with
+/// `T`, seconds optional), or `@` and Unix seconds.
+///
+/// Dates are UTC unless they end in an offset like `+02:00`. fd reads them as local time
+/// instead.
+pub fn parse_time(s: &str) -> Result<SystemTime, String> {
+    parse_time_at(s, SystemTime::now())
+}
+
+fn parse_time_at(s: &str, now: SystemTime) -> Result<SystemTime, String> {
+    let invalid = || {
+        format!(
+            "invalid time '{s}' (expected a duration like 2d, a date like 2024-01-31, or @<unix seconds>)"
+        )
+    };
+    let secs = if let Some(secs) = s.strip_prefix('@') {
+        secs.parse::<i64>().ok()
+    } else if s.as_bytes().get(4) == Some(&b'-') {
+        parse_datetime(s)
+    } else {
+        return parse_duration(s)
+            .ok()
+            .and_then(|d| now.checked_sub(d))
+            .ok_or_else(invalid);
+    };
+    let secs = secs.ok_or_else(invalid)?;
+    let offset = Duration::from_secs(secs.unsigned_abs());
+    if secs < 0 {
+        SystemTime::UNIX_EPOCH.checked_sub(offset)
+    } else {
+        SystemTime::UNIX_EPOCH.checked_add(offset)
+    }
+    .ok_or_else(invalid)
+}
+
+/// `YYYY-MM-DD[( |T)HH:MM[:SS][Z|±HH:MM]]` as Unix seconds.
+fn parse_datetime(s: &str) -> Option<i64> {
+    let (date, time) = match s.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+    let mut parts = date.split('-');
+    let year = i64::from(fixed_digits(parts.next()?, 4)?);
+    let month = fixed_digits(parts.next()?, 2)?;
+    let day = fixed_digits(parts.next()?, 2)?;
+    if parts.next().is_some() || !(1..=12).contains(&month) {
+        return None;
+    }
+    if day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    let mut secs = days_from_civil(year, month, day) * 86_400;
+
+    if let Some(time) = time {
+        let (clock, offset) = if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+            (clock, 0)
+        } else if let Some(i) = time.rfind(['+', '-']) {
+            let (h, m) = time[i + 1..].split_once(':')?;
+            let offset =
+                i64::from(fixed_digits(h, 2)?) * 3600 + i64::from(fixed_digits(m, 2)?) * 60;
+            (
+                &time[..i],
+                if time[i..].starts_with('-') {
+                    -offset
+                } else {
+                    offset
+                },
+            )
+        } else {
+            (time, 0)
+        };
+        let mut fields = clock.split(':');
+        let hour = fixed_digits(fields.next()?, 2)?;
+        let minute = fixed_digits(fields.next()?, 2)?;
+        let second = fields.next().map_or(Some(0), |f| fixed_digits(f, 2))?;
+        if fields.next().is_some() || hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        secs += i64::from(hour * 3600 + minute * 60 + second) - offset;
+    }
+    Some(secs)
+}
+
+/// `s` as a number of exactly `len` ASCII digits.
+fn fixed_digits(s: &str, len: usize) -> Option<u32> {
+    (s.len() == len && s.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| s.parse().ok())
+        .flatten()
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar (Howard Hinnant's
+/// `days_from_civil`).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((i64::from(month) + 9) % 12) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// `2024-01-31T12:00:00Z`, the form Spotlight's `$time.iso()` takes.
+pub(crate) fn format_iso_utc(time: SystemTime) -> String {
+    let (date, hour, minute, second) = utc_fields(time);
+    format!("{date}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+/// `time` in UTC, to the second: the `YYYY-MM-DD` date, hour, minute and second.
+pub(crate) fn utc_fields(time: SystemTime) -> (String, i64, i64, i64) {
+    let secs = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => i64::try_from(d.as_secs()).unwrap_or(i64::MAX),
+        Err(e) => -i64::try_from(e.duration().as_secs()).unwrap_or(i64::MAX),
+    };
+    let (days, secs_of_day) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    (
+        format!("{year:04}-{month:02}-{day:02}"),
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+    )
+}
+
+/// The proleptic Gregorian date `days` after 1970-01-01 (Howard Hinnant's
+/// `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Parse a duration like `10min`, `2d`, or `1h30m`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{s}' (expected e.g. 30s, 10min, 2h, 3d, 1w)");
+
+    let mut total = Duration::ZERO;
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        let digits_end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit_end = rest[digits_end..]
+            .find(|c: char| c.is_ascii_digit())
+            .map_or(rest.len(), |i| digits_end + i);
+        let n: u64 = rest[..digits_end].parse().map_err(|_| invalid())?;
+        let ms_per_unit: u64 = match rest[digits_end..unit_end].trim() {
+            "ms" | "msec" | "msecs" => 1,
+            "s" | "sec" | "secs" | "second" | "seconds" => 1000,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60 * 1000,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 60 * 60 * 1000,
+            "d" | "day" | "days" => 24 * 60 * 60 * 1000,
+            "w" | "week" | "weeks" => 7 * 24 * 60 * 60 * 1000,
+            "y" | "year" | "years" => 365 * 24 * 60 * 60 * 1000,
+            _ => return Err(invalid()),
+        };
+        let ms = n.checked_mul(ms_per_unit).ok_or_else(invalid)?;
+        total = total
+            .checked_add(Duration::from_millis(ms))
+            .ok_or_else(invalid)?;
+        rest = rest[unit_end..].trim_start();
+    }
+    Ok(total)
+}
+
+/// Whether `--extension` compares case-sensitively.
+///
+/// An explicit `--case-sensitive`/`--ignore-case` decides; otherwise extensions follow the
+/// volume, so `-e TS` matches only `.TS` on a case-sensitive volume but also `.ts` on the
+/// default case-insensitive one. `volume_case_sensitive` is only consulted when needed;
+/// if it can't tell, the macOS default (insensitive) is assumed.
+pub fn extensions_case_sensitive(
+    case: CaseMode,
+    volume_case_sensitive: impl FnOnce() -> Option<bool>,
+) -> bool {
+    match case {
+        CaseMode::Sensitive => true,
+        CaseMode::Insensitive => false,
+        CaseMode::Smart => volume_case_sensitive().unwrap_or(false),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CriteriaConfig {
+    pub file_types: Vec<FileType>,
+    pub extensions: Vec<String>,
+    /// Compare extensions exactly instead of ignoring case (see
+    /// `extensions_case_sensitive`).
+    pub extensions_case_sensitive: bool,
+    /// All constraints must hold (so `+1k -1M` is a range).
+    pub sizes: Vec<SizeFilter>,
+    /// Only keep entries modified at or after this instant.
+    pub changed_after: Option<SystemTime>,
+    /// Only keep entries modified before this instant.
+    pub changed_before: Option<SystemTime>,
+    /// Only keep entries with this owner (`--owner`).
+    pub owner: Option<OwnerFilter>,
+    /// Drop files that look binary (`--no-binary`).
+    pub skip_binary: bool,
+}
+
+/// Rust-side post-filters on a candidate's own metadata, name and contents (`--type`,
+/// `--extension`, `--size`, `--changed-within`, `--changed-before`, `--owner`,
+/// `--no-binary`).
+///
+/// Name checks (`check_name`) run before anything touches the filesystem, metadata checks
+/// (`check_metadata`) after ignore/hidden filtering. Spotlight can't evaluate entry types,
+/// and we don't trust it with fd's extension semantics.
+#[derive(Clone, Debug, Default)]
+pub struct Criteria {
+    types: Option<TypeFilter>,
+    /// Without a leading '.', and lowercased unless `extensions_case_sensitive`.
+    extensions: Vec<String>,
+    extensions_case_sensitive: bool,
+    sizes: Vec<SizeFilter>,
+    changed_after: Option<SystemTime>,
+    changed_before: Option<SystemTime>,
+    owner: Option<OwnerFilter>,
+    skip_binary: bool,
+}
+
+impl Criteria {
+    pub fn new(cfg: CriteriaConfig) -> Self {
+        Self {
+            types: (!cfg.file_types.is_empty()).then(|| TypeFilter::new(&cfg.file_types)),
+            extensions: cfg
+                .extensions
+                .iter()
+                .map(|e| {
+                    let e = e.trim_start_matches('.');
+                    if cfg.extensions_case_sensitive {
+                        e.to_owned()
+                    } else {
+                        e.to_lowercase()
+                    }
+                })
+                .collect(),
+            extensions_case_sensitive: cfg.extensions_case_sensitive,
+            sizes: cfg.sizes,
+            changed_after: cfg.changed_after,
+            changed_before: cfg.changed_before,
+            owner: cfg.owner,
+            skip_binary: cfg.skip_binary,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_none()
+            && self.extensions.is_empty()
+            && self.sizes.is_empty()
+            && self.changed_after.is_none()
+            && self.changed_before.is_none()
+            && self.owner.is_none()
+            && !self.skip_binary
+    }
+
+    #[cfg(test)]
+    pub fn matches(&self, path: &Path) -> bool {
+        self.check(path).is_ok()
+    }
+
+    /// Like `matches`, but reports which criterion rejected the candidate.
+    #[cfg(test)]
+    pub fn check(&self, path: &Path) -> Result<(), Rejection> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        self.check_name(path)?;
+        // Match fd defaults: do not follow symlinks when classifying entries.
+        self.check_metadata(path, fs::symlink_metadata(path).ok().as_ref())
+    }
+
+    /// The criteria decided by the name alone (`--extension`), cheap enough to run before
+    /// anything touches the filesystem.
+    pub fn check_name(&self, path: &Path) -> Result<(), Rejection> {
+        if !self.extensions.is_empty() && !self.matches_extension(path) {
+            return Err(Rejection::Extension);
+        }
+        Ok(())
+    }
+
+    /// The criteria that need the candidate's `lstat` (`None` if it failed), to be run
+    /// after `check_name`.
+    pub fn check_metadata(&self, path: &Path, meta: Option<&Metadata>) -> Result<(), Rejection> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        // A candidate that vanished since Spotlight indexed it has no type at all.
+        let Some(meta) = meta else {
+            return Err(Rejection::Type);
+        };
+
+        // `-e` implies "not a directory" unless `-t d` was asked for.
+        match self.types {
+            Some(tf) => {
+                if !tf.matches(path, meta) {
+                    return Err(Rejection::Type);
+                }
+            }
+            None => {
+                if !self.extensions.is_empty() && meta.is_dir() {
+                    return Err(Rejection::Type);
+                }
+            }
+        }
+
+        // Like fd, size constraints only ever match regular files.
+        if !self.sizes.is_empty() {
+            let fits = meta.is_file() && self.sizes.iter().all(|s| s.matches(meta.len()));
+            if !fits {
+                return Err(Rejection::Size);
+            }
+        }
+
+        if self.changed_after.is_some() || self.changed_before.is_some() {
+            let in_range = meta.modified().is_ok_and(|m| {
+                self.changed_after.is_none_or(|after| m >= after)
+                    && self.changed_before.is_none_or(|before| m < before)
+            });
+            if !in_range {
+                return Err(Rejection::Time);
+            }
+        }
+
+        if let Some(owner) = &self.owner
+            && !owner.matches(meta)
+        {
+            return Err(Rejection::Owner);
+        }
+
+        // Last, since it's the only check that reads the file.
+        if self.skip_binary && meta.is_file() && looks_binary(path) {
+            return Err(Rejection::Binary);
+        }
+
+        Ok(())
+    }
+
+    fn matches_extension(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            return false;
+        };
+        let name = if self.extensions_case_sensitive {
+            Cow::Borrowed(name)
+        } else {
+            Cow::Owned(name.to_lowercase())
+        };
+        // Like fd: the extension may contain dots (`tar.gz`) and there must be a non-empty
+        // stem in front of it (`.rs` alone is not a Rust file).
+        self.extensions.iter().any(|ext| {
+            name.len() > ext.len() + 1
+                && name.ends_with(ext.as_str())
+                && name.as_bytes()[name.len() - ext.len() - 1] == b'.'
+        })
+    }
+}
+
+/// How much of a file is sniffed for NUL bytes, like ripgrep's binary detection.
+const BINARY_SNIFF_LEN: u64 = 8 * 1024;
+
+/// A NUL byte near the start means binary. Files that can't be read are given the benefit
+/// of the doubt.
+fn looks_binary(path: &Path) -> bool {
+    let mut head = Vec::new();
+    fs::File::open(path)
+        .and_then(|f| f.take(BINARY_SNIFF_LEN).read_to_end(&mut head))
+        .is_ok_and(|_| head.contains(&0))
+}
+
+fn is_executable(meta: &Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        meta.permissions().mode() & 0o111 != 0
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        false
+    }
+}
+
+fn is_empty(path: &Path, meta: &Metadata) -> bool {
+    if meta.is_dir() {
+        fs::read_dir(path).is_ok_and(|mut rd| rd.next().is_none())
+    } else if meta.is_file() {
+        meta.len() == 0
+    } else {
+        false
+    }
+}
+
+fn is_socket(ft: &fs::FileType) -> bool {
+    #[cfg(unix)]
+    {
+        ft.is_socket()
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = ft;
+        false
+    }
+}
+
+fn is_pipe(ft: &fs::FileType) -> bool {
+    #[cfg(unix)]
+    {
+        ft.is_fifo()
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = ft;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn criteria(file_types: &[FileType], extensions: &[&str]) -> Criteria {
+        Criteria::new(CriteriaConfig {
+            file_types: file_types.to_vec(),
+            extensions: extensions.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn no_binary_drops_files_with_nul_bytes_only() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("notes.txt"), "plain text\n").unwrap();
+        fs::write(root.join("image.png"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        // A NUL past the sniffed prefix doesn't count.
+        let mut late = vec![b'a'; BINARY_SNIFF_LEN as usize];
+        late.push(0);
+        fs::write(root.join("late.log"), late).unwrap();
+        fs::create_dir(root.join("dir")).unwrap();
+
+        let c = Criteria::new(CriteriaConfig {
+            skip_binary: true,
+            ..Default::default()
+        });
+        assert!(!c.is_empty());
+        assert_eq!(c.check(&root.join("notes.txt")), Ok(()));
+        assert_eq!(c.check(&root.join("image.png")), Err(Rejection::Binary));
+        assert_eq!(c.check(&root.join("late.log")), Ok(()));
+        assert_eq!(c.check(&root.join("dir")), Ok(()));
+
+        assert!(Criteria::default().matches(&root.join("image.png")));
+    }
+
+    #[test]
+    fn extension_implies_not_a_directory() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("x.rs")).unwrap();
+        fs::write(root.join("main.rs"), "x").unwrap();
+
+        let c = criteria(&[], &["rs"]);
+        assert!(!c.matches(&root.join("x.rs")));
+        assert!(c.matches(&root.join("main.rs")));
+    }
+
+    #[test]
+    fn extension_with_directory_type_matches_directories() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("x.rs")).unwrap();
+        fs::write(root.join("main.rs"), "x").unwrap();
+
+        let c = criteria(&[FileType::Directory], &["rs"]);
+        assert!(c.matches(&root.join("x.rs")));
+        assert!(!c.matches(&root.join("main.rs")));
+    }
+
+    #[test]
+    fn extension_is_case_insensitive_and_needs_a_stem() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        for f in ["A.RS", ".rs", "archive.tar.gz", "notrs"] {
+            fs::write(root.join(f), "x").unwrap();
+        }
+
+        let c = criteria(&[], &[".rs", "tar.gz"]);
+        assert!(c.matches(&root.join("A.RS")));
+        assert!(c.matches(&root.join("archive.tar.gz")));
+        assert!(!c.matches(&root.join(".rs")));
+        assert!(!c.matches(&root.join("notrs")));
+    }
+
+    #[test]
+    fn type_file_and_directory() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("file"), "x").unwrap();
+
+        let files = criteria(&[FileType::File], &[]);
+        assert!(files.matches(&root.join("file")));
+        assert!(!files.matches(&root.join("dir")));
+
+        let both = criteria(&[FileType::File, FileType::Directory], &[]);
+        assert!(both.matches(&root.join("file")));
+        assert!(both.matches(&root.join("dir")));
+    }
+
+    #[test]
+    fn empty_alone_implies_files_and_directories() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("empty_dir")).unwrap();
+        fs::create_dir_all(root.join("full_dir")).unwrap();
+        fs::write(root.join("full_dir/f"), "x").unwrap();
+        fs::write(root.join("empty_file"), "").unwrap();
+
+        let c = criteria(&[FileType::Empty], &[]);
+        assert!(c.matches(&root.join("empty_dir")));
+        assert!(c.matches(&root.join("empty_file")));
+        assert!(!c.matches(&root.join("full_dir")));
+        assert!(!c.matches(&root.join("full_dir/f")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn executable_and_symlink_types() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("run.sh"), "x").unwrap();
+        fs::set_permissions(root.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(root.join("data"), "x").unwrap();
+        std::os::unix::fs::symlink(root.join("data"), root.join("link")).unwrap();
+
+        let exe = criteria(&[FileType::Executable], &[]);
+        assert!(exe.matches(&root.join("run.sh")));
+        assert!(!exe.matches(&root.join("data")));
+
+        let links = criteria(&[FileType::Symlink], &[]);
+        assert!(links.matches(&root.join("link")));
+        assert!(!links.matches(&root.join("data")));
+    }
+
+    #[test]
+    fn size_parser_accepts_fd_syntax() {
+        assert_eq!("+10k".parse(), Ok(SizeFilter::Min(10_000)));
+        assert_eq!("-1Mi".parse(), Ok(SizeFilter::Max(1024 * 1024)));
+        assert_eq!("500".parse(), Ok(SizeFilter::Equals(500)));
+        assert_eq!("3b".parse(), Ok(SizeFilter::Equals(3)));
+        assert_eq!("+2G".parse(), Ok(SizeFilter::Min(2_000_000_000)));
+        assert!("10x".parse::<SizeFilter>().is_err());
+        assert!("+".parse::<SizeFilter>().is_err());
+        assert!("k".parse::<SizeFilter>().is_err());
+        assert!("99999999999999ti".parse::<SizeFilter>().is_err());
+    }
+
+    #[test]
+    fn size_parser_units_and_edge_cases() {
+        let bytes = |s: &str| match s.parse::<SizeFilter>() {
+            Ok(SizeFilter::Equals(n)) => n,
+            other => panic!("{s}: {other:?}"),
+        };
+        for (units, base) in [
+            (["k", "m", "g", "t"], 1000u64),
+            (["ki", "mi", "gi", "ti"], 1024),
+        ] {
+            for (exp, unit) in (1..).zip(units) {
+                let want = 7 * base.pow(exp);
+                assert_eq!(bytes(&format!("7{unit}")), want, "{unit}");
+                assert_eq!(bytes(&format!("7{unit}b")), want, "{unit}b");
+                assert_eq!(bytes(&format!("7{}", unit.to_uppercase())), want, "{unit}");
+            }
+        }
+        assert_eq!(bytes("1GiB"), 1 << 30);
+        assert_eq!(bytes("0"), 0);
+        assert_eq!(bytes("0b"), 0);
+        assert_eq!(bytes("007k"), 7000);
+        assert_eq!("-0".parse(), Ok(SizeFilter::Max(0)));
+        assert_eq!(
+            "+18446744073709551615".parse(),
+            Ok(SizeFilter::Min(u64::MAX))
+        );
+
+        for bad in [
+            "",
+            "b",
+            "kb",
+            "-",
+            "++1",
+            "+-1",
+            "1.5k",
+            "1 k",
+            " 1k",
+            "1kk",
+            "1bb",
+            "1ib",
+            "1kbi",
+            "1e3",
+            "16777216ti",
+            "18446744073709551616",
+        ] {
+            assert!(
+                bad.parse::<SizeFilter>().is_err(),
+                "{bad:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn duration_parser_accepts_units_and_combinations() {
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("10min"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(2 * 86_400)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5_400)));
+        assert_eq!(parse_duration("1h 30min"), Ok(Duration::from_secs(5_400)));
+        assert_eq!(parse_duration("1s500ms"), Ok(Duration::from_millis(1_500)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3 fortnights").is_err());
+    }
+
+    #[test]
+    fn size_range_only_matches_files() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("small"), "x").unwrap();
+        fs::write(root.join("medium"), "x".repeat(100)).unwrap();
+        fs::write(root.join("large"), "x".repeat(1000)).unwrap();
+        fs::create_dir_all(root.join("dir")).unwrap();
+
+        let c = Criteria::new(CriteriaConfig {
+            sizes: vec![SizeFilter::Min(10), SizeFilter::Max(500)],
+            ..Default::default()
+        });
+        assert_eq!(c.check(&root.join("small")), Err(Rejection::Size));
+        assert_eq!(c.check(&root.join("medium")), Ok(()));
+        assert_eq!(c.check(&root.join("large")), Err(Rejection::Size));
+        assert_eq!(c.check(&root.join("dir")), Err(Rejection::Size));
+    }
+
+    #[test]
+    fn changed_after_compares_mtime() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("old"), "x").unwrap();
+        fs::write(root.join("new"), "x").unwrap();
+        let now = SystemTime::now();
+        fs::File::options()
+            .write(true)
+            .open(root.join("old"))
+            .unwrap()
+            .set_modified(now - Duration::from_secs(3 * 86_400))
+            .unwrap();
+
+        let c = Criteria::new(CriteriaConfig {
+            changed_after: Some(now - Duration::from_secs(86_400)),
+            ..Default::default()
+        });
+        assert_eq!(c.check(&root.join("old")), Err(Rejection::Time));
+        assert_eq!(c.check(&root.join("new")), Ok(()));
+
+        let c = Criteria::new(CriteriaConfig {
+            changed_before: Some(now - Duration::from_secs(86_400)),
+            ..Default::default()
+        });
+        assert_eq!(c.check(&root.join("old")), Ok(()));
+        assert_eq!(c.check(&root.join("new")), Err(Rejection::Time));
+
+        let c = Criteria::new(CriteriaConfig {
+            changed_after: Some(now - Duration::from_secs(4 * 86_400)),
+            changed_before: Some(now - Duration::from_secs(2 * 86_400)),
+            ..Default::default()
+        });
+        assert_eq!(c.check(&root.join("old")), Ok(()));
+        assert_eq!(c.check(&root.join("new")), Err(Rejection::Time));
+    }
+
+    #[test]
+    fn time_parser_accepts_durations_dates_and_timestamps() {
+        let epoch = |secs: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let now = epoch(1_700_000_000);
+        let at = |s: &str| parse_time_at(s, now);
+
+        assert_eq!(at("2d"), Ok(now - Duration::from_secs(2 * 86_400)));
+        assert_eq!(at("1h 30min"), Ok(now - Duration::from_secs(5_400)));
+        assert_eq!(at("1970-01-01"), Ok(epoch(0)));
+        assert_eq!(at("2000-03-01"), Ok(epoch(951_868_800)));
+        assert_eq!(at("2024-02-29"), Ok(epoch(1_709_164_800)));
+        assert_eq!(at("2024-01-31 12:00"), Ok(epoch(1_706_702_400)));
+        assert_eq!(at("2024-01-31T12:00:30Z"), Ok(epoch(1_706_702_430)));
+        assert_eq!(at("2024-01-31 12:00:00+02:00"), Ok(epoch(1_706_695_200)));
+        assert_eq!(at("2024-01-31T12:00-05:30"), Ok(epoch(1_706_722_200)));
+        assert_eq!(at("@1700000000"), Ok(now));
+        assert_eq!(at("@0"), Ok(epoch(0)));
+        assert_eq!(
+            at("1969-12-31"),
+            Ok(SystemTime::UNIX_EPOCH - Duration::from_secs(86_400))
+        );
+        assert_eq!(
+            at("@-60"),
+            Ok(SystemTime::UNIX_EPOCH - Duration::from_secs(60))
+        );
+        assert_eq!(format_iso_utc(epoch(1_709_251_199)), "2024-02-29T23:59:59Z");
+
+        for bad in [
+            "",
+            "yesterday",
+            "10",
+            "2023-02-29",
+            "1900-02-29",
+            "2024-13-01",
+            "2024-00-10",
+            "2024-04-31",
+            "2024-1-01",
+            "2024-01-01-01",
+            "2024-01-01 24:00",
+            "2024-01-01 12:60",
+            "2024-01-01 12",
+            "2024-01-01 12:00:00:00",
+            "2024-01-01T12:00+2",
+            "2024-01-01 ",
+            "@",
+            "@1.5",
+        ] {
+            assert!(at(bad).is_err(), "{bad:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn extension_case_follows_volume_unless_explicit() {
+        let never = || -> Option<bool> { panic!("volume probed despite explicit case mode") };
+        assert!(extensions_case_sensitive(CaseMode::Sensitive, never));
+        assert!(!extensions_case_sensitive(CaseMode::Insensitive, never));
+        assert!(extensions_case_sensitive(CaseMode::Smart, || Some(true)));
+        assert!(!extensions_case_sensitive(CaseMode::Smart, || Some(false)));
+        assert!(!extensions_case_sensitive(CaseMode::Smart, || None));
+    }
+
+    #[test]
+    fn case_sensitive_extensions_compare_exactly() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("upper.TS"), "").unwrap();
+        fs::write(root.join("lower.ts"), "").unwrap();
+
+        let criteria = |extensions_case_sensitive| {
+            Criteria::new(CriteriaConfig {
+                extensions: vec!["TS".into()],
+                extensions_case_sensitive,
+                ..Default::default()
+            })
+        };
+        let exact = criteria(true);
+        assert!(exact.matches(&root.join("upper.TS")));
+        assert!(!exact.matches(&root.join("lower.ts")));
+        let folded = criteria(false);
+        assert!(folded.matches(&root.join("upper.TS")));
+        assert!(folded.matches(&root.join("lower.ts")));
+    }
+
+    #[test]
+    fn extension_matching_follows_the_volume_under_test() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("a.TS"), "").unwrap();
+        fs::write(root.join("b.ts"), "").unwrap();
+
+        let criteria = Criteria::new(CriteriaConfig {
+            extensions: vec!["TS".into()],
+            extensions_case_sensitive: extensions_case_sensitive(CaseMode::Smart, || {
+                crate::volume::is_case_sensitive(root)
+            }),
+            ..Default::default()
+        });
+        assert!(criteria.matches(&root.join("a.TS")));
+        let case_sensitive_volume = !root.join("A.ts").exists();
+        assert_eq!(criteria.matches(&root.join("b.ts")), !case_sensitive_volume);
+    }
+}
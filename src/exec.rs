@@ -0,0 +1,1170 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    output::{self, Delimiter, Sink},
+    template::{Placeholder, Token, expand_arg, has_placeholder, tokenize},
+};
+
+/// A spawned command exited unsuccessfully.
+///
+/// The command has already reported its own errors, so `main` maps this to a non-zero exit
+/// status without printing anything else.
+#[derive(Debug)]
+pub struct ExecFailed;
+
+impl std::fmt::Display for ExecFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "command failed")
+    }
+}
+
+impl std::error::Error for ExecFailed {}
+
+/// How many `--exec` commands succeeded and failed, for the closing summary.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Outcomes {
+    ok: usize,
+    failed: usize,
+}
+
+impl Outcomes {
+    fn record(&mut self, success: bool) {
+        if success {
+            self.ok += 1;
+        } else {
+            self.failed += 1;
+        }
+    }
+}
+
+/// `1 file`, `2 files`.
+fn files(n: usize) -> String {
+    format!("{n} file{}", if n == 1 { "" } else { "s" })
+}
+
+/// A command line for `--exec`, expanded once per result.
+///
+/// Arguments may contain `{}`, `{/}`, `{//}`, `{.}`, `{/.}` and `{..}` anywhere. Without any
+/// placeholder, `{}` is appended as the last argument.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommandTemplate {
+    args: Vec<Vec<Token>>,
+    /// `--exec-shell`: run the arguments as one script with this shell's `-c`.
+    shell: Option<OsString>,
+}
+
+impl CommandTemplate {
+    pub fn new(argv: &[String]) -> Result<Self> {
+        anyhow::ensure!(!argv.is_empty(), "--exec requires a command");
+        let mut args: Vec<Vec<Token>> = argv.iter().map(|a| tokenize(a)).collect();
+        if !args.iter().any(|a| has_placeholder(a)) {
+            args.push(vec![Token::Placeholder(Placeholder::Path)]);
+        }
+        Ok(Self { args, shell: None })
+    }
+
+    /// Template for `--exec-shell`: the arguments are joined with spaces into one script
+    /// for `shell -c`, and each placeholder expands to a single quoted shell word.
+    pub fn new_shell(argv: &[String], shell: OsString) -> Result<Self> {
+        let mut template = Self::new(argv)?;
+        template.shell = Some(shell);
+        Ok(template)
+    }
+
+    /// Template for `--exec-batch`, where the placeholder argument is repeated once per
+    /// result. Like fd, at most one argument may contain placeholders, and not the
+    /// program itself.
+    pub fn new_batch(argv: &[String]) -> Result<Self> {
+        anyhow::ensure!(!argv.is_empty(), "--exec-batch requires a command");
+        let template = Self::new(argv)?;
+        anyhow::ensure!(
+            !has_placeholder(&template.args[0]),
+            "the first argument of --exec-batch must be a fixed program"
+        );
+        anyhow::ensure!(
+            template.args.iter().filter(|a| has_placeholder(a)).count() == 1,
+            "only one argument of --exec-batch can contain placeholders"
+        );
+        Ok(template)
+    }
+
+    /// The command line for one result.
+    fn expand(&self, path: &Path) -> Vec<OsString> {
+        let Some(shell) = &self.shell else {
+            return self.args.iter().map(|a| expand_arg(a, path)).collect();
+        };
+        let script: Vec<String> = self
+            .args
+            .iter()
+            .map(|a| expand_arg_quoted(a, path))
+            .collect();
+        vec![shell.clone(), OsString::from("-c"), script.join(" ").into()]
+    }
+
+    /// The command line for all results at once.
+    fn expand_batch(&self, paths: &[PathBuf]) -> Vec<OsString> {
+        let mut argv = Vec::new();
+        for arg in &self.args {
+            if has_placeholder(arg) {
+                argv.extend(paths.iter().map(|p| expand_arg(arg, p)));
+            } else {
+                argv.push(expand_arg(arg, Path::new("")));
+            }
+        }
+        argv
+    }
+}
+
+/// `expand_arg` for a shell script: the text is kept as written, expansions are quoted.
+fn expand_arg_quoted(arg: &[Token], path: &Path) -> String {
+    let mut out = String::new();
+    for token in arg {
+        match token {
+            Token::Text(text) => out.push_str(text),
+            Token::Placeholder(p) => out.push_str(&shell_quote(&p.expand(path))),
+        }
+    }
+    out
+}
+
+/// `--exec`: run the command once per result, in order, sharing `sf`'s stdout and stderr.
+///
+/// A failing command doesn't stop the search; `finish` reports it afterwards. With
+/// retries, only a command that still fails after its last attempt counts as failed.
+pub struct PerResult {
+    template: CommandTemplate,
+    launcher: Launcher,
+    retries: u32,
+    retry_delay: Duration,
+    outcomes: Outcomes,
+    parallel: Option<Parallel>,
+    echo: Option<Box<dyn Write>>,
+    summary: Option<Box<dyn Write>>,
+}
+
+impl PerResult {
+    pub fn new(template: CommandTemplate, launcher: Launcher) -> Self {
+        Self {
+            template,
+            launcher,
+            retries: 0,
+            retry_delay: Duration::ZERO,
+            outcomes: Outcomes::default(),
+            parallel: None,
+            echo: None,
+            summary: None,
+        }
+    }
+
+    /// `--threads`/`--exec-ordered`: run up to `jobs` commands at once. Each command's
+    /// output is captured and written out whole when it exits: in completion order, or in
+    /// result order if `ordered`. `--dry-run` stays sequential.
+    pub fn with_jobs(self, jobs: usize, ordered: bool) -> Self {
+        self.with_jobs_writing_to(
+            jobs,
+            ordered,
+            Box::new(io::stdout()),
+            Box::new(io::stderr()),
+        )
+    }
+
+    fn with_jobs_writing_to(
+        mut self,
+        jobs: usize,
+        ordered: bool,
+        out: Box<dyn Write>,
+        err: Box<dyn Write>,
+    ) -> Self {
+        if jobs > 1 && matches!(self.launcher, Launcher::Spawn) {
+            self.parallel = Some(Parallel::new(jobs, ordered, out, err));
+        }
+        self
+    }
+
+    /// `--exec-retries`/`--exec-retry-delay`: rerun a failing command up to `retries`
+    /// more times, waiting `delay` before the first retry and doubling it each time after.
+    pub fn with_retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Print each path to stderr just before its command starts (`--exec-echo`).
+    pub fn with_echo(self, on: bool) -> Self {
+        if on {
+            self.with_echo_to(Box::new(io::stderr()))
+        } else {
+            self
+        }
+    }
+
+    fn with_echo_to(mut self, echo: Box<dyn Write>) -> Self {
+        self.echo = Some(echo);
+        self
+    }
+
+    /// Print `sf: ran command on N files (X ok, Y failed)` to stderr once every command
+    /// has finished (on by default; off with `--no-messages`).
+    pub fn with_summary(self, on: bool) -> Self {
+        // `--dry-run` runs nothing worth summarizing.
+        if on && matches!(self.launcher, Launcher::Spawn) {
+            self.with_summary_to(Box::new(io::stderr()))
+        } else {
+            self
+        }
+    }
+
+    fn with_summary_to(mut self, summary: Box<dyn Write>) -> Self {
+        self.summary = Some(summary);
+        self
+    }
+}
+
+impl Sink for PerResult {
+    fn accept(
+        &mut self,
+        _abs_path: &Path,
+        rendered: &Path,
+        _meta: Option<&fs::Metadata>,
+    ) -> Result<()> {
+        let argv = self.template.expand(rendered);
+        if let Some(echo) = self.echo.as_mut() {
+            output::write_path(echo, rendered, Delimiter::Newline)?;
+        }
+        if let Some(parallel) = self.parallel.as_mut() {
+            let Some(cmd) = self.launcher.command(&argv)? else {
+                return Ok(());
+            };
+            let program = argv[0].to_string_lossy().into_owned();
+            return parallel.submit(cmd, program, self.retries, self.retry_delay);
+        }
+        let mut delay = self.retry_delay;
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                std::thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+            }
+            match self.launcher.run(&argv) {
+                Err(e) if e.is::<ExecFailed>() => continue,
+                Ok(()) => {
+                    self.outcomes.record(true);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.outcomes.record(false);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(parallel) = self.parallel.as_mut() {
+            parallel.drain()?;
+            self.outcomes.ok += parallel.outcomes.ok;
+            self.outcomes.failed += parallel.outcomes.failed;
+        }
+        let Outcomes { ok, failed } = self.outcomes;
+        if let Some(summary) = self.summary.as_mut()
+            && ok + failed > 0
+        {
+            writeln!(
+                summary,
+                "sf: ran command on {} ({ok} ok, {failed} failed)",
+                files(ok + failed)
+            )?;
+        }
+        if failed > 0 {
+            return Err(anyhow::Error::new(ExecFailed));
+        }
+        Ok(())
+    }
+}
+
+/// Commands running on worker threads for a parallel `PerResult`.
+///
+/// Output is only ever written from the thread driving the sink, one command at a time,
+/// so the captured stdout and stderr of different commands never interleave.
+struct Parallel {
+    jobs: usize,
+    ordered: bool,
+    out: Box<dyn Write>,
+    err: Box<dyn Write>,
+    tx: mpsc::Sender<Finished>,
+    rx: mpsc::Receiver<Finished>,
+    started: usize,
+    in_flight: usize,
+    // Under `ordered`, finished commands wait here until every earlier one is written.
+    next_to_emit: usize,
+    held: BTreeMap<usize, Captured>,
+    outcomes: Outcomes,
+}
+
+struct Finished {
+    index: usize,
+    program: String,
+    result: io::Result<Captured>,
+}
+
+#[derive(Default)]
+struct Captured {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    success: bool,
+}
+
+impl Parallel {
+    fn new(jobs: usize, ordered: bool, out: Box<dyn Write>, err: Box<dyn Write>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            jobs,
+            ordered,
+            out,
+            err,
+            tx,
+            rx,
+            started: 0,
+            in_flight: 0,
+            next_to_emit: 0,
+            held: BTreeMap::new(),
+            outcomes: Outcomes::default(),
+        }
+    }
+
+    fn submit(
+        &mut self,
+        cmd: Command,
+        program: String,
+        retries: u32,
+        delay: Duration,
+    ) -> Result<()> {
+        while self.in_flight >= self.jobs {
+            self.wait_one()?;
+        }
+        let index = self.started;
+        let tx = self.tx.clone();
+        std::thread::spawn(move || {
+            let result = run_captured(cmd, retries, delay);
+            // The receiver only goes away if `sf` is already bailing out.
+            let _ = tx.send(Finished {
+                index,
+                program,
+                result,
+            });
+        });
+        self.started += 1;
+        self.in_flight += 1;
+        Ok(())
+    }
+
+    fn drain(&mut self) -> Result<()> {
+        while self.in_flight > 0 {
+            self.wait_one()?;
+        }
+        Ok(())
+    }
+
+    fn wait_one(&mut self) -> Result<()> {
+        let done = self.rx.recv().context("exec worker disappeared")?;
+        self.in_flight -= 1;
+        let captured = done
+            .result
+            .with_context(|| format!("failed to spawn {}", done.program))?;
+        if !self.ordered {
+            return self.emit(captured);
+        }
+        self.held.insert(done.index, captured);
+        while let Some(captured) = self.held.remove(&self.next_to_emit) {
+            self.emit(captured)?;
+            self.next_to_emit += 1;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, captured: Captured) -> Result<()> {
+        self.outcomes.record(captured.success);
+        self.out.write_all(&captured.stdout)?;
+        self.out.flush()?;
+        self.err.write_all(&captured.stderr)?;
+        self.err.flush()?;
+        Ok(())
+    }
+}
+
+/// Run `cmd` to completion with retries, keeping the output of every attempt.
+fn run_captured(mut cmd: Command, retries: u32, mut delay: Duration) -> io::Result<Captured> {
+    // Parallel commands can't share `sf`'s stdin in any meaningful way.
+    cmd.stdin(Stdio::null());
+    let mut captured = Captured::default();
+    for attempt in 0..=retries {
+        if attempt > 0 {
+            std::thread::sleep(delay);
+            delay = delay.saturating_mul(2);
+        }
+        let output = cmd.output()?;
+        captured.stdout.extend(output.stdout);
+        captured.stderr.extend(output.stderr);
+        captured.success = output.status.success();
+        if captured.success {
+            break;
+        }
+    }
+    Ok(captured)
+}
+
+/// `--exec-batch`: collect every result, then run the command once with all of them.
+///
+/// Each path is passed once, in the order it first arrived (sorted under `--sort`), even if
+/// Spotlight reported it twice: commands like `rm` would fail on the repeat. An empty
+/// result set skips the command entirely.
+pub struct Batch {
+    template: CommandTemplate,
+    launcher: Launcher,
+    paths: Vec<PathBuf>,
+    seen: HashSet<PathBuf>,
+    summary: Option<Box<dyn Write>>,
+}
+
+impl Batch {
+    pub fn new(template: CommandTemplate, launcher: Launcher) -> Self {
+        Self {
+            template,
+            launcher,
+            paths: Vec::new(),
+            seen: HashSet::new(),
+            summary: None,
+        }
+    }
+
+    /// Print `sf: ran command once on N files (ok)` (or `failed`) to stderr after the
+    /// command exits.
+    pub fn with_summary(self, on: bool) -> Self {
+        // `--dry-run` runs nothing worth summarizing.
+        if on && matches!(self.launcher, Launcher::Spawn) {
+            self.with_summary_to(Box::new(io::stderr()))
+        } else {
+            self
+        }
+    }
+
+    fn with_summary_to(mut self, summary: Box<dyn Write>) -> Self {
+        self.summary = Some(summary);
+        self
+    }
+}
+
+/// The summary line for a command run once on `n` results; spawn errors are left alone.
+fn write_batch_summary(
+    summary: Option<&mut Box<dyn Write>>,
+    n: usize,
+    result: &Result<()>,
+) -> Result<()> {
+    let outcome = match result {
+        Ok(()) => "ok",
+        Err(e) if e.is::<ExecFailed>() => "failed",
+        Err(_) => return Ok(()),
+    };
+    if let Some(summary) = summary {
+        writeln!(summary, "sf: ran command once on {} ({outcome})", files(n))?;
+    }
+    Ok(())
+}
+
+impl Sink for Batch {
+    fn accept(
+        &mut self,
+        _abs_path: &Path,
+        rendered: &Path,
+        _meta: Option<&fs::Metadata>,
+    ) -> Result<()> {
+        if self.seen.insert(rendered.to_path_buf()) {
+            self.paths.push(rendered.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.paths.is_empty() {
+            return Ok(());
+        }
+        let paths = std::mem::take(&mut self.paths);
+        let argv = self.template.expand_batch(&paths);
+        let result = self.launcher.run(&argv);
+        write_batch_summary(self.summary.as_mut(), paths.len(), &result)?;
+        result
+    }
+}
+
+/// The single point where every exec variant starts its command.
+///
+/// Under `--dry-run`, commands are printed as shell-quoted lines instead of spawned, so no
+/// variant can run anything by accident.
+pub enum Launcher {
+    Spawn,
+    DryRun(Box<dyn Write>),
+}
+
+impl Launcher {
+    /// Run a command to completion with `sf`'s stdio; a non-zero exit becomes `ExecFailed`.
+    fn run(&mut self, argv: &[OsString]) -> Result<()> {
+        let Some(mut cmd) = self.command(argv)? else {
+            return Ok(());
+        };
+        let status = cmd
+            .status()
+            .with_context(|| format!("failed to spawn {}", argv[0].to_string_lossy()))?;
+        if !status.success() {
+            return Err(anyhow::Error::new(ExecFailed));
+        }
+        Ok(())
+    }
+
+    /// Start a command reading from a pipe. `None` under `--dry-run`.
+    fn spawn_piped(&mut self, argv: &[OsString]) -> Result<Option<Child>> {
+        let Some(mut cmd) = self.command(argv)? else {
+            return Ok(None);
+        };
+        let child = cmd
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to spawn {}", argv[0].to_string_lossy()))?;
+        Ok(Some(child))
+    }
+
+    fn command(&mut self, argv: &[OsString]) -> Result<Option<Command>> {
+        let (program, args) = argv.split_first().context("missing command")?;
+        match self {
+            Launcher::Spawn => {
+                let mut cmd = Command::new(program);
+                cmd.args(args);
+                Ok(Some(cmd))
+            }
+            Launcher::DryRun(out) => {
+                let line: Vec<_> = argv.iter().map(|a| shell_quote(a)).collect();
+                writeln!(out, "{}", line.join(" ")).context("failed to print command")?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Quote an argument for a POSIX shell, leaving plainly safe words alone.
+fn shell_quote(arg: &OsStr) -> Cow<'_, str> {
+    let s = arg.to_string_lossy();
+    let is_safe = |b: u8| b.is_ascii_alphanumeric() || b"_-+=:,./@%".contains(&b);
+    if !s.is_empty() && s.bytes().all(is_safe) {
+        return s;
+    }
+    Cow::Owned(format!("'{}'", s.replace('\'', r"'\''")))
+}
+
+/// `--exec-batch-stdin`: run one command and stream every result to its stdin.
+///
+/// The command is spawned lazily on the first result, so an empty result set never runs
+/// it. Results are written as they arrive, separated by `delimiter` (independent of
+/// `--print0`, which only affects `sf`'s own stdout).
+pub struct BatchStdin {
+    argv: Vec<OsString>,
+    delimiter: Delimiter,
+    launcher: Launcher,
+    started: bool,
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+    count: usize,
+    summary: Option<Box<dyn Write>>,
+}
+
+impl BatchStdin {
+    pub fn new(argv: Vec<String>, delimiter: Delimiter, launcher: Launcher) -> Self {
+        Self {
+            argv: argv.into_iter().map(OsString::from).collect(),
+            delimiter,
+            launcher,
+            started: false,
+            child: None,
+            stdin: None,
+            count: 0,
+            summary: None,
+        }
+    }
+
+    /// Like `Batch::with_summary`, counting every result streamed to the command.
+    pub fn with_summary(self, on: bool) -> Self {
+        // `--dry-run` runs nothing worth summarizing.
+        if on && matches!(self.launcher, Launcher::Spawn) {
+            self.with_summary_to(Box::new(io::stderr()))
+        } else {
+            self
+        }
+    }
+
+    fn with_summary_to(mut self, summary: Box<dyn Write>) -> Self {
+        self.summary = Some(summary);
+        self
+    }
+
+    fn spawn(&mut self) -> Result<()> {
+        self.started = true;
+        anyhow::ensure!(
+            !self.argv.is_empty(),
+            "--exec-batch-stdin requires a command"
+        );
+        if let Some(mut child) = self.launcher.spawn_piped(&self.argv)? {
+            self.stdin = child.stdin.take();
+            self.child = Some(child);
+        }
+        Ok(())
+    }
+}
+
+impl Sink for BatchStdin {
+    fn accept(
+        &mut self,
+        _abs_path: &Path,
+        rendered: &Path,
+        _meta: Option<&fs::Metadata>,
+    ) -> Result<()> {
+        if !self.started {
+            self.spawn()?;
+        }
+        self.count += 1;
+        let Some(stdin) = self.stdin.as_mut() else {
+            // The command stopped reading (or was only printed by `--dry-run`); drain the
+            // rest without failing.
+            return Ok(());
+        };
+        match output::write_path(stdin, rendered, self.delimiter) {
+            Ok(()) => Ok(()),
+            // Like `sf | head`: a consumer that exits early is not an error.
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                self.stdin = None;
+                Ok(())
+            }
+            Err(e) => Err(anyhow::Error::new(e).context("failed to write to command stdin")),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(mut stdin) = self.stdin.take() {
+            match stdin.flush() {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                    return Err(anyhow::Error::new(e).context("failed to write to command stdin"));
+                }
+                _ => {}
+            }
+            // Dropping closes the pipe so the command sees EOF.
+        }
+        let Some(mut child) = self.child.take() else {
+            return Ok(());
+        };
+        let status = child.wait().context("failed to wait for command")?;
+        let result = if status.success() {
+            Ok(())
+        } else {
+            Err(anyhow::Error::new(ExecFailed))
+        };
+        write_batch_summary(self.summary.as_mut(), self.count, &result)?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn sh(script: &str, out: &Path) -> Vec<String> {
+        vec![
+            "sh".into(),
+            "-c".into(),
+            script.into(),
+            "sh".into(),
+            out.to_str().unwrap().into(),
+        ]
+    }
+
+    fn feed(sink: &mut dyn Sink, paths: &[&str]) -> Result<()> {
+        for p in paths {
+            sink.accept(Path::new(p), Path::new(p), None)?;
+        }
+        sink.finish()
+    }
+
+    #[test]
+    fn exec_gets_the_printed_path_relative_to_the_cwd() {
+        // `sf subdir child -x cat` run from `parent`: `{}` is `subdir/child.txt`, exactly
+        // what would be printed, so the command (which inherits the cwd) can open it.
+        let tmp = TempDir::new().unwrap();
+        let parent = tmp.path();
+        fs::create_dir_all(parent.join("subdir")).unwrap();
+        fs::write(parent.join("subdir/child.txt"), "hello\n").unwrap();
+        let style = output::OutputStyle::new(
+            parent.to_path_buf(),
+            parent.join("subdir"),
+            Some(Path::new("subdir")),
+            output::PathMode::Relative,
+        );
+        let abs = parent.join("subdir/child.txt");
+        let rendered = style.render(&abs);
+        assert_eq!(rendered, Path::new("subdir/child.txt"));
+
+        // Changing into `parent` stands in for running `sf` from there.
+        let out = parent.join("out");
+        let mut argv = sh(r#"cd "$2" && cat "$3" > "$1""#, &out);
+        argv.extend([parent.to_str().unwrap().into(), "{}".into()]);
+        let mut sink = PerResult::new(CommandTemplate::new(&argv).unwrap(), Launcher::Spawn);
+        sink.accept(&abs, &rendered, None).unwrap();
+        sink.finish().unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "hello\n");
+    }
+
+    #[test]
+    fn nul_separator_feeds_xargs_0() {
+        let tmp = TempDir::new().unwrap();
+        let out = tmp.path().join("out");
+        let mut sink = BatchStdin::new(
+            sh(r#"xargs -0 printf '[%s]\n' > "$1""#, &out),
+            Delimiter::Nul,
+            Launcher::Spawn,
+        );
+        feed(&mut sink, &["a b", "c\nd"]).unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "[a b]\n[c\nd]\n");
+    }
+
+    #[test]
+    fn newline_separator_feeds_line_consumers() {
+        let tmp = TempDir::new().unwrap();
+        let out = tmp.path().join("out");
+        let mut sink = BatchStdin::new(
+            sh(r#"cat > "$1""#, &out),
+            Delimiter::Newline,
+            Launcher::Spawn,
+        );
+        feed(&mut sink, &["a b", "c"]).unwrap();
+        assert_eq!(fs::read(&out).unwrap(), b"a b\nc\n");
+    }
+
+    #[test]
+    fn empty_result_set_never_spawns() {
+        let tmp = TempDir::new().unwrap();
+        let out = tmp.path().join("out");
+        let mut sink = BatchStdin::new(sh(r#"touch "$1""#, &out), Delimiter::Nul, Launcher::Spawn);
+        feed(&mut sink, &[]).unwrap();
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn failing_command_is_reported() {
+        let mut sink = BatchStdin::new(vec!["false".into()], Delimiter::Nul, Launcher::Spawn);
+        let err = feed(&mut sink, &["x"]).unwrap_err();
+        assert!(err.downcast_ref::<ExecFailed>().is_some());
+    }
+
+    #[test]
+    fn command_that_stops_reading_is_not_an_error() {
+        let tmp = TempDir::new().unwrap();
+        let out = tmp.path().join("out");
+        let mut sink = BatchStdin::new(
+            sh(r#"head -c 1 > "$1""#, &out),
+            Delimiter::Newline,
+            Launcher::Spawn,
+        );
+        let many = vec!["some/long/path/name"; 50_000];
+        feed(&mut sink, &many).unwrap();
+        assert_eq!(fs::read(&out).unwrap(), b"s");
+    }
+
+    fn expand(argv: &[&str], path: &str) -> Vec<String> {
+        let argv: Vec<String> = argv.iter().map(|a| a.to_string()).collect();
+        CommandTemplate::new(&argv)
+            .unwrap()
+            .expand(Path::new(path))
+            .into_iter()
+            .map(|a| a.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn placeholders_expand_from_the_rendered_path() {
+        assert_eq!(
+            expand(
+                &["echo", "{}", "{/}", "{//}", "{.}", "{/.}"],
+                "src/a.tar.gz"
+            ),
+            [
+                "echo",
+                "src/a.tar.gz",
+                "a.tar.gz",
+                "src",
+                "src/a.tar",
+                "a.tar"
+            ]
+        );
+        assert_eq!(
+            expand(&["echo", "{//}", "{.}", "{/.}"], ".hidden"),
+            ["echo", ".", ".hidden", ".hidden"]
+        );
+        assert_eq!(
+            expand(&["mv", "{}", "{.}.bak", "--x={/}"], "dir/f.txt"),
+            ["mv", "dir/f.txt", "dir/f.bak", "--x=f.txt"]
+        );
+    }
+
+    #[test]
+    fn grandparent_placeholder_moves_up_two_levels() {
+        assert_eq!(
+            expand(&["mv", "{}", "{..}/"], "photos/2024/trip/a.jpg"),
+            ["mv", "photos/2024/trip/a.jpg", "photos/2024/"]
+        );
+        assert_eq!(
+            expand(&["mv", "{}", "{..}"], "a.jpg"),
+            ["mv", "a.jpg", ".."]
+        );
+    }
+
+    #[test]
+    fn path_is_appended_without_placeholders() {
+        assert_eq!(expand(&["wc", "-l"], "a b.rs"), ["wc", "-l", "a b.rs"]);
+        // Unknown braces are plain text.
+        assert_eq!(expand(&["echo", "{x}"], "a"), ["echo", "{x}", "a"]);
+    }
+
+    #[test]
+    fn per_result_runs_once_per_path_and_reports_failures() {
+        let tmp = TempDir::new().unwrap();
+        let out = tmp.path().join("out");
+        let script = format!(
+            r#"printf '%s\n' "$1" >> '{}'; [ "$1" != bad ]"#,
+            out.display()
+        );
+        let argv: Vec<String> = ["sh", "-c", &script, "sh"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        let mut sink = PerResult::new(CommandTemplate::new(&argv).unwrap(), Launcher::Spawn);
+        let err = feed(&mut sink, &["a", "bad", "c"]).unwrap_err();
+        assert!(err.downcast_ref::<ExecFailed>().is_some());
+        assert_eq!(fs::read_to_string(&out).unwrap(), "a\nbad\nc\n");
+    }
+
+    fn strings(argv: &[&str]) -> Vec<String> {
+        argv.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn batch_repeats_the_placeholder_argument_per_path() {
+        let template = CommandTemplate::new_batch(&strings(&["echo", "-a", "{/}", "--"])).unwrap();
+        let argv = template.expand_batch(&[PathBuf::from("x/a.rs"), PathBuf::from("b.rs")]);
+        assert_eq!(argv, ["echo", "-a", "a.rs", "b.rs", "--"]);
+
+        let template = CommandTemplate::new_batch(&strings(&["wc", "-l"])).unwrap();
+        let argv = template.expand_batch(&[PathBuf::from("a"), PathBuf::from("b")]);
+        assert_eq!(argv, ["wc", "-l", "a", "b"]);
+    }
+
+    #[test]
+    fn batch_rejects_ambiguous_templates() {
+        assert!(CommandTemplate::new_batch(&strings(&["echo", "{}", "{/}"])).is_err());
+        assert!(CommandTemplate::new_batch(&strings(&["{}", "x"])).is_err());
+    }
+
+    #[test]
+    fn batch_runs_once_after_the_last_result_and_skips_empty_sets() {
+        let tmp = TempDir::new().unwrap();
+        let out = tmp.path().join("out");
+        let script = format!(r#"echo "$#" "$@" >> '{}'"#, out.display());
+        let template = CommandTemplate::new_batch(&strings(&["sh", "-c", &script, "sh"])).unwrap();
+
+        let mut sink = Batch::new(template.clone(), Launcher::Spawn);
+        feed(&mut sink, &[]).unwrap();
+        assert!(!out.exists());
+
+        let mut sink = Batch::new(template, Launcher::Spawn);
+        sink.accept(Path::new("a"), Path::new("a"), None).unwrap();
+        sink.accept(Path::new("b c"), Path::new("b c"), None)
+            .unwrap();
+        assert!(!out.exists());
+        sink.finish().unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "2 a b c\n");
+    }
+
+    /// Captures `--dry-run` output while the sink owns the writer.
+    #[derive(Clone, Default)]
+    struct SharedBuf(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn shell_quote_leaves_safe_words_alone() {
+        assert_eq!(shell_quote(OsStr::new("src/a-b_c.rs")), "src/a-b_c.rs");
+        assert_eq!(shell_quote(OsStr::new("a b")), "'a b'");
+        assert_eq!(shell_quote(OsStr::new("it's")), r"'it'\''s'");
+        assert_eq!(shell_quote(OsStr::new("")), "''");
+        assert_eq!(shell_quote(OsStr::new("$HOME;rm")), "'$HOME;rm'");
+    }
+
+    #[test]
+    fn exec_shell_runs_one_script_with_each_path_as_a_single_word() {
+        let tmp = TempDir::new().unwrap();
+        for shell in ["sh", "bash"] {
+            let log = tmp.path().join(format!("{shell}.log"));
+            let script = format!(
+                "for w in {{}}; do printf '<%s>' \"$w\"; done >> '{}'; echo >> '{}'",
+                log.display(),
+                log.display()
+            );
+            let template = CommandTemplate::new_shell(&strings(&[&script]), shell.into()).unwrap();
+            assert_eq!(
+                template.expand(Path::new("e1 e2"))[..2],
+                [OsString::from(shell), OsString::from("-c")]
+            );
+            let mut sink = PerResult::new(template, Launcher::Spawn);
+            feed(&mut sink, &["e1 e2", "it's $HOME"]).unwrap();
+            assert_eq!(
+                fs::read_to_string(&log).unwrap(),
+                "<e1 e2>\n<it's $HOME>\n",
+                "{shell}"
+            );
+        }
+
+        // Without a placeholder, the path is appended as the last word.
+        let template = CommandTemplate::new_shell(&strings(&["ls", "-l"]), "sh".into()).unwrap();
+        assert_eq!(
+            template.expand(Path::new("a b")),
+            strings(&["sh", "-c", "ls -l 'a b'"])
+                .into_iter()
+                .map(OsString::from)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn echo_announces_each_path_before_its_command() {
+        let tmp = TempDir::new().unwrap();
+        let log = tmp.path().join("log");
+        let echo = SharedBuf::default();
+        let argv = strings(&[
+            "sh",
+            "-c",
+            r#"echo "$0" >> "$1""#,
+            "{}",
+            log.to_str().unwrap(),
+        ]);
+        let mut sink = PerResult::new(CommandTemplate::new(&argv).unwrap(), Launcher::Spawn)
+            .with_jobs(1, false)
+            .with_echo_to(Box::new(echo.clone()));
+        feed(&mut sink, &["b.txt", "a b.txt", "c.txt"]).unwrap();
+
+        assert_eq!(echo.contents(), "b.txt\na b.txt\nc.txt\n");
+        assert_eq!(fs::read_to_string(&log).unwrap(), echo.contents());
+    }
+
+    #[test]
+    fn summary_counts_successes_and_failures() {
+        let argv = strings(&["sh", "-c", r#"case "$0" in bad*) exit 1; esac"#, "{}"]);
+        let paths = ["a", "bad1", "b", "bad2", "c"];
+        for jobs in [1, 3] {
+            let summary = SharedBuf::default();
+            let mut sink = PerResult::new(CommandTemplate::new(&argv).unwrap(), Launcher::Spawn)
+                .with_jobs(jobs, false)
+                .with_summary_to(Box::new(summary.clone()));
+            let err = feed(&mut sink, &paths).unwrap_err();
+            assert!(err.is::<ExecFailed>());
+            assert_eq!(
+                summary.contents(),
+                "sf: ran command on 5 files (3 ok, 2 failed)\n",
+                "jobs={jobs}"
+            );
+        }
+
+        let summary = SharedBuf::default();
+        let mut sink = PerResult::new(CommandTemplate::new(&argv).unwrap(), Launcher::Spawn)
+            .with_summary_to(Box::new(summary.clone()));
+        feed(&mut sink, &["a"]).unwrap();
+        assert_eq!(
+            summary.contents(),
+            "sf: ran command on 1 file (1 ok, 0 failed)\n"
+        );
+
+        let summary = SharedBuf::default();
+        let mut sink = PerResult::new(CommandTemplate::new(&argv).unwrap(), Launcher::Spawn)
+            .with_summary_to(Box::new(summary.clone()));
+        feed(&mut sink, &[]).unwrap();
+        assert_eq!(summary.contents(), "");
+
+        let batch = strings(&["sh", "-c", r#"[ "$#" -eq 2 ]"#, "sh", "{}"]);
+        for (paths, want) in [
+            (&["a", "b"][..], "sf: ran command once on 2 files (ok)\n"),
+            (
+                &["a", "b", "c"][..],
+                "sf: ran command once on 3 files (failed)\n",
+            ),
+            (&[][..], ""),
+        ] {
+            let summary = SharedBuf::default();
+            let mut sink = Batch::new(CommandTemplate::new_batch(&batch).unwrap(), Launcher::Spawn)
+                .with_summary_to(Box::new(summary.clone()));
+            let _ = feed(&mut sink, paths);
+            assert_eq!(summary.contents(), want);
+        }
+
+        let summary = SharedBuf::default();
+        let mut sink = BatchStdin::new(
+            strings(&["sh", "-c", "cat >/dev/null"]),
+            Delimiter::Nul,
+            Launcher::Spawn,
+        )
+        .with_summary_to(Box::new(summary.clone()));
+        feed(&mut sink, &["a", "b"]).unwrap();
+        assert_eq!(summary.contents(), "sf: ran command once on 2 files (ok)\n");
+
+        // Nothing runs under `--dry-run`, so there is nothing to summarize.
+        let sink = PerResult::new(
+            CommandTemplate::new(&argv).unwrap(),
+            Launcher::DryRun(Box::new(io::sink())),
+        )
+        .with_summary(true);
+        assert!(sink.summary.is_none());
+    }
+
+    #[test]
+    fn dry_run_prints_and_never_spawns_for_every_variant() {
+        let tmp = TempDir::new().unwrap();
+        let marker = tmp.path().join("ran");
+        let script = format!("touch '{}'", marker.display());
+        let paths = ["a b.txt", "it's.rs"];
+
+        let buf = SharedBuf::default();
+        let argv = strings(&["sh", "-c", &script, "sh", "{}"]);
+        let mut sink = PerResult::new(
+            CommandTemplate::new(&argv).unwrap(),
+            Launcher::DryRun(Box::new(buf.clone())),
+        );
+        feed(&mut sink, &paths).unwrap();
+        let quoted_script = shell_quote(OsStr::new(&script)).into_owned();
+        assert_eq!(
+            buf.contents(),
+            format!("sh -c {quoted_script} sh 'a b.txt'\nsh -c {quoted_script} sh 'it'\\''s.rs'\n")
+        );
+
+        let buf = SharedBuf::default();
+        let mut sink = Batch::new(
+            CommandTemplate::new_batch(&argv).unwrap(),
+            Launcher::DryRun(Box::new(buf.clone())),
+        );
+        feed(&mut sink, &paths).unwrap();
+        assert_eq!(
+            buf.contents(),
+            format!("sh -c {quoted_script} sh 'a b.txt' 'it'\\''s.rs'\n")
+        );
+
+        let buf = SharedBuf::default();
+        let mut sink = BatchStdin::new(
+            strings(&["sh", "-c", &script]),
+            Delimiter::Nul,
+            Launcher::DryRun(Box::new(buf.clone())),
+        );
+        feed(&mut sink, &paths).unwrap();
+        assert_eq!(buf.contents(), format!("sh -c {quoted_script}\n"));
+
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn parallel_output_follows_completion_or_input_order() {
+        // Each command waits for its path to exist, prints its name and creates the path
+        // of the one before it, so `c` finishes first and `a` last. That only gets anywhere
+        // if all three run at once; a command that waits too long fails rather than hangs.
+        let script = r#"
+            n=0
+            while [ ! -e "$1" ]; do
+                n=$((n + 1)); [ "$n" -lt 1000 ] || exit 1; sleep 0.01
+            done
+            echo "${1##*/}"
+            case "${1##*/}" in c) touch "${1%/*}/b" ;; b) touch "${1%/*}/a" ;; esac"#;
+        let argv = strings(&["sh", "-c", script, "sh", "{}"]);
+        let run = |ordered: bool| {
+            let tmp = TempDir::new().unwrap();
+            let paths = ["a", "b", "c"].map(|name| tmp.path().join(name));
+            fs::write(&paths[2], "").unwrap();
+            let out = SharedBuf::default();
+            let mut sink = PerResult::new(CommandTemplate::new(&argv).unwrap(), Launcher::Spawn)
+                .with_jobs_writing_to(3, ordered, Box::new(out.clone()), Box::new(io::sink()));
+            feed(&mut sink, &paths.each_ref().map(|p| p.to_str().unwrap())).unwrap();
+            out.contents()
+        };
+        // Completion order is up to the scheduler, but every command ran.
+        let mut unordered: Vec<String> = run(false).lines().map(str::to_owned).collect();
+        unordered.sort();
+        assert_eq!(unordered, ["a", "b", "c"]);
+        // Ordered output is in input order, although `a` finished last.
+        assert_eq!(run(true), "a\nb\nc\n");
+    }
+
+    #[test]
+    fn parallel_commands_keep_their_stderr_together_and_report_failures() {
+        let argv = strings(&[
+            "sh",
+            "-c",
+            r#"echo "out $1"; echo "err $1" >&2; [ "$1" != b ]"#,
+            "sh",
+        ]);
+        let (out, err) = (SharedBuf::default(), SharedBuf::default());
+        let mut sink = PerResult::new(CommandTemplate::new(&argv).unwrap(), Launcher::Spawn)
+            .with_jobs_writing_to(2, true, Box::new(out.clone()), Box::new(err.clone()));
+        let res = feed(&mut sink, &["a", "b", "c"]);
+        assert!(res.unwrap_err().is::<ExecFailed>());
+        assert_eq!(out.contents(), "out a\nout b\nout c\n");
+        assert_eq!(err.contents(), "err a\nerr b\nerr c\n");
+    }
+
+    #[test]
+    fn per_result_retries_until_the_command_succeeds() {
+        let tmp = TempDir::new().unwrap();
+        let attempts = tmp.path().join("attempts");
+        // Fails twice, then succeeds.
+        let script = format!(
+            r#"echo x >> '{0}'; [ "$(wc -l < '{0}')" -ge 3 ]"#,
+            attempts.display()
+        );
+        let argv = strings(&["sh", "-c", &script]);
+        let template = CommandTemplate::new(&argv).unwrap();
+
+        let mut sink = PerResult::new(template.clone(), Launcher::Spawn)
+            .with_retries(3, Duration::from_millis(1));
+        feed(&mut sink, &["a"]).unwrap();
+        assert_eq!(fs::read_to_string(&attempts).unwrap().lines().count(), 3);
+
+        fs::remove_file(&attempts).unwrap();
+        let mut sink = PerResult::new(template, Launcher::Spawn).with_retries(1, Duration::ZERO);
+        let err = feed(&mut sink, &["a"]).unwrap_err();
+        assert!(err.downcast_ref::<ExecFailed>().is_some());
+        assert_eq!(fs::read_to_string(&attempts).unwrap().lines().count(), 2);
+    }
+}
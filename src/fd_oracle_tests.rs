@@ -8,8 +8,9 @@ use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use tempfile::TempDir;
 
 use crate::{
-    filter::{Filter, FilterConfig},
-    output::OutputStyle,
+    criteria::{Criteria, CriteriaConfig, FileType},
+    filter::{DepthRange, Filter, FilterConfig, IgnoreSources},
+    output::{OutputStyle, PathMode},
     test_support,
 };
 
@@ -42,24 +43,35 @@ fn collect_sf_like(
             cwd: root.to_path_buf(),
             search_base: root.to_path_buf(),
             include_hidden,
-            ignore_enabled,
+            ignores: if ignore_enabled {
+                IgnoreSources::ALL
+            } else {
+                IgnoreSources::NONE
+            },
+            parent_ignores: true,
+            depth: DepthRange::ANY,
         },
         global_gitignore,
         global_fd_ignore,
     );
-    let out_style = OutputStyle::new(root.to_path_buf(), root.to_path_buf(), None);
+    collect_sf_like_with(root, &mut filter, &CriteriaConfig::default(), pattern)
+}
 
-    let mut out = Vec::new();
-    for abs in test_support::enumerate_paths(root) {
-        if !test_support::smartcase_basename_contains(&abs, pattern) {
-            continue;
-        }
-        if filter.should_include(&abs) {
-            out.push(out_style.render(&abs).to_string_lossy().to_string());
-        }
-    }
-    out.sort();
-    out
+/// `collect_sf_like` with `sf`'s metadata filters (`--type`, `--extension`, ...) too.
+fn collect_sf_like_with(
+    root: &Path,
+    filter: &mut Filter,
+    criteria: &CriteriaConfig,
+    pattern: &str,
+) -> Vec<String> {
+    let out_style = OutputStyle::new(
+        root.to_path_buf(),
+        root.to_path_buf(),
+        None,
+        PathMode::Relative,
+    );
+    let criteria = Criteria::new(criteria.clone());
+    test_support::collect_through_pipeline(root, filter, &criteria, &out_style, pattern)
 }
 
 fn find_fd_binary() -> Option<PathBuf> {
@@ -75,13 +87,12 @@ fn find_fd_binary() -> Option<PathBuf> {
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
         .output()
+        && out.status.success()
     {
-        if out.status.success() {
-            let s = String::from_utf8_lossy(&out.stdout);
-            let p = PathBuf::from(s.trim());
-            if p.is_file() {
-                return Some(p);
-            }
+        let s = String::from_utf8_lossy(&out.stdout);
+        let p = PathBuf::from(s.trim());
+        if p.is_file() {
+            return Some(p);
         }
     }
 
@@ -359,3 +370,55 @@ fn fd_oracle_global_gitignore_inside_repo() {
 
     assert_eq!(sf, fd);
 }
+
+#[test]
+fn fd_oracle_type_extension_and_depth() {
+    if !oracle_enabled() {
+        eprintln!("skipping (set SF_FD_ORACLE=1 to enable)");
+        return;
+    }
+    let Some(fd_bin) = fd_or_skip() else {
+        eprintln!("skipping (fd not found; set SF_FD_BIN=/path/to/fd or ensure fd is in PATH)");
+        return;
+    };
+
+    let (_tmp, root) = setup_fd_like_tree();
+    let env = tempfile::Builder::new()
+        .prefix("sf-fd-oracle-env")
+        .tempdir()
+        .unwrap();
+    let home = env.path().join("home");
+    let xdg = env.path().join("xdg");
+    fs::create_dir_all(&home).unwrap();
+    fs::create_dir_all(&xdg).unwrap();
+
+    let mut args = ["--type", "f", "--extension", "foo", "--max-depth", "3"]
+        .map(String::from)
+        .to_vec();
+    args.extend(fd_pattern_args("o"));
+    let args_ref = args.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    let fd = normalize_fd_output(&run_fd(&fd_bin, &root, &args_ref, &home, &xdg));
+
+    let mut filter = Filter::new_with_globals(
+        FilterConfig {
+            cwd: root.clone(),
+            search_base: root.clone(),
+            include_hidden: false,
+            ignores: IgnoreSources::ALL,
+            parent_ignores: true,
+            depth: DepthRange {
+                min: 0,
+                max: Some(3),
+            },
+        },
+        Gitignore::empty(),
+        None,
+    );
+    let criteria = CriteriaConfig {
+        file_types: vec![FileType::File],
+        extensions: vec!["foo".into()],
+        ..Default::default()
+    };
+    let sf = collect_sf_like_with(&root, &mut filter, &criteria, "o");
+    assert_eq!(sf, fd);
+}
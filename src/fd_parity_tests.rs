@@ -7,8 +7,10 @@ use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use tempfile::TempDir;
 
 use crate::{
-    filter::{Filter, FilterConfig},
-    output::{Delimiter, OutputStyle},
+    criteria::{Criteria, CriteriaConfig, FileType},
+    filter::{DepthRange, Filter, FilterConfig, IgnoreSources},
+    output::{Delimiter, OutputStyle, PathMode},
+    query::{self, QueryOptions},
     test_support,
 };
 
@@ -96,18 +98,8 @@ fn collect_matches(
     out_style: &OutputStyle,
     pattern_substr: &str,
 ) -> Vec<String> {
-    let mut out = Vec::new();
-    for abs_path in test_support::enumerate_paths(root) {
-        if !test_support::smartcase_basename_contains(&abs_path, pattern_substr) {
-            continue;
-        }
-        if filter.should_include(&abs_path) {
-            let rendered = out_style.render(&abs_path);
-            out.push(rendered.to_string_lossy().to_string());
-        }
-    }
-    out.sort();
-    out
+    let criteria = Criteria::new(CriteriaConfig::default());
+    test_support::collect_through_pipeline(root, filter, &criteria, out_style, pattern_substr)
 }
 
 fn make_filter(
@@ -122,7 +114,13 @@ fn make_filter(
             cwd: root.to_path_buf(),
             search_base: root.to_path_buf(),
             include_hidden,
-            ignore_enabled,
+            ignores: if ignore_enabled {
+                IgnoreSources::ALL
+            } else {
+                IgnoreSources::NONE
+            },
+            parent_ignores: true,
+            depth: DepthRange::ANY,
         },
         global_gitignore,
         global_fd_ignore,
@@ -130,7 +128,12 @@ fn make_filter(
 }
 
 fn make_out_style(root: &Path) -> OutputStyle {
-    OutputStyle::new(root.to_path_buf(), root.to_path_buf(), None)
+    OutputStyle::new(
+        root.to_path_buf(),
+        root.to_path_buf(),
+        None,
+        PathMode::Relative,
+    )
 }
 
 // Port/adapted from fd v10.3.0: `test_hidden`.
@@ -143,7 +146,8 @@ fn fd_hidden_adapted() {
     let out_style = make_out_style(root);
     let got = collect_matches(root, &mut f, &out_style, "foo");
 
-    // fd includes a trailing slash for directories; sf prints plain paths.
+    // fd includes a trailing slash for directories; sf prints plain paths unless
+    // `--trailing-slash` is given (see `fd_hidden_trailing_slash_adapted`).
     assert_eq!(
         got,
         vec![
@@ -161,6 +165,33 @@ fn fd_hidden_adapted() {
     );
 }
 
+// Port/adapted from fd v10.3.0: `test_hidden`, with fd's directory output.
+#[test]
+fn fd_hidden_trailing_slash_adapted() {
+    let tree = TestTree::new(DEFAULT_DIRS, DEFAULT_FILES);
+    let root = tree.root();
+
+    let mut f = make_filter(root, true, true, Gitignore::empty(), None);
+    let out_style = make_out_style(root).with_trailing_slash(true);
+    let got = collect_matches(root, &mut f, &out_style, "foo");
+
+    assert_eq!(
+        got,
+        vec![
+            ".hidden.foo",
+            "a.foo",
+            "one/b.foo",
+            "one/two/C.Foo2",
+            "one/two/c.foo",
+            "one/two/three/d.foo",
+            "one/two/three/directory_foo/",
+        ]
+        .into_iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>(),
+    );
+}
+
 // Port/adapted from fd v10.3.0: `test_no_ignore`.
 #[test]
 fn fd_no_ignore_adapted() {
@@ -246,6 +277,56 @@ fn fd_custom_ignore_precedence_adapted() {
     assert_eq!(got, vec!["inner/foo".to_string()]);
 }
 
+// fd applies `--exclude` as an override on top of the ignore files, so a gitignore
+// whitelist cannot re-include an explicitly excluded file.
+#[test]
+fn exclude_beats_gitignore_whitelist() {
+    let tree = TestTree::new(&["logs"], &["keep.log", "logs/app.log", "keep.txt"]);
+    let root = tree.root();
+    tree.write_file(".gitignore", "*.log\n!keep.log\n");
+    let out_style = make_out_style(root);
+
+    let mut f = make_filter(root, true, true, Gitignore::empty(), None);
+    let got = collect_matches(root, &mut f, &out_style, "keep");
+    assert_eq!(got, vec!["keep.log", "keep.txt"]);
+
+    for ignore_enabled in [true, false] {
+        let mut f = make_filter(root, true, ignore_enabled, Gitignore::empty(), None)
+            .with_excludes(&["*.log".into()])
+            .unwrap();
+        let got = collect_matches(root, &mut f, &out_style, "keep");
+        assert_eq!(got, vec!["keep.txt"], "ignore_enabled={ignore_enabled}");
+    }
+}
+
+// fd reads the repository's `.gitignore` files above the search path, but always
+// descends into the search path itself, even when one of them ignores it.
+#[test]
+fn gitignore_above_the_search_base_applies_inside_it() {
+    let tree = TestTree::new(
+        &["src/generated", "build"],
+        &[
+            "src/main.rs",
+            "src/app.log",
+            "src/generated/out.rs",
+            "build/lib.rs",
+        ],
+    );
+    tree.write_file(".gitignore", "*.log\ngenerated/\n");
+    let src = tree.root().join("src");
+    let out_style = make_out_style(&src);
+
+    let mut f = make_filter(&src, true, true, Gitignore::empty(), None);
+    let got = collect_matches(&src, &mut f, &out_style, "");
+    assert_eq!(got, vec!["main.rs"]);
+
+    tree.write_file(".gitignore", "build/\n");
+    let build = tree.root().join("build");
+    let mut f = make_filter(&build, true, true, Gitignore::empty(), None);
+    let got = collect_matches(&build, &mut f, &make_out_style(&build), "");
+    assert_eq!(got, vec!["lib.rs"]);
+}
+
 // Port/adapted from fd v10.3.0: `test_respect_ignore_files` (require git).
 #[test]
 fn fd_require_git_adapted() {
@@ -270,6 +351,292 @@ fn fd_require_git_adapted() {
     assert!(!got.contains(&"gitignored.foo".to_string()));
 }
 
+// Adapted from fd v10.3.0: `test_smart_case_glob_searches`. Under a temporary directory
+// globs go to Spotlight as a predicate, whose case folding the matcher has to correct.
+#[test]
+fn fd_smart_case_glob_adapted() {
+    let tree = TestTree::new(DEFAULT_DIRS, DEFAULT_FILES);
+    let root = tree.root();
+
+    let matches = |pattern: &str| {
+        let matcher = query::build_mdfind_plan(
+            &[root.to_path_buf()],
+            Some(pattern),
+            QueryOptions::default(),
+        )
+        .unwrap()
+        .rust_matcher
+        .unwrap();
+        let mut got: Vec<String> = test_support::enumerate_paths(root)
+            .into_iter()
+            .filter(|p| matcher.matches(p))
+            .map(|p| p.strip_prefix(root).unwrap().to_string_lossy().into_owned())
+            .collect();
+        got.sort();
+        got
+    };
+
+    assert_eq!(matches("*.Foo*"), ["one/two/C.Foo2"]);
+    assert_eq!(
+        matches("*.foo*"),
+        [
+            ".hidden.foo",
+            "a.foo",
+            "fdignored.foo",
+            "gitignored.foo",
+            "one/b.foo",
+            "one/two/C.Foo2",
+            "one/two/c.foo",
+            "one/two/three/d.foo",
+        ]
+    );
+}
+
+// Adapted from fd v10.3.0: `test_full_path` (substring instead of regex, relative to the
+// search root instead of absolute).
+#[test]
+fn fd_full_path_adapted() {
+    let tree = TestTree::new(DEFAULT_DIRS, DEFAULT_FILES);
+    let root = tree.root();
+
+    let mut f = make_filter(root, false, true, Gitignore::empty(), None);
+    let out_style = make_out_style(root);
+    let matcher = query::build_mdfind_plan(
+        &[root.to_path_buf()],
+        Some("two/"),
+        QueryOptions {
+            full_path: true,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+    .rust_matcher
+    .unwrap();
+
+    let mut got = Vec::new();
+    for abs_path in test_support::enumerate_paths(root) {
+        let expected = test_support::smartcase_relative_path_contains(&abs_path, root, "two/");
+        assert_eq!(
+            matcher.matches(&abs_path),
+            expected,
+            "{}",
+            abs_path.display()
+        );
+        if expected && f.should_include(&abs_path) {
+            got.push(out_style.render(&abs_path).to_string_lossy().to_string());
+        }
+    }
+    got.sort();
+
+    assert_eq!(
+        got,
+        vec![
+            "one/two/C.Foo2",
+            "one/two/c.foo",
+            "one/two/three",
+            "one/two/three/d.foo",
+            "one/two/three/directory_foo",
+        ]
+        .into_iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>()
+    );
+}
+
+// Port/adapted from fd v10.3.0: `test_max_depth`/`test_min_depth`, with hidden entries.
+#[test]
+fn fd_depth_counts_hidden_components_like_any_other() {
+    let tree = TestTree::new(&[], &["a/b/c.foo", ".a/b/c.foo", "a/.b/c.foo"]);
+    let root = tree.root();
+    let out_style = make_out_style(root);
+
+    let collect_at = |include_hidden: bool, min: usize, max: Option<usize>| {
+        let mut f = Filter::new_with_globals(
+            FilterConfig {
+                cwd: root.to_path_buf(),
+                search_base: root.to_path_buf(),
+                include_hidden,
+                ignores: IgnoreSources::ALL,
+                parent_ignores: true,
+                depth: DepthRange { min, max },
+            },
+            Gitignore::empty(),
+            None,
+        );
+        collect_matches(root, &mut f, &out_style, "c.foo")
+    };
+
+    // `.a/b/c.foo` is at depth 3, the same as `a/b/c.foo`.
+    assert_eq!(
+        collect_at(true, 3, Some(3)),
+        [".a/b/c.foo", "a/.b/c.foo", "a/b/c.foo"]
+    );
+    assert!(collect_at(true, 0, Some(2)).is_empty());
+    assert!(collect_at(true, 4, None).is_empty());
+
+    // Without `--hidden`, hidden paths are gone regardless of depth.
+    assert_eq!(collect_at(false, 3, Some(3)), ["a/b/c.foo"]);
+    assert_eq!(collect_at(false, 0, Some(2)), Vec::<String>::new());
+}
+
+// `-u` only turns off ignore files (and hidden filtering); `fd -u --max-depth 1 -t f`
+// still applies the depth and type.
+#[test]
+fn unrestricted_keeps_depth_and_type_filters() {
+    let tree = TestTree::new(DEFAULT_DIRS, DEFAULT_FILES);
+    let root = tree.root();
+    let mut f = Filter::new_with_globals(
+        FilterConfig {
+            depth: DepthRange {
+                min: 0,
+                max: Some(1),
+            },
+            ..make_filter(root, true, false, Gitignore::empty(), None)
+                .config()
+                .clone()
+        },
+        Gitignore::empty(),
+        None,
+    );
+    let criteria = Criteria::new(CriteriaConfig {
+        file_types: vec![FileType::File],
+        ..Default::default()
+    });
+    let got =
+        test_support::collect_through_pipeline(root, &mut f, &criteria, &make_out_style(root), "");
+
+    // Ignored and hidden top-level files are back, `one` is a directory, and everything
+    // under it is too deep.
+    assert_eq!(
+        got,
+        [
+            ".fdignore",
+            ".gitignore",
+            ".hidden.foo",
+            "a.foo",
+            "e1 e2",
+            "fdignored.foo",
+            "gitignored.foo",
+        ]
+    );
+}
+
+// Adapted from fd v10.3.0: `test_type`, `test_extension` and `test_max_depth` combined.
+#[test]
+fn fd_type_extension_and_depth_combined() {
+    let tree = TestTree::new(DEFAULT_DIRS, DEFAULT_FILES);
+    let root = tree.root();
+    let mut f = Filter::new_with_globals(
+        FilterConfig {
+            depth: DepthRange {
+                min: 0,
+                max: Some(3),
+            },
+            ..make_filter(root, false, true, Gitignore::empty(), None)
+                .config()
+                .clone()
+        },
+        Gitignore::empty(),
+        None,
+    );
+    let criteria = Criteria::new(CriteriaConfig {
+        file_types: vec![FileType::File],
+        extensions: vec!["foo".into()],
+        ..Default::default()
+    });
+    let got =
+        test_support::collect_through_pipeline(root, &mut f, &criteria, &make_out_style(root), "o");
+
+    // `C.Foo2` has the wrong extension, `directory_foo` is no file, `three/d.foo` is at
+    // depth 4, and the rest are hidden or ignored.
+    assert_eq!(got, ["a.foo", "one/b.foo", "one/two/c.foo"]);
+}
+
+// Living documentation of `--type`: kinds are OR-ed, `x` and `e` narrow whatever kinds
+// were selected, and symlinks are never followed.
+#[cfg(unix)]
+#[test]
+fn fd_type_union_matrix() {
+    use std::os::unix::fs::{PermissionsExt, symlink};
+
+    // `dir` only holds a hidden file: never printed, but it keeps `dir` from being empty.
+    let tree = TestTree::new(&["dir", "empty_dir"], &["file", "dir/.keep", "exe"]);
+    let root = tree.root();
+    fs::write(root.join("empty_file"), "").unwrap();
+    fs::set_permissions(root.join("exe"), fs::Permissions::from_mode(0o755)).unwrap();
+    symlink(root.join("file"), root.join("link_file")).unwrap();
+    symlink(root.join("dir"), root.join("link_dir")).unwrap();
+
+    use FileType::{Directory as D, Empty as E, Executable as X, File as F, Symlink as L};
+    let cases: &[(&[FileType], &[&str])] = &[
+        (&[F], &["empty_file", "exe", "file"]),
+        (&[D], &["dir", "empty_dir"]),
+        (&[L], &["link_dir", "link_file"]),
+        (&[X], &["exe"]),
+        (&[E], &["empty_dir", "empty_file"]),
+        (&[F, D], &["dir", "empty_dir", "empty_file", "exe", "file"]),
+        (
+            &[F, L],
+            &["empty_file", "exe", "file", "link_dir", "link_file"],
+        ),
+        (&[D, L], &["dir", "empty_dir", "link_dir", "link_file"]),
+        (
+            &[F, D, L],
+            &[
+                "dir",
+                "empty_dir",
+                "empty_file",
+                "exe",
+                "file",
+                "link_dir",
+                "link_file",
+            ],
+        ),
+        (&[F, E], &["empty_file"]),
+        (&[D, E], &["empty_dir"]),
+        // `e` without `f` or `d` implies both, as in fd; a symlink is never empty.
+        (&[L, E], &["empty_dir", "empty_file"]),
+        // Nothing here is an empty executable.
+        (&[X, E], &[]),
+        // `x` checks the entry's own mode without following links, and a symlink's mode
+        // always has the exec bits set, so every link passes, as in fd.
+        (&[X, L], &["exe", "link_dir", "link_file"]),
+    ];
+    for (types, want) in cases {
+        let mut f = make_filter(root, false, true, Gitignore::empty(), None);
+        let criteria = Criteria::new(CriteriaConfig {
+            file_types: types.to_vec(),
+            ..Default::default()
+        });
+        let got = test_support::collect_through_pipeline(
+            root,
+            &mut f,
+            &criteria,
+            &make_out_style(root),
+            "",
+        );
+        assert_eq!(got, *want, "--type {types:?}");
+    }
+}
+
+#[test]
+fn names_with_trailing_space_or_dot_are_found() {
+    let tree = TestTree::new(&[], &["report ", "report", "weird.", "weird"]);
+    let root = tree.root();
+
+    let mut f = make_filter(root, false, true, Gitignore::empty(), None);
+    let out_style = make_out_style(root);
+    assert_eq!(
+        collect_matches(root, &mut f, &out_style, "report "),
+        ["report "]
+    );
+    assert_eq!(
+        collect_matches(root, &mut f, &out_style, "weird."),
+        ["weird."]
+    );
+    assert_eq!(collect_matches(root, &mut f, &out_style, "*."), ["weird."]);
+}
+
 #[test]
 fn global_gitignore_only_applies_inside_real_repo() {
     let tree = TestTree::new(&[], &["foo", "bar"]);
@@ -1,11 +1,16 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, OnceLock},
 };
 
+use anyhow::{Context, Result};
 use ignore::gitignore::{Gitignore, GitignoreBuilder};
 
+use crate::stats::Rejection;
+
 #[cfg(unix)]
 use std::os::unix::ffi::OsStrExt;
 
@@ -17,8 +22,62 @@ pub struct FilterConfig {
     pub search_base: PathBuf,
     /// If false, any candidate with a hidden component under `search_base` is excluded.
     pub include_hidden: bool,
-    /// If false, ignore matching is completely disabled (but hidden filtering still applies).
-    pub ignore_enabled: bool,
+    /// Which ignore files are consulted. Hidden filtering applies regardless.
+    pub ignores: IgnoreSources,
+    /// Also read ignore files in directories above `search_base` (fd's default; off with
+    /// `--no-ignore-parent`).
+    ///
+    /// When off, a git repository whose root is above `search_base` is still detected, so
+    /// `.gitignore` files at or below the base keep working, but that repository's
+    /// `.git/info/exclude` is skipped along with its higher `.gitignore` files.
+    pub parent_ignores: bool,
+    /// Only accept results this many components below `search_base`.
+    pub depth: DepthRange,
+}
+
+/// fd's `--min-depth`/`--max-depth`. Entries directly in the search base are at depth 1.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DepthRange {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl DepthRange {
+    #[cfg(test)]
+    pub const ANY: Self = Self { min: 0, max: None };
+
+    fn contains(self, depth: usize) -> bool {
+        depth >= self.min && self.max.is_none_or(|max| depth <= max)
+    }
+}
+
+/// Ignore file families that can be switched off independently, like fd's `--no-ignore`
+/// (all of them) and `--no-ignore-vcs` (just git's).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IgnoreSources {
+    /// `.gitignore`, `.git/info/exclude` and the global gitignore.
+    pub git_ignore: bool,
+    /// `.ignore`.
+    pub dot_ignore: bool,
+    /// `.fdignore` and fd's global ignore file.
+    pub fd_ignore: bool,
+}
+
+impl IgnoreSources {
+    pub const ALL: Self = Self {
+        git_ignore: true,
+        dot_ignore: true,
+        fd_ignore: true,
+    };
+    pub const NONE: Self = Self {
+        git_ignore: false,
+        dot_ignore: false,
+        fd_ignore: false,
+    };
+
+    fn any(self) -> bool {
+        self.git_ignore || self.dot_ignore || self.fd_ignore
+    }
 }
 
 /// fd-like ignore/hidden filtering applied to a flat stream of Spotlight candidates.
@@ -26,13 +85,17 @@ pub struct FilterConfig {
 /// Key detail: `sf` does not walk the filesystem. To emulate fd's directory pruning
 /// semantics, we also evaluate the ignore/hidden status of ancestor directories under
 /// `search_base` and cache "walkability" decisions.
+///
+/// Clones start with a copy of the caches and share the global gitignore, so each thread
+/// of a parallel search can filter on its own without finding that file again.
+#[derive(Clone)]
 pub struct Filter {
     cfg: FilterConfig,
 
     // Directory -> whether we can "walk into" it (pruning emulation).
     dir_walkable_cache: HashMap<PathBuf, bool>,
 
-    // Directory -> nearest repo root (requires `.git/HEAD`), or None.
+    // Directory -> nearest repo root (see `git_dir`), or None.
     repo_root_cache: HashMap<PathBuf, Option<PathBuf>>,
 
     // Ignore file caches keyed by directory that contains the ignore file.
@@ -40,11 +103,26 @@ pub struct Filter {
     ignore_by_dir: HashMap<PathBuf, Option<Gitignore>>,
     gitignore_by_dir: HashMap<PathBuf, Option<Gitignore>>,
 
+    // `--ignore-name` files (e.g. `.rgignore`), in command-line order, with their own
+    // per-directory caches.
+    named_ignores: Vec<NamedIgnore>,
+
     // Repo-root keyed caches.
     info_exclude_by_repo: HashMap<PathBuf, Gitignore>,
 
-    global_gitignore: Gitignore,
+    // Loaded on first use (see `global_gitignore`): finding it may mean running `git`.
+    global_gitignore: Arc<OnceLock<Gitignore>>,
     global_fd_ignore: Option<Gitignore>,
+
+    // `--ignore-file` matchers, in command-line order. They apply to the whole search
+    // rather than per directory.
+    custom_ignores: Vec<Gitignore>,
+
+    // `--exclude` globs, rooted at `search_base`.
+    excludes: Gitignore,
+
+    // `--prune`: matched directories whose contents are skipped. `None` when off.
+    pruned_dirs: Option<HashSet<PathBuf>>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -60,21 +138,37 @@ impl IgnoreDecision {
 }
 
 impl Filter {
-    pub fn new(cfg: FilterConfig) -> Self {
-        let (global_gitignore, _err) = GitignoreBuilder::new(&cfg.cwd).build_global();
-        let global_fd_ignore = if cfg.ignore_enabled {
+    /// `ignore_files` are extra ignore files (`--ignore-file`); unlike the ones discovered
+    /// during the search, a missing or unreadable one is an error.
+    pub fn new(cfg: FilterConfig, ignore_files: &[PathBuf]) -> Result<Self> {
+        let global_fd_ignore = if cfg.ignores.fd_ignore {
             load_global_fd_ignore(&cfg.cwd)
         } else {
             None
         };
-
-        Self::new_with_globals(cfg, global_gitignore, global_fd_ignore)
+        let custom_ignores = ignore_files
+            .iter()
+            .map(|p| load_ignore_file(&cfg.cwd.join(p)))
+            .collect::<Result<_>>()?;
+
+        let mut filter = Self::build(cfg, None, global_fd_ignore);
+        filter.custom_ignores = custom_ignores;
+        Ok(filter)
     }
 
+    #[cfg(test)]
     pub(crate) fn new_with_globals(
         cfg: FilterConfig,
         global_gitignore: Gitignore,
         global_fd_ignore: Option<Gitignore>,
+    ) -> Self {
+        Self::build(cfg, Some(global_gitignore), global_fd_ignore)
+    }
+
+    fn build(
+        cfg: FilterConfig,
+        global_gitignore: Option<Gitignore>,
+        global_fd_ignore: Option<Gitignore>,
     ) -> Self {
         Self {
             cfg,
@@ -83,32 +177,140 @@ impl Filter {
             fdignore_by_dir: HashMap::new(),
             ignore_by_dir: HashMap::new(),
             gitignore_by_dir: HashMap::new(),
+            named_ignores: Vec::new(),
             info_exclude_by_repo: HashMap::new(),
-            global_gitignore,
+            global_gitignore: Arc::new(global_gitignore.map_or_else(OnceLock::new, OnceLock::from)),
             global_fd_ignore,
+            custom_ignores: Vec::new(),
+            excludes: Gitignore::empty(),
+            pruned_dirs: None,
         }
     }
 
+    /// Also read ignore files with these names (`--ignore-name .rgignore`) wherever
+    /// `.ignore` files are read. They rank just below `.fdignore`, later names first.
+    pub fn with_ignore_names(mut self, names: &[String]) -> Self {
+        self.named_ignores = names
+            .iter()
+            .map(|name| NamedIgnore {
+                name: name.clone(),
+                by_dir: HashMap::new(),
+            })
+            .collect();
+        self
+    }
+
+    /// fd's `--prune`: once a directory is reported (see `prune`), reject everything below
+    /// it. Only sound if every directory is checked before its contents.
+    pub fn with_prune(mut self, on: bool) -> Self {
+        self.pruned_dirs = on.then(HashSet::new);
+        self
+    }
+
+    pub fn prunes(&self) -> bool {
+        self.pruned_dirs.is_some()
+    }
+
+    /// Record that `dir` was reported, so its contents are rejected from now on.
+    pub fn prune(&mut self, dir: &Path) {
+        if let Some(pruned) = self.pruned_dirs.as_mut() {
+            pruned.insert(dir.to_path_buf());
+        }
+    }
+
+    /// Reject anything matching one of these gitignore-style globs (`--exclude`), whatever
+    /// the ignore files say. A matching directory prunes everything below it.
+    pub fn with_excludes(mut self, patterns: &[String]) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(&self.cfg.search_base);
+        for pattern in patterns {
+            builder
+                .add_line(None, pattern)
+                .with_context(|| format!("invalid exclude pattern: {pattern}"))?;
+        }
+        self.excludes = builder.build().context("invalid exclude patterns")?;
+        Ok(self)
+    }
+
+    pub fn config(&self) -> &FilterConfig {
+        &self.cfg
+    }
+
+    #[cfg(test)]
     pub fn should_include(&mut self, path: &Path) -> bool {
+        self.check(path).is_ok()
+    }
+
+    /// Like `should_include`, but reports why a candidate was rejected.
+    #[cfg(test)]
+    pub fn check(&mut self, path: &Path) -> Result<(), Rejection> {
         // Match fd defaults: do not follow symlinks when determining whether something is a dir.
         let is_dir = fs::symlink_metadata(path)
             .map(|m| m.is_dir())
             .unwrap_or(false);
+        self.check_entry(path, is_dir)
+    }
 
+    /// `check` for callers that already know whether `path` is a directory (not following
+    /// symlinks).
+    #[cfg(test)]
+    pub fn check_entry(&mut self, path: &Path, is_dir: bool) -> Result<(), Rejection> {
+        self.check_location(path)?;
+        self.check_located_entry(path, is_dir)
+    }
+
+    /// The part of `check_entry` that only looks at where `path` is, so it can run before
+    /// the candidate is `lstat`ed: anything below a pruned, hidden, ignored or excluded
+    /// directory fails here, decided once per directory by the walkability cache.
+    pub fn check_location(&mut self, path: &Path) -> Result<(), Rejection> {
+        if let Some(pruned) = &self.pruned_dirs
+            && path
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| dir.starts_with(&self.cfg.search_base))
+                .any(|dir| pruned.contains(dir))
+        {
+            return Err(Rejection::Pruned);
+        }
+
+        // Every component counts, hidden or not, just like fd's walk depth.
+        if let Ok(rel) = path.strip_prefix(&self.cfg.search_base)
+            && !self.cfg.depth.contains(rel.components().count())
+        {
+            return Err(Rejection::Depth);
+        }
+
+        // This covers every component under the base, so past this point an unwalkable
+        // ancestor can only be an ignored (or excluded) one.
         if !self.cfg.include_hidden && is_hidden_under_base(path, &self.cfg.search_base) {
-            return false;
+            return Err(Rejection::Hidden);
         }
 
-        if !self.is_walkable_to(path, is_dir) {
-            return false;
+        if let Some(parent) = path.parent()
+            && !self.is_walkable_to(parent, true)
+        {
+            return Err(Rejection::Ignored);
         }
+        Ok(())
+    }
 
-        if !self.cfg.ignore_enabled {
-            return true;
+    /// The rest of `check_entry`, for a `path` that passed `check_location`.
+    pub fn check_located_entry(&mut self, path: &Path, is_dir: bool) -> Result<(), Rejection> {
+        if is_dir && !self.is_walkable_to(path, true) {
+            return Err(Rejection::Ignored);
+        }
+
+        // Only ignore matching is left. `--no-ignore`/`-u` skips it, but the structural
+        // checks above (and the type, size and time criteria after this) still apply.
+        if !self.ignores_active() {
+            return Ok(());
         }
 
         let parent = path.parent().unwrap_or(path);
-        self.is_entry_included(path, is_dir, parent)
+        if self.is_entry_included(path, is_dir, parent) {
+            Ok(())
+        } else {
+            Err(Rejection::Ignored)
+        }
     }
 
     fn is_walkable_to(&mut self, path: &Path, is_dir: bool) -> bool {
@@ -129,10 +331,15 @@ impl Filter {
         // a cached decision, then fill in the missing suffix.
         //
         // Invariant: if a directory is cached as walkable, then all of its ancestors
-        // under `search_base` were previously validated as walkable too.
+        // below `search_base` were previously validated as walkable too.
         let mut missing = Vec::new();
         let mut cur = container;
         loop {
+            // Like fd, always descend into the search base itself, even if an ignore
+            // file above it matches it.
+            if cur == self.cfg.search_base {
+                break;
+            }
             if let Some(&ok) = self.dir_walkable_cache.get(cur) {
                 if !ok {
                     return false;
@@ -141,9 +348,6 @@ impl Filter {
             }
             missing.push(cur.to_path_buf());
 
-            if cur == self.cfg.search_base {
-                break;
-            }
             let Some(parent) = cur.parent() else { break };
             cur = parent;
         }
@@ -167,7 +371,7 @@ impl Filter {
         if !self.cfg.include_hidden && is_hidden_under_base(dir, &self.cfg.search_base) {
             return false;
         }
-        if !self.cfg.ignore_enabled {
+        if !self.ignores_active() {
             return true;
         }
         let parent = dir.parent().unwrap_or(dir);
@@ -175,17 +379,41 @@ impl Filter {
     }
 
     fn is_entry_included(&mut self, path: &Path, is_dir: bool, parent_dir: &Path) -> bool {
-        // Precedence: .fdignore > .ignore > git ignores (repo only) > global fd ignore.
-        if let Some(dec) = self.match_fdignore(path, is_dir, parent_dir) {
+        // `--exclude` is explicit user intent, so it is final: no ignore-file whitelist
+        // (`!keep.log`) can bring an excluded path back.
+        if self.excludes.matched(path, is_dir).is_ignore() {
+            return false;
+        }
+        // Precedence: .fdignore > --ignore-name files > --ignore-file > .ignore > git
+        // ignores (repo only) > global fd ignore. Disabled sources are skipped, so the next
+        // one in line decides.
+        let sources = self.cfg.ignores;
+        if sources.fd_ignore
+            && let Some(dec) = self.match_fdignore(path, is_dir, parent_dir)
+        {
             return dec.include();
         }
-        if let Some(dec) = self.match_dot_ignore(path, is_dir, parent_dir) {
+        if sources.dot_ignore
+            && let Some(dec) = self.match_named_ignores(path, is_dir, parent_dir)
+        {
             return dec.include();
         }
-        if let Some(dec) = self.match_git_ignores(path, is_dir, parent_dir) {
+        if let Some(dec) = self.match_custom_ignores(path, is_dir) {
             return dec.include();
         }
-        if let Some(dec) = self.match_global_fd_ignore(path, is_dir) {
+        if sources.dot_ignore
+            && let Some(dec) = self.match_dot_ignore(path, is_dir, parent_dir)
+        {
+            return dec.include();
+        }
+        if sources.git_ignore
+            && let Some(dec) = self.match_git_ignores(path, is_dir, parent_dir)
+        {
+            return dec.include();
+        }
+        if sources.fd_ignore
+            && let Some(dec) = self.match_global_fd_ignore(path, is_dir)
+        {
             return dec.include();
         }
         true
@@ -209,6 +437,18 @@ impl Filter {
         self.match_from_ancestors(path, is_dir, start, IgnoreKind::DotIgnore)
     }
 
+    /// Each name is looked up on its own, closest directory first; later names win.
+    fn match_named_ignores(
+        &mut self,
+        path: &Path,
+        is_dir: bool,
+        start: &Path,
+    ) -> Option<IgnoreDecision> {
+        (0..self.named_ignores.len())
+            .rev()
+            .find_map(|i| self.match_from_ancestors(path, is_dir, start, IgnoreKind::Named(i)))
+    }
+
     fn match_git_ignores(
         &mut self,
         path: &Path,
@@ -220,6 +460,9 @@ impl Filter {
         // Closest `.gitignore` wins (deepest directory has highest precedence).
         let mut cur = parent_dir;
         loop {
+            if !self.reads_ignores_in(cur) {
+                break;
+            }
             if let Some(gi) = self.gitignore_in_dir(cur)
                 && let Some(dec) = match_to_decision(gi.matched(path, is_dir))
             {
@@ -232,18 +475,28 @@ impl Filter {
             cur = p;
         }
 
-        let info = self.info_exclude_for_repo(&repo_root);
-        if let Some(dec) = match_to_decision(info.matched(path, is_dir)) {
-            return Some(dec);
+        if self.reads_ignores_in(&repo_root) {
+            let info = self.info_exclude_for_repo(&repo_root);
+            if let Some(dec) = match_to_decision(info.matched(path, is_dir)) {
+                return Some(dec);
+            }
         }
 
-        if let Some(dec) = match_to_decision(self.global_gitignore.matched(path, is_dir)) {
+        if let Some(dec) = match_to_decision(self.global_gitignore().matched(path, is_dir)) {
             return Some(dec);
         }
 
         None
     }
 
+    /// Later `--ignore-file`s take precedence over earlier ones.
+    fn match_custom_ignores(&self, path: &Path, is_dir: bool) -> Option<IgnoreDecision> {
+        self.custom_ignores
+            .iter()
+            .rev()
+            .find_map(|gi| match_to_decision(gi.matched(path, is_dir)))
+    }
+
     fn match_global_fd_ignore(&mut self, path: &Path, is_dir: bool) -> Option<IgnoreDecision> {
         let gi = self.global_fd_ignore.as_ref()?;
         match_to_decision(gi.matched(path, is_dir))
@@ -256,12 +509,19 @@ impl Filter {
         start: &Path,
         kind: IgnoreKind,
     ) -> Option<IgnoreDecision> {
-        // fd default behavior reads ignore files in parent directories too. We don't implement
-        // `--no-ignore-parent`, so this always walks to the filesystem root.
+        // fd default behavior reads ignore files in parent directories too, all the way to the
+        // filesystem root; `--no-ignore-parent` stops at the search base.
         for cur in std::iter::successors(Some(start), |p| p.parent()) {
+            if !self.reads_ignores_in(cur) {
+                break;
+            }
             let gi = match kind {
                 IgnoreKind::FdIgnore => self.fdignore_in_dir(cur),
                 IgnoreKind::DotIgnore => self.ignore_in_dir(cur),
+                IgnoreKind::Named(i) => {
+                    let NamedIgnore { name, by_dir } = &mut self.named_ignores[i];
+                    get_or_build_ignore_file(by_dir, cur, name)
+                }
             };
             if let Some(gi) = gi
                 && let Some(dec) = match_to_decision(gi.matched(path, is_dir))
@@ -272,6 +532,15 @@ impl Filter {
         None
     }
 
+    /// `--ignore-file`s and `--exclude`s apply even with `--no-ignore`, like fd.
+    fn ignores_active(&self) -> bool {
+        self.cfg.ignores.any() || !self.custom_ignores.is_empty() || !self.excludes.is_empty()
+    }
+
+    fn reads_ignores_in(&self, dir: &Path) -> bool {
+        self.cfg.parent_ignores || dir.starts_with(&self.cfg.search_base)
+    }
+
     fn fdignore_in_dir(&mut self, dir: &Path) -> Option<&Gitignore> {
         get_or_build_ignore_file(&mut self.fdignore_by_dir, dir, ".fdignore")
     }
@@ -284,12 +553,28 @@ impl Filter {
         get_or_build_ignore_file(&mut self.gitignore_by_dir, dir, ".gitignore")
     }
 
+    fn global_gitignore(&self) -> &Gitignore {
+        let cwd = &self.cfg.cwd;
+        self.global_gitignore.get_or_init(|| {
+            let mut git = Command::new("git");
+            git.current_dir(cwd);
+            load_global_gitignore(cwd, git)
+        })
+    }
+
     fn info_exclude_for_repo(&mut self, repo_root: &Path) -> &Gitignore {
         self.info_exclude_by_repo
             .entry(repo_root.to_path_buf())
             .or_insert_with(|| build_info_exclude_matcher(repo_root))
     }
 
+    /// The root of the git repository containing `path` (a directory with a `.git`
+    /// directory, or a `.git` file pointing at one, holding `HEAD`),
+    /// cached like the lookups for ignore files.
+    pub fn repo_root_of(&mut self, path: &Path) -> Option<PathBuf> {
+        self.repo_root_for_dir(path.parent()?)
+    }
+
     fn repo_root_for_dir(&mut self, dir: &Path) -> Option<PathBuf> {
         let mut cur = dir.to_path_buf();
         let mut visited = Vec::new();
@@ -305,7 +590,7 @@ impl Filter {
 
             visited.push(cur.clone());
 
-            if cur.join(".git").join("HEAD").is_file() {
+            if git_dir(&cur).is_some() {
                 let root = Some(cur.clone());
                 for v in visited {
                     self.repo_root_cache.insert(v, root.clone());
@@ -328,6 +613,24 @@ impl Filter {
 enum IgnoreKind {
     FdIgnore,
     DotIgnore,
+    /// Index into `Filter::named_ignores`.
+    Named(usize),
+}
+
+#[derive(Clone)]
+struct NamedIgnore {
+    name: String,
+    by_dir: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+/// An `--ignore-name`: a plain file name, looked up in each directory.
+pub fn parse_ignore_name(s: &str) -> Result<String, String> {
+    if s.is_empty() || s == "." || s == ".." || s.contains('/') {
+        return Err(format!(
+            "invalid ignore file name '{s}' (expected a file name like .rgignore)"
+        ));
+    }
+    Ok(s.to_owned())
 }
 
 fn get_or_build_ignore_file<'a>(
@@ -349,8 +652,48 @@ fn get_or_build_ignore_file<'a>(
     cache.get(dir).and_then(|o| o.as_ref())
 }
 
+/// Patterns are relative to the file's directory, as for any other ignore file.
+fn load_ignore_file(path: &Path) -> Result<Gitignore> {
+    let root = path.parent().unwrap_or(Path::new("/"));
+    let mut builder = GitignoreBuilder::new(root);
+    if let Some(err) = builder.add(path) {
+        return Err(anyhow::Error::new(err))
+            .with_context(|| format!("failed to read ignore file {}", path.display()));
+    }
+    builder
+        .build()
+        .with_context(|| format!("invalid ignore file {}", path.display()))
+}
+
+/// The git directory of a repository rooted at `root`: `.git` itself, or the directory
+/// a `.git` file points to with `gitdir: <path>` (linked worktrees and submodules).
+/// None unless it contains a `HEAD` file.
+fn git_dir(root: &Path) -> Option<PathBuf> {
+    let dot_git = root.join(".git");
+    let dir = if dot_git.is_dir() {
+        dot_git
+    } else {
+        let contents = fs::read_to_string(&dot_git).ok()?;
+        let target = contents.lines().next()?.strip_prefix("gitdir:")?.trim();
+        root.join(target)
+    };
+    dir.join("HEAD").is_file().then_some(dir)
+}
+
+/// Where a git directory keeps the files shared by all worktrees (`info/exclude` among
+/// them): the directory named in its `commondir` file, or the git directory itself.
+fn common_dir(git_dir: &Path) -> PathBuf {
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(contents) if !contents.trim().is_empty() => git_dir.join(contents.trim()),
+        _ => git_dir.to_path_buf(),
+    }
+}
+
 fn build_info_exclude_matcher(repo_root: &Path) -> Gitignore {
-    let exclude = repo_root.join(".git").join("info").join("exclude");
+    let Some(git_dir) = git_dir(repo_root) else {
+        return Gitignore::empty();
+    };
+    let exclude = common_dir(&git_dir).join("info").join("exclude");
     if !exclude.is_file() {
         return Gitignore::empty();
     }
@@ -400,6 +743,56 @@ fn is_hidden_component(comp: &std::ffi::OsStr) -> bool {
     }
 }
 
+/// The global gitignore: git's effective `core.excludesFile` (which may come from
+/// `GIT_CONFIG_GLOBAL`, `/etc/gitconfig` or an included file, and may contain spaces or
+/// `~`), or, without a usable `git`, whatever the `ignore` crate finds on its own. A
+/// configured file that doesn't exist means no global gitignore, as in git.
+fn load_global_gitignore(cwd: &Path, git: Command) -> Gitignore {
+    let builder = GitignoreBuilder::new(cwd);
+    let Some(path) = git_excludes_file(git) else {
+        return builder.build_global().0;
+    };
+    let path = cwd.join(path);
+    if !path.is_file() {
+        return Gitignore::empty();
+    }
+    let mut builder = builder;
+    let _ = builder.add(&path);
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+/// `git config --get --path core.excludesFile`, or `None` if it's unset or git can't run.
+fn git_excludes_file(mut git: Command) -> Option<PathBuf> {
+    let output = git
+        .args(["config", "--get", "--path", "core.excludesFile"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let mut value = output.stdout;
+    if value.last() == Some(&b'\n') {
+        value.pop();
+    }
+    if value.is_empty() {
+        return None;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+
+        Some(PathBuf::from(std::ffi::OsString::from_vec(value)))
+    }
+
+    #[cfg(not(unix))]
+    {
+        String::from_utf8(value).ok().map(PathBuf::from)
+    }
+}
+
 fn load_global_fd_ignore(cwd: &Path) -> Option<Gitignore> {
     let p = global_fd_ignore_path()?;
     if !p.is_file() {
@@ -445,7 +838,13 @@ mod tests {
                 cwd: root.to_path_buf(),
                 search_base: root.to_path_buf(),
                 include_hidden,
-                ignore_enabled,
+                ignores: if ignore_enabled {
+                    IgnoreSources::ALL
+                } else {
+                    IgnoreSources::NONE
+                },
+                parent_ignores: true,
+                depth: DepthRange::ANY,
             },
             Gitignore::empty(),
             None,
@@ -470,13 +869,172 @@ mod tests {
                 cwd: root.to_path_buf(),
                 search_base: root.to_path_buf(),
                 include_hidden,
-                ignore_enabled,
+                ignores: if ignore_enabled {
+                    IgnoreSources::ALL
+                } else {
+                    IgnoreSources::NONE
+                },
+                parent_ignores: true,
+                depth: DepthRange::ANY,
             },
             Gitignore::empty(),
             global_fd_ignore,
         )
     }
 
+    /// `git` confined to a config file and home directory of our own.
+    fn isolated_git(home: &Path, config: &str) -> Command {
+        let config_path = home.join("gitconfig");
+        fs::write(&config_path, config).unwrap();
+        let mut git = Command::new("git");
+        git.current_dir(home)
+            .env("HOME", home)
+            .env("XDG_CONFIG_HOME", home.join("xdg"))
+            .env("GIT_CONFIG_GLOBAL", &config_path)
+            .env("GIT_CONFIG_NOSYSTEM", "1");
+        git
+    }
+
+    #[test]
+    fn global_gitignore_follows_gits_effective_excludes_file() {
+        let tmp = TempDir::new().unwrap();
+        let home = tmp.path();
+        fs::create_dir_all(home.join("My Config")).unwrap();
+        fs::write(home.join("My Config/ignore"), "*.secret\n").unwrap();
+        let ignored = |gi: &Gitignore| gi.matched(home.join("a.secret"), false).is_ignore();
+
+        // `~` and spaces, which the `ignore` crate's own lookup can't handle.
+        let git = isolated_git(home, "[core]\n\texcludesFile = \"~/My Config/ignore\"\n");
+        assert!(ignored(&load_global_gitignore(home, git)));
+        assert_eq!(
+            git_excludes_file(isolated_git(home, "[core]\n\texcludesFile = ~/x\n")),
+            Some(home.join("x"))
+        );
+
+        // Configured but missing: no global gitignore at all.
+        let git = isolated_git(home, "[core]\n\texcludesFile = ~/missing\n");
+        assert!(!ignored(&load_global_gitignore(home, git)));
+
+        // Unset, or no git to ask: fall back to the `ignore` crate's lookup.
+        assert_eq!(git_excludes_file(isolated_git(home, "")), None);
+        assert_eq!(git_excludes_file(Command::new("sf-test-no-such-git")), None);
+    }
+
+    #[test]
+    fn excluded_directory_prunes_its_descendants() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("build/deep")).unwrap();
+        fs::create_dir_all(root.join("src/build")).unwrap();
+        fs::write(root.join("build/deep/out.o"), "x").unwrap();
+        fs::write(root.join("src/lib.rs"), "x").unwrap();
+        fs::write(root.join("build.rs"), "x").unwrap();
+
+        // Excludes apply even with every ignore source off.
+        let mut f = filter_for_test(root, false, false)
+            .with_excludes(&["/build/".into()])
+            .unwrap();
+        assert_eq!(f.check(&root.join("build")), Err(Rejection::Ignored));
+        assert_eq!(
+            f.check(&root.join("build/deep/out.o")),
+            Err(Rejection::Ignored)
+        );
+        // Anchored and directory-only, like a `.gitignore` line.
+        assert!(f.should_include(&root.join("src/build")));
+        assert!(f.should_include(&root.join("build.rs")));
+        assert!(f.should_include(&root.join("src/lib.rs")));
+    }
+
+    #[test]
+    fn excluded_file_pattern_rejects_matching_files_and_beats_whitelists() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::write(root.join(".ignore"), "!keep.log\n").unwrap();
+        fs::write(root.join("logs/app.log"), "x").unwrap();
+        fs::write(root.join("keep.log"), "x").unwrap();
+        fs::write(root.join("app.txt"), "x").unwrap();
+
+        let mut f = filter_for_test(root, false, true)
+            .with_excludes(&["*.log".into()])
+            .unwrap();
+        assert_eq!(f.check(&root.join("logs/app.log")), Err(Rejection::Ignored));
+        assert_eq!(f.check(&root.join("keep.log")), Err(Rejection::Ignored));
+        assert!(f.should_include(&root.join("logs")));
+        assert!(f.should_include(&root.join("app.txt")));
+    }
+
+    #[test]
+    fn submodule_with_a_gitdir_file_is_a_repo() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("super/.git/modules/sub/info")).unwrap();
+        fs::write(root.join("super/.git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(
+            root.join("super/.git/modules/sub/HEAD"),
+            "ref: refs/heads/main\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("super/.git/modules/sub/info/exclude"),
+            "excluded.txt\n",
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("super/sub")).unwrap();
+        fs::write(root.join("super/sub/.git"), "gitdir: ../.git/modules/sub\n").unwrap();
+        fs::write(root.join("super/sub/.gitignore"), "ignored.txt\n").unwrap();
+        for name in ["ignored.txt", "excluded.txt", "kept.txt"] {
+            fs::write(root.join("super/sub").join(name), "x").unwrap();
+        }
+
+        let mut f = filter_for_test(root, false, true);
+        let sub = root.join("super/sub");
+        assert_eq!(f.repo_root_of(&sub.join("kept.txt")), Some(sub.clone()));
+        assert!(!f.should_include(&sub.join("ignored.txt")));
+        assert!(!f.should_include(&sub.join("excluded.txt")));
+        assert!(f.should_include(&sub.join("kept.txt")));
+    }
+
+    #[test]
+    fn linked_worktree_reads_exclude_from_the_common_dir() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        let main_git = root.join("main/.git");
+        fs::create_dir_all(main_git.join("info")).unwrap();
+        fs::create_dir_all(main_git.join("worktrees/wt")).unwrap();
+        fs::write(main_git.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(main_git.join("info/exclude"), "excluded.txt\n").unwrap();
+        fs::write(main_git.join("worktrees/wt/HEAD"), "ref: refs/heads/wt\n").unwrap();
+        fs::write(main_git.join("worktrees/wt/commondir"), "../..\n").unwrap();
+        fs::create_dir_all(root.join("wt")).unwrap();
+        fs::write(
+            root.join("wt/.git"),
+            format!("gitdir: {}\n", main_git.join("worktrees/wt").display()),
+        )
+        .unwrap();
+        for name in ["excluded.txt", "kept.txt"] {
+            fs::write(root.join("wt").join(name), "x").unwrap();
+        }
+
+        let mut f = filter_for_test(root, false, true);
+        let wt = root.join("wt");
+        assert_eq!(f.repo_root_of(&wt.join("kept.txt")), Some(wt.clone()));
+        assert!(!f.should_include(&wt.join("excluded.txt")));
+        assert!(f.should_include(&wt.join("kept.txt")));
+    }
+
+    #[test]
+    fn git_file_without_a_valid_gitdir_is_not_a_repo() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("proj")).unwrap();
+        fs::write(root.join("proj/.git"), "gitdir: ../missing\n").unwrap();
+        fs::write(root.join("proj/file.txt"), "x").unwrap();
+
+        let mut f = filter_for_test(root, false, true);
+        assert_eq!(f.repo_root_of(&root.join("proj/file.txt")), None);
+    }
+
     #[test]
     fn hidden_files_excluded_by_default() {
         let tmp = TempDir::new().unwrap();
@@ -614,4 +1172,168 @@ mod tests {
         let mut f = filter_for_test_with_global_fd_ignore(root, true, false, "bar\n");
         assert!(f.should_include(&root.join("bar")));
     }
+
+    #[test]
+    fn no_ignore_vcs_keeps_dot_and_fd_ignores() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join(".git/info")).unwrap();
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(root.join(".git/info/exclude"), "excluded.txt\n").unwrap();
+        fs::write(root.join(".gitignore"), "git.txt\n").unwrap();
+        fs::write(root.join(".ignore"), "dot.txt\n").unwrap();
+        fs::write(root.join(".fdignore"), "fd.txt\n").unwrap();
+        for name in ["git.txt", "excluded.txt", "dot.txt", "fd.txt", "global.txt"] {
+            fs::write(root.join(name), "x").unwrap();
+        }
+
+        let global_gitignore = {
+            let file = root.join("global-gitignore");
+            fs::write(&file, "global.txt\n").unwrap();
+            let mut builder = GitignoreBuilder::new(root);
+            let _ = builder.add(&file);
+            builder.build().unwrap()
+        };
+        let mut f = Filter::new_with_globals(
+            FilterConfig {
+                cwd: root.to_path_buf(),
+                search_base: root.to_path_buf(),
+                include_hidden: false,
+                ignores: IgnoreSources {
+                    git_ignore: false,
+                    ..IgnoreSources::ALL
+                },
+                parent_ignores: true,
+                depth: DepthRange::ANY,
+            },
+            global_gitignore,
+            None,
+        );
+        assert!(f.should_include(&root.join("git.txt")));
+        assert!(f.should_include(&root.join("excluded.txt")));
+        assert!(f.should_include(&root.join("global.txt")));
+        assert!(!f.should_include(&root.join("dot.txt")));
+        assert!(!f.should_include(&root.join("fd.txt")));
+    }
+
+    #[test]
+    fn no_ignore_parent_stops_at_the_search_base() {
+        let tmp = TempDir::new().unwrap();
+        let repo = tmp.path();
+        fs::create_dir_all(repo.join(".git/info")).unwrap();
+        fs::write(repo.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(repo.join(".git/info/exclude"), "excluded.txt\n").unwrap();
+        fs::write(repo.join(".gitignore"), "from-parent.txt\n").unwrap();
+        fs::write(repo.join(".ignore"), "dot-parent.txt\n").unwrap();
+        let base = repo.join("sub");
+        fs::create_dir_all(&base).unwrap();
+        fs::write(base.join(".gitignore"), "from-base.txt\n").unwrap();
+        let names = [
+            "excluded.txt",
+            "from-parent.txt",
+            "dot-parent.txt",
+            "from-base.txt",
+        ];
+        for name in names {
+            fs::write(base.join(name), "x").unwrap();
+        }
+
+        let filter = |parent_ignores| {
+            Filter::new_with_globals(
+                FilterConfig {
+                    cwd: base.clone(),
+                    search_base: base.clone(),
+                    include_hidden: false,
+                    ignores: IgnoreSources::ALL,
+                    parent_ignores,
+                    depth: DepthRange::ANY,
+                },
+                Gitignore::empty(),
+                None,
+            )
+        };
+
+        let mut f = filter(true);
+        for name in names {
+            assert!(!f.should_include(&base.join(name)), "{name}");
+        }
+
+        // The repo above the base is still found, so its `.gitignore` under the base
+        // applies; everything above the base is skipped.
+        let mut f = filter(false);
+        assert!(f.should_include(&base.join("excluded.txt")));
+        assert!(f.should_include(&base.join("from-parent.txt")));
+        assert!(f.should_include(&base.join("dot-parent.txt")));
+        assert!(!f.should_include(&base.join("from-base.txt")));
+    }
+
+    #[test]
+    fn ignore_file_ranks_below_fdignore_and_survives_no_ignore() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        for name in ["a.log", "b.log", "src/c.tmp", "keep.txt"] {
+            fs::write(root.join(name), "x").unwrap();
+        }
+        fs::write(root.join(".fdignore"), "!b.log\n").unwrap();
+        fs::write(root.join(".ignore"), "!c.tmp\n").unwrap();
+        let extra = tmp.path().join("extra-ignore");
+        fs::write(&extra, "*.log\n*.tmp\n").unwrap();
+
+        let mut f = filter_for_test(root, false, true);
+        f.custom_ignores = vec![load_ignore_file(&extra).unwrap()];
+        assert!(!f.should_include(&root.join("a.log")));
+        // `.fdignore` outranks the ignore file, which outranks `.ignore`.
+        assert!(f.should_include(&root.join("b.log")));
+        assert!(!f.should_include(&root.join("src/c.tmp")));
+        assert!(f.should_include(&root.join("keep.txt")));
+
+        let mut f = filter_for_test(root, false, false);
+        f.custom_ignores = vec![load_ignore_file(&extra).unwrap()];
+        assert!(!f.should_include(&root.join("b.log")));
+        assert!(f.should_include(&root.join("keep.txt")));
+    }
+
+    #[test]
+    fn ignore_names_rank_below_fdignore_and_above_dot_ignore() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        for name in ["a.log", "b.log", "sub/c.log", "d.tmp"] {
+            fs::write(root.join(name), "x").unwrap();
+        }
+        fs::write(root.join(".fdignore"), "!b.log\n").unwrap();
+        fs::write(root.join(".rgignore"), "*.log\n*.tmp\n").unwrap();
+        fs::write(root.join("sub/.ignore"), "!c.log\n").unwrap();
+        fs::write(root.join(".myignore"), "!d.tmp\n").unwrap();
+
+        let names = [".rgignore".to_owned(), ".myignore".to_owned()];
+        let mut f = filter_for_test(root, false, true).with_ignore_names(&names);
+        assert!(!f.should_include(&root.join("a.log")));
+        assert!(f.should_include(&root.join("b.log")));
+        assert!(!f.should_include(&root.join("sub/c.log")));
+        // The later name wins over the earlier one.
+        assert!(f.should_include(&root.join("d.tmp")));
+
+        let mut f = filter_for_test(root, false, false).with_ignore_names(&names);
+        assert!(f.should_include(&root.join("a.log")));
+    }
+
+    #[test]
+    fn ignore_names_must_be_plain_file_names() {
+        assert_eq!(parse_ignore_name(".rgignore").unwrap(), ".rgignore");
+        for bad in ["", ".", "..", "a/.rgignore"] {
+            assert!(parse_ignore_name(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn missing_ignore_file_is_an_error() {
+        let tmp = TempDir::new().unwrap();
+        let err = load_ignore_file(&tmp.path().join("nope")).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("failed to read ignore file"),
+            "{err:#}"
+        );
+    }
 }
@@ -0,0 +1,103 @@
+/// A friendly name for a set of Spotlight content types (UTIs).
+///
+/// Each UTI is matched against `kMDItemContentTypeTree`, which also contains every type a
+/// file's type conforms to, so a broad UTI like `public.image` covers PNG, JPEG, HEIC, etc.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Kind {
+    pub name: &'static str,
+    pub utis: &'static [&'static str],
+}
+
+/// Aliases accepted by `--kind`. To add one, append an entry here (error messages pick it
+/// up automatically) and list it in the `--kind` help and the README table.
+pub const KINDS: &[Kind] = &[
+    Kind {
+        name: "document",
+        utis: &[
+            "com.adobe.pdf",
+            "com.microsoft.word.doc",
+            "org.openxmlformats.wordprocessingml.document",
+            "com.apple.iwork.pages.sffpages",
+            "com.apple.iwork.pages.pages",
+            "public.rtf",
+            "public.plain-text",
+        ],
+    },
+    Kind {
+        name: "archive",
+        utis: &[
+            "public.zip-archive",
+            "public.tar-archive",
+            "org.gnu.gnu-zip-archive",
+            "org.gnu.gnu-tar-archive",
+            "public.bzip2-archive",
+            "org.tukaani.xz-archive",
+            "org.7-zip.7-zip-archive",
+            "com.rarlab.rar-archive",
+        ],
+    },
+    Kind {
+        name: "image",
+        utis: &["public.image"],
+    },
+    Kind {
+        name: "audio",
+        utis: &["public.audio"],
+    },
+    Kind {
+        name: "video",
+        utis: &["public.movie"],
+    },
+    Kind {
+        name: "source",
+        utis: &["public.source-code"],
+    },
+    Kind {
+        name: "pdf",
+        utis: &["com.adobe.pdf"],
+    },
+];
+
+/// `value_parser` for `--kind`.
+pub fn parse_kind(name: &str) -> Result<&'static Kind, String> {
+    KINDS
+        .iter()
+        .find(|k| k.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let known: Vec<&str> = KINDS.iter().map(|k| k.name).collect();
+            format!(
+                "unknown kind '{name}' (expected one of: {})",
+                known.join(", ")
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_is_case_insensitive_and_lists_known_kinds() {
+        assert_eq!(parse_kind("Archive").unwrap().name, "archive");
+        let err = parse_kind("spreadsheet").unwrap_err();
+        assert!(err.contains("document, archive, image"), "{err}");
+    }
+
+    #[test]
+    fn pdf_is_narrower_than_document() {
+        let pdf = parse_kind("pdf").unwrap();
+        assert_eq!(pdf.utis, ["com.adobe.pdf"]);
+        assert!(parse_kind("document").unwrap().utis.contains(&pdf.utis[0]));
+    }
+
+    #[test]
+    fn alias_names_are_unique() {
+        for (i, k) in KINDS.iter().enumerate() {
+            assert!(
+                KINDS[i + 1..].iter().all(|o| o.name != k.name),
+                "{}",
+                k.name
+            );
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Spotlight-powered file finding with fd-like ignore semantics (macOS only).
+//!
+//! [`Search`] is the embeddable API: it asks Spotlight for candidates under one or more
+//! directories and filters them the way the `sf` binary does (hidden files, ignore files,
+//! depth). Only [`Search::run`] needs Spotlight; [`Search::run_on`] filters candidates from
+//! anywhere, on any platform.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! # #[cfg(target_os = "macos")]
+//! for path in spotlight_find::Search::new("*.rs").with_path("src").collect()? {
+//!     println!("{}", path.display());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#![forbid(unsafe_code)]
+// The Spotlight-driven paths are only reachable on macOS; keep other targets warning-free
+// so the unit tests can still be built and linted there.
+#![cfg_attr(not(target_os = "macos"), allow(dead_code))]
+
+mod cli;
+mod completions;
+mod criteria;
+mod exec;
+mod filter;
+mod kind;
+mod ls_colors;
+mod mdfind;
+mod output;
+mod owner;
+mod query;
+mod search;
+mod stats;
+mod template;
+mod volume;
+
+#[cfg(test)]
+mod fd_oracle_tests;
+#[cfg(test)]
+mod fd_parity_tests;
+#[cfg(test)]
+mod test_support;
+
+pub use search::Search;
+
+/// The `sf` binary: parse the command line, search, and exit with `sf`'s status. Not a
+/// stable API.
+#[doc(hidden)]
+pub fn main_entry() {
+    cli::main()
+}
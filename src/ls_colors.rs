@@ -0,0 +1,198 @@
+//! `LS_COLORS` styles for `--color`, like fd and GNU `ls`.
+
+use std::{ffi::OsStr, fs, path::Path};
+
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+/// Used when `LS_COLORS` isn't set: the types GNU `dircolors` colors by default.
+const DEFAULT: &str = "di=01;34:ln=01;36:so=01;35:pi=40;33:ex=01;32:bd=40;33;01:cd=40;33;01:\
+                       or=40;31;01";
+
+/// Parsed `LS_COLORS`. Each style is the SGR parameter list, e.g. `01;34`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LsColors {
+    normal: Option<String>,
+    file: Option<String>,
+    dir: Option<String>,
+    symlink: Option<String>,
+    /// `ln=target`: style symlinks like whatever they point to.
+    symlink_as_target: bool,
+    orphan: Option<String>,
+    pipe: Option<String>,
+    socket: Option<String>,
+    block_device: Option<String>,
+    char_device: Option<String>,
+    executable: Option<String>,
+    /// `*suffix=style`, lowercased: GNU `ls` and fd match them case-insensitively.
+    suffixes: Vec<(String, String)>,
+}
+
+impl LsColors {
+    /// `LS_COLORS` from the environment, or the default styles when it's unset.
+    pub fn from_env() -> Self {
+        match std::env::var("LS_COLORS") {
+            Ok(spec) => Self::parse(&spec),
+            Err(_) => Self::parse(DEFAULT),
+        }
+    }
+
+    /// Parse `key=style` entries separated by `:`. Unknown keys and malformed entries
+    /// are skipped, like `ls` does.
+    pub fn parse(spec: &str) -> Self {
+        let mut colors = Self::default();
+        for entry in spec.split(':') {
+            let Some((key, style)) = entry.split_once('=') else {
+                continue;
+            };
+            if let Some(suffix) = key.strip_prefix('*') {
+                colors
+                    .suffixes
+                    .push((suffix.to_lowercase(), style.to_string()));
+                continue;
+            }
+            if key == "ln" && style == "target" {
+                colors.symlink_as_target = true;
+                continue;
+            }
+            let slot = match key {
+                "no" => &mut colors.normal,
+                "fi" => &mut colors.file,
+                "di" => &mut colors.dir,
+                "ln" => &mut colors.symlink,
+                "or" => &mut colors.orphan,
+                "pi" => &mut colors.pipe,
+                "so" => &mut colors.socket,
+                "bd" => &mut colors.block_device,
+                "cd" => &mut colors.char_device,
+                "ex" => &mut colors.executable,
+                _ => continue,
+            };
+            *slot = non_empty(style);
+        }
+        // Later entries win, as in `ls`.
+        colors.suffixes.reverse();
+        colors
+    }
+
+    /// The style for the directories leading up to a result.
+    pub fn dir_style(&self) -> Option<&str> {
+        self.dir.as_deref()
+    }
+
+    /// The style for a result's own name. `meta` is its `lstat`, if that succeeded.
+    pub fn style_for(&self, path: &Path, meta: Option<&fs::Metadata>) -> Option<&str> {
+        let Some(meta) = meta else {
+            return self.normal.as_deref();
+        };
+        let ft = meta.file_type();
+        if ft.is_symlink() {
+            return match fs::metadata(path) {
+                Err(_) => self.orphan.as_deref().or(self.symlink.as_deref()),
+                Ok(target) if self.symlink_as_target => {
+                    // The suffix comes from the target's name too.
+                    let target_path = fs::read_link(path).unwrap_or_else(|_| path.to_path_buf());
+                    self.style_by_type(&target_path, &target)
+                }
+                Ok(_) => self.symlink.as_deref(),
+            };
+        }
+        self.style_by_type(path, meta)
+    }
+
+    fn style_by_type(&self, path: &Path, meta: &fs::Metadata) -> Option<&str> {
+        let ft = meta.file_type();
+        if ft.is_dir() {
+            return self.dir.as_deref();
+        }
+        #[cfg(unix)]
+        {
+            let special = if ft.is_fifo() {
+                Some(&self.pipe)
+            } else if ft.is_socket() {
+                Some(&self.socket)
+            } else if ft.is_block_device() {
+                Some(&self.block_device)
+            } else if ft.is_char_device() {
+                Some(&self.char_device)
+            } else {
+                None
+            };
+            if let Some(style) = special {
+                return style.as_deref();
+            }
+            if meta.permissions().mode() & 0o111 != 0 && self.executable.is_some() {
+                return self.executable.as_deref();
+            }
+        }
+        path.file_name()
+            .and_then(|name| self.suffix_style(name))
+            .or(self.file.as_deref())
+            .or(self.normal.as_deref())
+    }
+
+    fn suffix_style(&self, name: &OsStr) -> Option<&str> {
+        let name = name.to_string_lossy().to_lowercase();
+        self.suffixes
+            .iter()
+            .find(|(suffix, _)| name.ends_with(suffix.as_str()))
+            .and_then(|(_, style)| non_empty_ref(style))
+    }
+}
+
+fn non_empty(style: &str) -> Option<String> {
+    non_empty_ref(style).map(String::from)
+}
+
+fn non_empty_ref(style: &str) -> Option<&str> {
+    // `0` and `00` mean "no color", so skip the escape codes entirely.
+    (!style.chars().all(|c| c == '0')).then_some(style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn style<'c>(colors: &'c LsColors, path: &Path) -> Option<&'c str> {
+        colors.style_for(path, fs::symlink_metadata(path).ok().as_ref())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn styles_follow_type_then_suffix() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir(root.join("dir.rs")).unwrap();
+        fs::write(root.join("lib.RS"), "").unwrap();
+        fs::write(root.join("notes.txt"), "").unwrap();
+        fs::write(root.join("run.rs"), "").unwrap();
+        fs::set_permissions(root.join("run.rs"), fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink("lib.RS", root.join("link")).unwrap();
+        std::os::unix::fs::symlink("missing", root.join("dangling")).unwrap();
+
+        let colors = LsColors::parse("di=01;34:ln=36:or=31:ex=32:*.rs=33:*.txt=00:fi=0:bogus");
+        assert_eq!(colors.dir_style(), Some("01;34"));
+        // Type beats suffix: a directory named `*.rs` and an executable `.rs` file.
+        assert_eq!(style(&colors, &root.join("dir.rs")), Some("01;34"));
+        assert_eq!(style(&colors, &root.join("run.rs")), Some("32"));
+        assert_eq!(style(&colors, &root.join("lib.RS")), Some("33"));
+        // `00`/`0` mean no color.
+        assert_eq!(style(&colors, &root.join("notes.txt")), None);
+        assert_eq!(style(&colors, &root.join("link")), Some("36"));
+        assert_eq!(style(&colors, &root.join("dangling")), Some("31"));
+        assert_eq!(colors.style_for(&root.join("gone"), None), None);
+
+        let colors = LsColors::parse("ln=target:*.rs=33:*.rs=35");
+        assert_eq!(style(&colors, &root.join("link")), Some("35"));
+    }
+
+    #[test]
+    fn default_styles_cover_the_common_types() {
+        let colors = LsColors::parse(DEFAULT);
+        assert_eq!(colors.dir_style(), Some("01;34"));
+        assert_eq!(colors.executable.as_deref(), Some("01;32"));
+        assert_eq!(colors.symlink.as_deref(), Some("01;36"));
+        assert!(colors.suffixes.is_empty());
+    }
+}
@@ -1,12 +1,23 @@
 use std::{
+    collections::HashSet,
     ffi::OsString,
-    io::{self, BufRead, BufReader, Write},
+    fs,
+    io::{self, BufRead, BufReader},
+    path::{Component, Path, PathBuf},
     process::{Command, Stdio},
+    sync::{Mutex, mpsc},
+    thread,
 };
 
 use anyhow::{Context, Result};
+use ignore::WalkBuilder;
 
-use crate::{filter::Filter, output, query};
+use crate::{
+    criteria::Criteria,
+    filter::Filter,
+    output, query,
+    stats::{Rejection, Stats},
+};
 
 #[derive(Debug)]
 pub struct MdfindNotFound;
@@ -19,15 +30,227 @@ impl std::fmt::Display for MdfindNotFound {
 
 impl std::error::Error for MdfindNotFound {}
 
+/// Per-search-path state: ignore handling and output prefixes both depend on the base.
+pub struct Root<'a> {
+    pub filter: &'a mut Filter,
+    pub out_style: &'a output::OutputStyle,
+}
+
+/// How much of the candidate stream to take, and how many threads to filter it on.
+#[derive(Clone, Copy, Debug)]
+pub struct RunOptions {
+    /// Stop after this many results.
+    pub limit: Option<u64>,
+    /// Candidates are filtered on this many threads. With more than one, results arrive in
+    /// completion order rather than candidate order.
+    pub threads: usize,
+    /// Drop results that are the same file as an earlier one (`--unique`).
+    pub unique: bool,
+    /// Print notes to stderr, such as falling back to a walk (off with `--no-warnings`).
+    pub warnings: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            limit: None,
+            threads: 1,
+            unique: false,
+            warnings: true,
+        }
+    }
+}
+
+impl RunOptions {
+    pub fn with_limit(mut self, limit: Option<u64>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn with_unique(mut self, on: bool) -> Self {
+        self.unique = on;
+        self
+    }
+
+    pub fn with_warnings(mut self, on: bool) -> Self {
+        self.warnings = on;
+        self
+    }
+}
+
+/// What makes two results the same file for `--unique`.
+#[derive(Debug, Eq, Hash, PartialEq)]
+enum FileId {
+    /// Device and inode from the candidate's `lstat`: catches hard links and names that
+    /// differ only in case on a case-insensitive volume.
+    Inode(u64, u64),
+    /// The canonical path, when there is no `lstat` to go by.
+    Path(PathBuf),
+}
+
+impl FileId {
+    fn of(path: &Path, meta: Option<&fs::Metadata>) -> Self {
+        #[cfg(unix)]
+        if let Some(meta) = meta {
+            use std::os::unix::fs::MetadataExt;
+            return FileId::Inode(meta.dev(), meta.ino());
+        }
+        #[cfg(not(unix))]
+        let _ = meta;
+        FileId::Path(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+    }
+}
+
+/// The files accepted so far under `--unique`; always empty without it. Only identities
+/// are kept, so results still stream, at a few dozen bytes per distinct result.
+struct Seen(Option<HashSet<FileId>>);
+
+impl Seen {
+    fn new(opts: RunOptions) -> Self {
+        Seen(opts.unique.then(HashSet::new))
+    }
+
+    /// Whether an earlier result was the same file, remembering this one if not.
+    fn is_repeat(&mut self, path: &Path, meta: Option<&fs::Metadata>) -> bool {
+        self.0
+            .as_mut()
+            .is_some_and(|seen| !seen.insert(FileId::of(path, meta)))
+    }
+}
+
+/// The root whose search base is the longest prefix of `path`. Candidates outside every
+/// base fall back to the first root, whose filter rejects them like any other stray path.
+fn root_for<'r, 'a>(roots: &'r mut [Root<'a>], path: &Path) -> &'r mut Root<'a> {
+    let idx = root_index(roots.iter().map(|r| &*r.filter), path);
+    &mut roots[idx]
+}
+
+fn root_index<'f>(filters: impl Iterator<Item = &'f Filter>, path: &Path) -> usize {
+    filters
+        .enumerate()
+        .filter(|(_, f)| path.starts_with(&f.config().search_base))
+        .max_by_key(|(_, f)| f.config().search_base.components().count())
+        .map_or(0, |(i, _)| i)
+}
+
+/// Run `mdfind` and feed every result that survives the pipeline to `sink`.
+///
+/// With a `limit`, stops after that many results and kills `mdfind` instead of waiting for
+/// the rest of the index scan. With a `walk_plan` (`--walk`), a query that returns no
+/// candidates at all is retried by walking the search paths, matched with that plan.
 pub fn run(
     plan: &query::QueryPlan,
-    filter: &mut Filter,
-    out_style: &output::OutputStyle,
-    delimiter: output::Delimiter,
-    out: &mut dyn Write,
-) -> Result<()> {
-    let mut child = Command::new("mdfind")
-        .args(&plan.args)
+    walk_plan: Option<&query::QueryPlan>,
+    roots: &mut [Root<'_>],
+    criteria: &Criteria,
+    opts: RunOptions,
+    sink: &mut dyn output::Sink,
+) -> Result<Stats> {
+    let mut cmd = Command::new("mdfind");
+    cmd.args(&plan.args);
+    run_command(cmd, plan, walk_plan, roots, criteria, opts, sink)
+}
+
+/// Like `run`, but the candidates are paths read from `reader`, one per line, instead of
+/// `mdfind` results (`--from-stdin`). Relative paths are resolved against the current
+/// directory, and paths outside every search base are skipped.
+pub fn run_stdin(
+    reader: &mut dyn BufRead,
+    plan: &query::QueryPlan,
+    roots: &mut [Root<'_>],
+    criteria: &Criteria,
+    opts: RunOptions,
+    sink: &mut dyn output::Sink,
+) -> Result<Stats> {
+    let candidates = read_candidates(reader, b'\n');
+    let stats = consume_given(candidates, plan, roots, criteria, opts, sink)
+        .context("failed to read paths from stdin")?;
+    sink.finish()?;
+    Ok(stats)
+}
+
+/// Like `run_stdin`, with the candidate paths supplied directly.
+pub fn run_paths(
+    paths: impl IntoIterator<Item = PathBuf>,
+    plan: &query::QueryPlan,
+    roots: &mut [Root<'_>],
+    criteria: &Criteria,
+    opts: RunOptions,
+    sink: &mut dyn output::Sink,
+) -> Result<Stats> {
+    let candidates = paths.into_iter().map(Ok);
+    let stats = consume_given(candidates, plan, roots, criteria, opts, sink)?;
+    sink.finish()?;
+    Ok(stats)
+}
+
+/// `consume` for candidates that don't come from a search of the bases: relative paths are
+/// resolved against the current directory, and paths outside every base are skipped.
+fn consume_given(
+    candidates: impl Iterator<Item = io::Result<PathBuf>>,
+    plan: &query::QueryPlan,
+    roots: &mut [Root<'_>],
+    criteria: &Criteria,
+    opts: RunOptions,
+    sink: &mut dyn output::Sink,
+) -> Result<Stats> {
+    let cwd = roots[0].filter.config().cwd.clone();
+    let bases: Vec<PathBuf> = roots
+        .iter()
+        .map(|r| r.filter.config().search_base.clone())
+        .collect();
+    let under_a_base = |p: &Path| bases.iter().any(|b| p.starts_with(b));
+    let candidates = candidates
+        .map(|path| {
+            path.map(|p| {
+                let p = absolute_lexically(&cwd, &p);
+                if under_a_base(&p) {
+                    p
+                } else {
+                    through_real_parent(p)
+                }
+            })
+        })
+        .filter(|path| path.as_ref().map_or(true, |p| under_a_base(p)));
+    consume(candidates, plan, roots, criteria, opts, sink)
+}
+
+/// Whether `mdutil -s` reports that Spotlight has no enabled index for the volume holding
+/// `path`. Anything else, including `mdutil` failing, counts as indexed.
+pub fn indexing_disabled(path: &Path) -> bool {
+    Command::new("mdutil")
+        .arg("-s")
+        .arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .is_ok_and(|out| reports_indexing_off(&String::from_utf8_lossy(&out.stdout)))
+}
+
+/// `mdutil -s` prints the volume, then e.g. `Indexing enabled.`, `Indexing disabled.` or
+/// `No index.` on the next line.
+fn reports_indexing_off(status: &str) -> bool {
+    status.lines().any(|line| {
+        let line = line.trim();
+        line.starts_with("Indexing disabled") || line.starts_with("No index")
+    })
+}
+
+fn run_command(
+    mut cmd: Command,
+    plan: &query::QueryPlan,
+    walk_plan: Option<&query::QueryPlan>,
+    roots: &mut [Root<'_>],
+    criteria: &Criteria,
+    opts: RunOptions,
+    sink: &mut dyn output::Sink,
+) -> Result<Stats> {
+    let mut child = cmd
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
         .spawn()
@@ -45,41 +268,353 @@ pub fn run(
         .take()
         .context("failed to capture mdfind stdout")?;
     let mut reader = BufReader::new(stdout);
+    let bases: Vec<PathBuf> = roots
+        .iter()
+        .map(|r| r.filter.config().search_base.clone())
+        .collect();
+    let candidates =
+        read_candidates(&mut reader, b'\0').map(|path| path.map(|p| through_firmlink(p, &bases)));
+
+    let mut stats = consume(candidates, plan, roots, criteria, opts, sink)?;
 
+    let stopped_early = opts.limit.is_some_and(|n| stats.matched >= n);
+    if stopped_early {
+        // Done early: the rest of the scan is unwanted, and being killed is not a failure.
+        // `kill` only errors if the child already exited, which is fine too.
+        let _ = child.kill();
+    }
+    // Ensure we don't leave zombies. Sinks finish after mdfind is gone, so batch commands
+    // never run alongside the index scan.
+    let status = child.wait().context("failed to wait for mdfind")?;
+    if !stopped_early && !status.success() {
+        sink.finish()?;
+        anyhow::bail!("mdfind exited with status {status}");
+    }
+    if let Some(walk_plan) = walk_plan
+        && stats.candidates == 0
+    {
+        if opts.warnings {
+            eprintln!("Spotlight found nothing; walking the search path instead (slower)");
+        }
+        stats = consume(walk(roots), walk_plan, roots, criteria, opts, sink)?;
+    }
+    sink.finish()?;
+
+    Ok(stats)
+}
+
+/// Every entry below each search base, without following symlinks or skipping anything:
+/// hidden and ignore handling is left to the `Filter`, as for Spotlight results.
+fn walk(roots: &[Root<'_>]) -> impl Iterator<Item = io::Result<PathBuf>> + use<> {
+    let bases: Vec<PathBuf> = roots
+        .iter()
+        .map(|r| r.filter.config().search_base.clone())
+        .collect();
+    bases.into_iter().flat_map(|base| {
+        WalkBuilder::new(base)
+            .standard_filters(false)
+            .build()
+            // Unreadable directories are skipped, like entries Spotlight can't see.
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.depth() > 0)
+            .map(|entry| Ok(entry.into_path()))
+    })
+}
+
+/// Where the macOS data volume is mounted. Firmlinks make `/Users/a` and
+/// `/System/Volumes/Data/Users/a` the same directory, and Spotlight may report either.
+const DATA_VOLUME: &str = "/System/Volumes/Data";
+
+/// `path` spelled like the search bases: if it's only under one of them by way of the
+/// data volume firmlink, the other spelling. Ignore files, hidden checks and rendering
+/// all compare prefixes, so a result under `/System/Volumes/Data` for a search of
+/// `/Users/a` would otherwise be filtered and printed as if it were outside the base.
+fn through_firmlink(path: PathBuf, bases: &[PathBuf]) -> PathBuf {
+    let under_a_base = |p: &Path| bases.iter().any(|b| p.starts_with(b));
+    if under_a_base(&path) {
+        return path;
+    }
+    let data = Path::new(DATA_VOLUME);
+    let other = match path.strip_prefix(data) {
+        Ok(rest) => Path::new("/").join(rest),
+        Err(_) => data.join(path.strip_prefix("/").unwrap_or(&path)),
+    };
+    if under_a_base(&other) { other } else { path }
+}
+
+/// Split a candidate stream on `separator` (NUL for `mdfind -0`), skipping empty entries.
+fn read_candidates(
+    reader: &mut dyn BufRead,
+    separator: u8,
+) -> impl Iterator<Item = io::Result<PathBuf>> + '_ {
     let mut buf = Vec::new();
-    loop {
-        buf.clear();
-        let n = reader.read_until(b'\0', &mut buf)?;
-        if n == 0 {
+    std::iter::from_fn(move || {
+        loop {
+            buf.clear();
+            match reader.read_until(separator, &mut buf) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+
+            // Strip the separator (and optional CR, just in case).
+            while buf.last().is_some_and(|&b| b == separator || b == b'\r') {
+                buf.pop();
+            }
+            if !buf.is_empty() {
+                // Avoid an extra allocation: `read_until` gives us a Vec<u8> already.
+                let bytes = std::mem::take(&mut buf);
+                return Some(Ok(PathBuf::from(os_string_from_vec(bytes))));
+            }
+        }
+    })
+}
+
+/// Filter and print candidates, stopping once `limit` results have been accepted.
+///
+/// Spotlight doesn't report a directory before its contents, so with `--prune` the
+/// candidates are collected and sorted first: every directory is then decided before
+/// anything below it, and results come out in path order once the query has finished.
+/// That also needs one filter to see every directory, so pruning ignores `threads`.
+pub(crate) fn consume(
+    candidates: impl Iterator<Item = io::Result<PathBuf>>,
+    plan: &query::QueryPlan,
+    roots: &mut [Root<'_>],
+    criteria: &Criteria,
+    opts: RunOptions,
+    sink: &mut dyn output::Sink,
+) -> Result<Stats> {
+    if roots.iter().any(|r| r.filter.prunes()) {
+        let mut paths = candidates.collect::<io::Result<Vec<_>>>()?;
+        paths.sort();
+        return consume_in_order(paths.into_iter().map(Ok), plan, roots, criteria, opts, sink);
+    }
+    if opts.threads > 1 {
+        return consume_parallel(candidates, plan, roots, criteria, opts, sink);
+    }
+    consume_in_order(candidates, plan, roots, criteria, opts, sink)
+}
+
+fn consume_in_order(
+    mut candidates: impl Iterator<Item = io::Result<PathBuf>>,
+    plan: &query::QueryPlan,
+    roots: &mut [Root<'_>],
+    criteria: &Criteria,
+    opts: RunOptions,
+    sink: &mut dyn output::Sink,
+) -> Result<Stats> {
+    let mut stats = Stats::default();
+    let mut seen = Seen::new(opts);
+    while opts.limit.is_none_or(|n| stats.matched < n) {
+        let Some(path) = candidates.next().transpose()? else {
             break;
+        };
+        let root = root_for(roots, &path);
+        let candidate = match accept(&path, plan, root.filter, criteria) {
+            Ok(candidate) => candidate,
+            Err(reason) => {
+                stats.record(Err(reason));
+                continue;
+            }
+        };
+        if seen.is_repeat(&path, candidate.meta.as_ref()) {
+            stats.record(Err(Rejection::Duplicate));
+            continue;
         }
+        stats.record(Ok(()));
+        if candidate.is_dir {
+            root.filter.prune(&path);
+        }
+        let repo_root = if root.out_style.is_repo_relative() {
+            root.filter.repo_root_of(&path)
+        } else {
+            None
+        };
+        let rendered =
+            root.out_style
+                .render_entry_in(&path, candidate.is_dir, repo_root.as_deref());
+        sink.accept(&path, &rendered, candidate.meta.as_ref())?;
+    }
 
-        // Strip trailing NUL (and optional CR, just in case).
-        while matches!(buf.last(), Some(b'\0' | b'\r')) {
-            buf.pop();
+    Ok(stats)
+}
+
+/// A candidate that a worker of `consume_parallel` accepted, rendered for the sink.
+struct Accepted {
+    path: PathBuf,
+    rendered: PathBuf,
+    meta: Option<fs::Metadata>,
+}
+
+/// `consume_in_order` on `opts.threads` workers, each with its own copy of every root's
+/// filter. Candidates are handed out as they are read and results reach `sink` (on this
+/// thread) as soon as a worker is done with them, so their order is unspecified.
+fn consume_parallel(
+    mut candidates: impl Iterator<Item = io::Result<PathBuf>>,
+    plan: &query::QueryPlan,
+    roots: &[Root<'_>],
+    criteria: &Criteria,
+    opts: RunOptions,
+    sink: &mut dyn output::Sink,
+) -> Result<Stats> {
+    let (work_tx, work_rx) = mpsc::sync_channel::<PathBuf>(opts.threads * 64);
+    let work_rx = Mutex::new(work_rx);
+    let (done_tx, done_rx) = mpsc::channel::<Result<Accepted, Rejection>>();
+    let mut stats = Stats::default();
+    let mut seen = Seen::new(opts);
+    let mut deliver = |outcome: Result<Accepted, Rejection>, stats: &mut Stats| -> Result<()> {
+        let outcome = outcome.and_then(|found| {
+            if seen.is_repeat(&found.path, found.meta.as_ref()) {
+                Err(Rejection::Duplicate)
+            } else {
+                Ok(found)
+            }
+        });
+        stats.record(outcome.as_ref().map(drop).map_err(|r| *r));
+        match outcome {
+            Ok(found) => sink.accept(&found.path, &found.rendered, found.meta.as_ref()),
+            Err(_) => Ok(()),
         }
-        if buf.is_empty() {
-            continue;
+    };
+    let under_limit = |stats: &Stats| opts.limit.is_none_or(|n| stats.matched < n);
+
+    thread::scope(|scope| {
+        for _ in 0..opts.threads {
+            let mut filters: Vec<Filter> = roots.iter().map(|r| r.filter.clone()).collect();
+            let (work_rx, done_tx) = (&work_rx, done_tx.clone());
+            scope.spawn(move || {
+                loop {
+                    // The lock is only held while waiting for the next candidate.
+                    let next = match work_rx.lock() {
+                        Ok(rx) => rx.recv(),
+                        Err(_) => break,
+                    };
+                    let Ok(path) = next else {
+                        break;
+                    };
+                    let i = root_index(filters.iter(), &path);
+                    let outcome = accept(&path, plan, &mut filters[i], criteria).map(|candidate| {
+                        let out_style = roots[i].out_style;
+                        let repo_root = if out_style.is_repo_relative() {
+                            filters[i].repo_root_of(&path)
+                        } else {
+                            None
+                        };
+                        Accepted {
+                            rendered: out_style.render_entry_in(
+                                &path,
+                                candidate.is_dir,
+                                repo_root.as_deref(),
+                            ),
+                            path,
+                            meta: candidate.meta,
+                        }
+                    });
+                    if done_tx.send(outcome).is_err() {
+                        break;
+                    }
+                }
+            });
         }
+        // Only the workers' copies remain, so the results end once every worker has quit.
+        drop(done_tx);
 
-        // Avoid an extra allocation: `read_until` gives us a Vec<u8> already.
-        let bytes = std::mem::take(&mut buf);
-        let path = std::path::PathBuf::from(os_string_from_vec(bytes));
-        if filter.should_include(&path)
-            && plan.rust_matcher.as_ref().is_none_or(|m| m.matches(&path))
+        // Owning both ends here means leaving, by error or limit, hangs up on the workers.
+        let (work_tx, done_rx) = (work_tx, done_rx);
+        while under_limit(&stats) {
+            let Some(path) = candidates.next().transpose()? else {
+                break;
+            };
+            work_tx.send(path)?;
+            while under_limit(&stats)
+                && let Ok(outcome) = done_rx.try_recv()
+            {
+                deliver(outcome, &mut stats)?;
+            }
+        }
+        drop(work_tx);
+        // Results still in flight; past the limit, the rest are dropped unread.
+        while under_limit(&stats)
+            && let Ok(outcome) = done_rx.recv()
         {
-            let rendered = out_style.render(&path);
-            output::write_path(out, &rendered, delimiter)?;
+            deliver(outcome, &mut stats)?;
         }
+        Ok(stats)
+    })
+}
+
+/// What the pipeline learned about a candidate it accepted.
+#[derive(Debug)]
+struct CandidateMeta {
+    /// The candidate's `lstat`, or `None` if it failed.
+    meta: Option<fs::Metadata>,
+    is_dir: bool,
+}
+
+/// Run a candidate through the whole pipeline, cheapest checks first: the name (pattern,
+/// then extension), then where it is (below a hidden, ignored or excluded directory?),
+/// then a single `lstat` shared by the rest of the ignore filter and the metadata criteria
+/// (type, size, time, contents).
+fn accept(
+    path: &Path,
+    plan: &query::QueryPlan,
+    filter: &mut Filter,
+    criteria: &Criteria,
+) -> Result<CandidateMeta, Rejection> {
+    // Match fd defaults: do not follow symlinks when determining whether something is a dir.
+    accept_with(path, plan, filter, criteria, |p| {
+        fs::symlink_metadata(p).ok()
+    })
+}
+
+fn accept_with(
+    path: &Path,
+    plan: &query::QueryPlan,
+    filter: &mut Filter,
+    criteria: &Criteria,
+    lstat: impl FnOnce(&Path) -> Option<fs::Metadata>,
+) -> Result<CandidateMeta, Rejection> {
+    if plan.rust_matcher.as_ref().is_some_and(|m| !m.matches(path)) {
+        return Err(Rejection::Pattern);
     }
+    criteria.check_name(path)?;
+    filter.check_location(path)?;
 
-    // Ensure we don't leave zombies (and propagate any execution failure).
-    let status = child.wait().context("failed to wait for mdfind")?;
-    if !status.success() {
-        anyhow::bail!("mdfind exited with status {status}");
+    let meta = lstat(path);
+    let is_dir = meta.as_ref().is_some_and(|m| m.is_dir());
+    filter.check_located_entry(path, is_dir)?;
+    criteria.check_metadata(path, meta.as_ref())?;
+    Ok(CandidateMeta { meta, is_dir })
+}
+
+/// Join `path` onto `cwd` and resolve `.` and `..` without touching the filesystem, so
+/// `../x` can't pass for a path under the search base.
+fn absolute_lexically(cwd: &Path, path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in cwd.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
     }
+    out
+}
 
-    Ok(())
+/// `path` with its parent directory canonicalized, so a path spelled through a symlink
+/// (`/tmp` for `/private/tmp`, say) lines up with the canonical search bases. The last
+/// component is kept as is, since a symlink result is reported as the link. Unchanged if
+/// the parent can't be resolved.
+fn through_real_parent(path: PathBuf) -> PathBuf {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return path;
+    };
+    match fs::canonicalize(parent) {
+        Ok(parent) => parent.join(name),
+        Err(_) => path,
+    }
 }
 
 fn os_string_from_vec(bytes: Vec<u8>) -> OsString {
@@ -95,3 +630,1052 @@ fn os_string_from_vec(bytes: Vec<u8>) -> OsString {
         String::from_utf8_lossy(&bytes).into_owned().into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Write,
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    use ignore::gitignore::Gitignore;
+    use tempfile::TempDir;
+
+    use crate::{
+        criteria::{CriteriaConfig, FileType, SizeFilter},
+        exec,
+        filter::{DepthRange, FilterConfig, IgnoreSources},
+        query::{QueryOptions, build_mdfind_plan, build_stdin_plan},
+    };
+
+    /// `base` searched from `cwd`, with no global gitignore.
+    fn config_for_test(
+        cwd: &Path,
+        base: &Path,
+        ignores: IgnoreSources,
+        parent_ignores: bool,
+    ) -> FilterConfig {
+        FilterConfig {
+            cwd: cwd.to_path_buf(),
+            search_base: base.to_path_buf(),
+            include_hidden: false,
+            ignores,
+            parent_ignores,
+            depth: DepthRange::ANY,
+        }
+    }
+
+    fn filter_for_test(
+        cwd: &Path,
+        base: &Path,
+        ignores: IgnoreSources,
+        parent_ignores: bool,
+    ) -> Filter {
+        Filter::new_with_globals(
+            config_for_test(cwd, base, ignores, parent_ignores),
+            Gitignore::empty(),
+            None,
+        )
+    }
+
+    /// How `sf` prints results under `base` when no search path was typed.
+    fn relative_style(cwd: &Path, base: &Path) -> output::OutputStyle {
+        output::OutputStyle::new(
+            cwd.to_path_buf(),
+            base.to_path_buf(),
+            None,
+            output::PathMode::Relative,
+        )
+    }
+
+    /// Feed `rels` under the filter's search base through `consume`, and return what was
+    /// printed along with the stats.
+    fn run_consume(
+        filter: &mut Filter,
+        plan: &query::QueryPlan,
+        criteria: &Criteria,
+        opts: RunOptions,
+        rels: &[&str],
+    ) -> (String, Stats) {
+        let config = filter.config();
+        let out_style = relative_style(&config.cwd, &config.search_base);
+        let input = nul_separated(&config.search_base, rels);
+        let mut out = Vec::new();
+        let stats = consume(
+            read_candidates(&mut &input[..], b'\0'),
+            plan,
+            &mut [Root {
+                filter,
+                out_style: &out_style,
+            }],
+            criteria,
+            opts,
+            &mut output::PathWriter::new(&mut out, output::Delimiter::Newline),
+        )
+        .unwrap();
+        (String::from_utf8(out).unwrap(), stats)
+    }
+
+    fn nul_separated(root: &Path, rels: &[&str]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for rel in rels {
+            buf.extend_from_slice(root.join(rel).to_str().unwrap().as_bytes());
+            buf.push(b'\0');
+        }
+        buf
+    }
+
+    #[test]
+    fn stats_attribute_rejections_to_the_first_failing_stage() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(root.join(".gitignore"), "ignored.log\n").unwrap();
+        fs::create_dir_all(root.join("folder.log")).unwrap();
+
+        fs::write(root.join("keep.log"), "x".repeat(2000)).unwrap();
+        fs::write(root.join("wee.log"), "x").unwrap();
+        fs::write(root.join("stale.log"), "x".repeat(2000)).unwrap();
+        fs::write(root.join("notes.txt"), "x".repeat(2000)).unwrap();
+        fs::write(root.join("ignored.log"), "x".repeat(2000)).unwrap();
+        fs::write(root.join(".hidden.log"), "x".repeat(2000)).unwrap();
+        fs::write(root.join("Other.log"), "x".repeat(2000)).unwrap();
+        fs::write(root.join("data.log"), "x".repeat(2000)).unwrap();
+        let now = SystemTime::now();
+        fs::File::options()
+            .write(true)
+            .open(root.join("stale.log"))
+            .unwrap()
+            .set_modified(now - Duration::from_secs(30 * 86_400))
+            .unwrap();
+
+        let mut filter = filter_for_test(root, root, IgnoreSources::ALL, true);
+        let criteria = Criteria::new(CriteriaConfig {
+            file_types: vec![FileType::File],
+            extensions: vec!["log".into()],
+            extensions_case_sensitive: false,
+            sizes: vec![SizeFilter::Min(1000)],
+            changed_after: Some(now - Duration::from_secs(86_400)),
+            changed_before: None,
+            owner: None,
+            skip_binary: false,
+        });
+        // Full-path substring keeps a Rust-side matcher in play for any base.
+        let plan = build_mdfind_plan(
+            &[root.to_path_buf()],
+            Some("e"),
+            QueryOptions {
+                full_path: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let (out, stats) = run_consume(
+            &mut filter,
+            &plan,
+            &criteria,
+            RunOptions::default(),
+            &[
+                "keep.log",
+                "wee.log",
+                "stale.log",
+                "notes.txt",
+                "ignored.log",
+                ".hidden.log",
+                "folder.log",
+                "Other.log",
+                "data.log",
+                "deleted.log",
+            ],
+        );
+
+        assert_eq!(out, "keep.log\nOther.log\n");
+        assert_eq!(stats.candidates, 10);
+        assert_eq!(stats.matched, 2);
+        assert_eq!(stats.rejected(Rejection::Hidden), 1);
+        assert_eq!(stats.rejected(Rejection::Ignored), 1);
+        // `folder.log` is a directory and `deleted.log` no longer exists.
+        assert_eq!(stats.rejected(Rejection::Type), 2);
+        assert_eq!(stats.rejected(Rejection::Extension), 1);
+        assert_eq!(stats.rejected(Rejection::Size), 1);
+        assert_eq!(stats.rejected(Rejection::Time), 1);
+        assert_eq!(stats.rejected(Rejection::Pattern), 1);
+    }
+
+    #[test]
+    fn just_touched_file_passes_changed_within_one_second() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        let after = crate::criteria::parse_time("1s").unwrap();
+        fs::write(root.join("fresh.o"), "x").unwrap();
+        fs::write(root.join("old.o"), "x").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(root.join("old.o"))
+            .unwrap()
+            .set_modified(after - Duration::from_secs(3600))
+            .unwrap();
+
+        let opts = QueryOptions {
+            changed_after: Some(after),
+            time_slop: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&[root.to_path_buf()], Some(".o"), opts).unwrap();
+        let since = crate::criteria::format_iso_utc(after - Duration::from_secs(60));
+        assert!(
+            plan.args
+                .last()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .ends_with(&format!(
+                    "(kMDItemFSContentChangeDate >= $time.iso({since}))"
+                ))
+        );
+
+        let mut filter = filter_for_test(root, root, IgnoreSources::NONE, false);
+        let criteria = Criteria::new(CriteriaConfig {
+            changed_after: Some(after),
+            ..Default::default()
+        });
+        // Spotlight's slop window can let a stale entry through; lstat has the last word.
+        let (out, stats) = run_consume(
+            &mut filter,
+            &plan,
+            &criteria,
+            RunOptions::default(),
+            &["fresh.o", "old.o"],
+        );
+        assert_eq!(out, "fresh.o\n");
+        assert_eq!(stats.rejected(Rejection::Time), 1);
+    }
+
+    #[test]
+    fn name_checks_reject_before_any_lstat() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("lib.rs"), "").unwrap();
+        fs::write(root.join("lib.txt"), "").unwrap();
+        fs::write(root.join("main.rs"), "").unwrap();
+
+        let mut filter = filter_for_test(root, root, IgnoreSources::NONE, false);
+        let criteria = Criteria::new(CriteriaConfig {
+            file_types: vec![FileType::File],
+            extensions: vec!["rs".into()],
+            ..Default::default()
+        });
+        let plan =
+            build_stdin_plan(&[root.to_path_buf()], Some("lib"), QueryOptions::default()).unwrap();
+
+        let lstats = std::cell::Cell::new(0);
+        let mut check = |name: &str| {
+            accept_with(&root.join(name), &plan, &mut filter, &criteria, |p| {
+                lstats.set(lstats.get() + 1);
+                fs::symlink_metadata(p).ok()
+            })
+            .map(|c| c.is_dir)
+        };
+        assert_eq!(check("main.rs"), Err(Rejection::Pattern));
+        assert_eq!(check("lib.txt"), Err(Rejection::Extension));
+        assert_eq!(lstats.get(), 0);
+        assert_eq!(check("lib.rs"), Ok(false));
+        assert_eq!(lstats.get(), 1);
+    }
+
+    #[test]
+    fn nothing_below_an_excluded_directory_is_lstated() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        let deep = "vendor/a/b/c/d";
+        fs::create_dir_all(root.join(deep)).unwrap();
+        fs::write(root.join(deep).join("lib.rs"), "").unwrap();
+        fs::write(root.join("main.rs"), "").unwrap();
+
+        let mut filter = filter_for_test(root, root, IgnoreSources::NONE, false)
+            .with_excludes(&["/vendor/".into()])
+            .unwrap();
+        let criteria = Criteria::new(CriteriaConfig::default());
+        let plan = build_stdin_plan(&[root.to_path_buf()], None, QueryOptions::default()).unwrap();
+
+        let lstats = std::cell::RefCell::new(Vec::new());
+        let mut check = |rel: &str| {
+            accept_with(&root.join(rel), &plan, &mut filter, &criteria, |p| {
+                lstats.borrow_mut().push(p.to_path_buf());
+                fs::symlink_metadata(p).ok()
+            })
+            .map(|c| c.is_dir)
+        };
+        // The excluded directory itself needs its `lstat` (the rule only matches
+        // directories), and then prunes the whole subtree.
+        assert_eq!(check("vendor"), Err(Rejection::Ignored));
+        for rel in ["vendor/a", "vendor/a/b/c", "vendor/a/b/c/d/lib.rs"] {
+            assert_eq!(check(rel), Err(Rejection::Ignored), "{rel}");
+        }
+        assert_eq!(check("main.rs"), Ok(false));
+        assert_eq!(
+            *lstats.borrow(),
+            [root.join("vendor"), root.join("main.rs")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn every_metadata_check_shares_one_lstat() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("big.log"), "x".repeat(2000)).unwrap();
+        let uid = fs::symlink_metadata(root.join("big.log")).unwrap().uid();
+
+        let mut filter = filter_for_test(root, root, IgnoreSources::ALL, false);
+        let criteria = Criteria::new(CriteriaConfig {
+            file_types: vec![FileType::File],
+            extensions: vec!["log".into()],
+            sizes: vec![SizeFilter::Min(1000)],
+            changed_after: Some(SystemTime::now() - Duration::from_secs(3600)),
+            owner: Some(uid.to_string().parse().unwrap()),
+            ..Default::default()
+        });
+        let plan = build_stdin_plan(&[root.to_path_buf()], None, QueryOptions::default()).unwrap();
+
+        let lstats = std::cell::Cell::new(0);
+        let candidate = accept_with(&root.join("big.log"), &plan, &mut filter, &criteria, |p| {
+            lstats.set(lstats.get() + 1);
+            fs::symlink_metadata(p).ok()
+        })
+        .unwrap();
+        // Hidden, ignore, type, size, time and owner checks all read the same `lstat`,
+        // which is then handed on to the sink.
+        assert_eq!(lstats.get(), 1);
+        assert_eq!(candidate.meta.map(|m| m.len()), Some(2000));
+    }
+
+    #[test]
+    fn mdutil_status_says_whether_indexing_is_off() {
+        assert!(reports_indexing_off(
+            "/Volumes/Backup:\n\tIndexing disabled.\n"
+        ));
+        assert!(reports_indexing_off("/Volumes/USB:\n\tNo index.\n"));
+        assert!(!reports_indexing_off(
+            "/System/Volumes/Data:\n\tIndexing enabled. \n"
+        ));
+        assert!(!reports_indexing_off("Error: unknown indexing state.\n"));
+        assert!(!reports_indexing_off(""));
+    }
+
+    #[test]
+    fn limit_stops_reading_and_kills_the_producer() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("a.txt"), "").unwrap();
+
+        let mut filter = filter_for_test(root, root, IgnoreSources::NONE, true);
+        let plan = build_mdfind_plan(&[root.to_path_buf()], None, QueryOptions::default()).unwrap();
+        let out_style = relative_style(root, root);
+
+        // Stands in for a slow index scan that never finishes on its own.
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", r#"while :; do printf '%s\0' "$1"; done"#, "sh"])
+            .arg(root.join("a.txt"));
+        let mut out = Vec::new();
+        let stats = run_command(
+            cmd,
+            &plan,
+            None,
+            &mut [Root {
+                filter: &mut filter,
+                out_style: &out_style,
+            }],
+            &Criteria::new(CriteriaConfig::default()),
+            RunOptions::default().with_limit(Some(3)),
+            &mut output::PathWriter::new(&mut out, output::Delimiter::Newline),
+        )
+        .unwrap();
+
+        assert_eq!(stats.matched, 3);
+        assert_eq!(stats.candidates, 3);
+        assert_eq!(String::from_utf8(out).unwrap(), "a.txt\na.txt\na.txt\n");
+    }
+
+    #[test]
+    fn results_through_the_data_volume_firmlink_are_under_the_base() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("a.txt"), "").unwrap();
+        fs::write(root.join("b.txt"), "").unwrap();
+        fs::write(root.join(".hidden.txt"), "").unwrap();
+
+        let mut filter = filter_for_test(root, root, IgnoreSources::NONE, true);
+        let plan = build_mdfind_plan(&[root.to_path_buf()], None, QueryOptions::default()).unwrap();
+        let out_style = relative_style(root, root);
+
+        // Spotlight answering a search of `root` with the data volume's spelling of it.
+        let data = Path::new(DATA_VOLUME);
+        let via_data = data.join(root.strip_prefix("/").unwrap());
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", r#"printf '%s\0' "$@""#, "sh"]).args([
+            via_data.join("a.txt"),
+            root.join("b.txt"),
+            via_data.join(".hidden.txt"),
+        ]);
+        let mut out = Vec::new();
+        let stats = run_command(
+            cmd,
+            &plan,
+            None,
+            &mut [Root {
+                filter: &mut filter,
+                out_style: &out_style,
+            }],
+            &Criteria::new(CriteriaConfig::default()),
+            RunOptions::default(),
+            &mut output::PathWriter::new(&mut out, output::Delimiter::Newline),
+        )
+        .unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "a.txt\nb.txt\n");
+        assert_eq!(stats.rejected(Rejection::Hidden), 1);
+
+        // And the other way round, for a base spelled through the data volume.
+        let base = data.join("Users/a");
+        assert_eq!(
+            through_firmlink(PathBuf::from("/Users/a/x"), std::slice::from_ref(&base)),
+            base.join("x")
+        );
+        assert_eq!(
+            through_firmlink(PathBuf::from("/Users/b/x"), &[base]),
+            PathBuf::from("/Users/b/x")
+        );
+    }
+
+    #[test]
+    fn limit_counts_emitted_results_and_ignores_the_producer_status() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("a.txt"), "").unwrap();
+        fs::write(root.join(".hidden.txt"), "").unwrap();
+
+        let mut filter = filter_for_test(root, root, IgnoreSources::NONE, true);
+        let plan = build_mdfind_plan(&[root.to_path_buf()], None, QueryOptions::default()).unwrap();
+        let out_style = relative_style(root, root);
+
+        // Rejected candidates don't count toward the limit, and a producer that fails after
+        // the limit was reached is not an error.
+        let mut cmd = Command::new("sh");
+        cmd.args([
+            "-c",
+            r#"printf '%s\0' "$1" "$2" "$1" "$2" "$2"; exit 3"#,
+            "sh",
+        ])
+        .arg(root.join(".hidden.txt"))
+        .arg(root.join("a.txt"));
+        let mut out = Vec::new();
+        let stats = run_command(
+            cmd,
+            &plan,
+            None,
+            &mut [Root {
+                filter: &mut filter,
+                out_style: &out_style,
+            }],
+            &Criteria::new(CriteriaConfig::default()),
+            RunOptions::default().with_limit(Some(2)),
+            &mut output::PathWriter::new(&mut out, output::Delimiter::Newline),
+        )
+        .unwrap();
+
+        assert_eq!(stats.matched, 2);
+        assert_eq!(stats.candidates, 4);
+        assert_eq!(String::from_utf8(out).unwrap(), "a.txt\na.txt\n");
+    }
+
+    #[test]
+    fn walk_fallback_runs_only_when_spotlight_returns_nothing() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("src/.cache")).unwrap();
+        fs::write(root.join(".ignore"), "target/\n").unwrap();
+        for rel in [
+            "Notes.md",
+            "src/notes.rs",
+            "src/.cache/notes",
+            "target/notes",
+        ] {
+            fs::create_dir_all(root.join(rel).parent().unwrap()).unwrap();
+            fs::write(root.join(rel), "").unwrap();
+        }
+
+        let search = |script: &str| {
+            let mut filter = filter_for_test(root, root, IgnoreSources::ALL, true);
+            let bases = [root.to_path_buf()];
+            // Spotlight's `-name` plan leaves case-insensitive matching to `mdfind`; the
+            // walk needs a plan that enforces the pattern itself.
+            let plan = build_mdfind_plan(&bases, Some("notes"), QueryOptions::default()).unwrap();
+            let walk_plan =
+                build_stdin_plan(&bases, Some("notes"), QueryOptions::default()).unwrap();
+            let out_style = relative_style(root, root);
+            let mut cmd = Command::new("sh");
+            cmd.args(["-c", script, "sh"]).arg(root.join("Notes.md"));
+            let mut out = Vec::new();
+            run_command(
+                cmd,
+                &plan,
+                Some(&walk_plan),
+                &mut [Root {
+                    filter: &mut filter,
+                    out_style: &out_style,
+                }],
+                &Criteria::new(CriteriaConfig::default()),
+                RunOptions::default(),
+                &mut output::PathWriter::new(&mut out, output::Delimiter::Newline),
+            )
+            .unwrap();
+            let mut lines: Vec<String> = String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .map(str::to_owned)
+                .collect();
+            lines.sort();
+            lines
+        };
+
+        assert_eq!(search("true"), ["Notes.md", "src/notes.rs"]);
+        assert_eq!(search(r#"printf '%s\0' "$1""#), ["Notes.md"]);
+    }
+
+    #[test]
+    fn stdin_candidates_are_matched_against_the_pattern_with_smart_case() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("root");
+        fs::create_dir_all(root.join("src")).unwrap();
+        for name in [
+            "Config.toml",
+            "config.rs",
+            "src/app_config.json",
+            "notes.txt",
+        ] {
+            fs::write(root.join(name), "").unwrap();
+        }
+        fs::write(tmp.path().join("config.outside"), "").unwrap();
+
+        let filter_stdin = |pattern: &str, input: &str| {
+            let mut filter = filter_for_test(&root, &root, IgnoreSources::NONE, true);
+            let plan = build_stdin_plan(
+                std::slice::from_ref(&root),
+                Some(pattern),
+                QueryOptions::default(),
+            )
+            .unwrap();
+            let out_style = relative_style(&root, &root);
+            let mut out = Vec::new();
+            run_stdin(
+                &mut input.as_bytes(),
+                &plan,
+                &mut [Root {
+                    filter: &mut filter,
+                    out_style: &out_style,
+                }],
+                &Criteria::new(CriteriaConfig::default()),
+                RunOptions::default(),
+                &mut output::PathWriter::new(&mut out, output::Delimiter::Newline),
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let input = format!(
+            "Config.toml\n./config.rs\r\n\n{}\nnotes.txt\n../config.outside\n{}\n",
+            root.join("src/app_config.json").display(),
+            tmp.path().join("config.outside").display(),
+        );
+        assert_eq!(
+            filter_stdin("config", &input),
+            "Config.toml\nconfig.rs\nsrc/app_config.json\n"
+        );
+        assert_eq!(filter_stdin("Config", &input), "Config.toml\n");
+        assert_eq!(filter_stdin("*.rs", &input), "config.rs\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn stdin_paths_spelled_through_a_symlinked_base_are_kept() {
+        let tmp = TempDir::new().unwrap();
+        let cwd = fs::canonicalize(tmp.path()).unwrap();
+        let root = cwd.join("real");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/a.txt"), "").unwrap();
+        std::os::unix::fs::symlink(&root, cwd.join("link")).unwrap();
+
+        let mut filter = filter_for_test(&cwd, &root, IgnoreSources::NONE, false);
+        let plan =
+            build_stdin_plan(std::slice::from_ref(&root), None, QueryOptions::default()).unwrap();
+        let out_style = relative_style(&cwd, &root);
+        let mut out = Vec::new();
+        let input = format!("link/src/a.txt\n{}\n", cwd.join("link/src/a.txt").display());
+        run_stdin(
+            &mut input.as_bytes(),
+            &plan,
+            &mut [Root {
+                filter: &mut filter,
+                out_style: &out_style,
+            }],
+            &Criteria::new(CriteriaConfig::default()),
+            RunOptions::default(),
+            &mut output::PathWriter::new(&mut out, output::Delimiter::Newline),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "real/src/a.txt\nreal/src/a.txt\n"
+        );
+    }
+
+    #[test]
+    fn each_candidate_uses_the_closest_search_path() {
+        let tmp = TempDir::new().unwrap();
+        let cwd = tmp.path().to_path_buf();
+        for rel in [
+            "one/a.txt",
+            "one/nested/b.txt",
+            "two/.ignore",
+            "two/c.txt",
+            "d.txt",
+        ] {
+            let p = cwd.join(rel);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(p, "c.txt\n").unwrap();
+        }
+
+        let paths = ["one", "two", "one/nested"].map(PathBuf::from);
+        let mut filters: Vec<Filter> = paths
+            .iter()
+            .map(|path| {
+                Filter::new_with_globals(
+                    FilterConfig {
+                        depth: DepthRange {
+                            min: 0,
+                            max: Some(1),
+                        },
+                        ..config_for_test(&cwd, &cwd.join(path), IgnoreSources::ALL, true)
+                    },
+                    Gitignore::empty(),
+                    None,
+                )
+            })
+            .collect();
+        let out_styles: Vec<output::OutputStyle> = paths
+            .iter()
+            .map(|path| {
+                output::OutputStyle::new(
+                    cwd.clone(),
+                    cwd.join(path),
+                    Some(path),
+                    output::PathMode::Relative,
+                )
+            })
+            .collect();
+        let mut roots: Vec<Root> = filters
+            .iter_mut()
+            .zip(&out_styles)
+            .map(|(filter, out_style)| Root { filter, out_style })
+            .collect();
+        let bases: Vec<PathBuf> = paths.iter().map(|p| cwd.join(p)).collect();
+        let plan = build_stdin_plan(&bases, Some("txt"), QueryOptions::default()).unwrap();
+
+        let mut out = Vec::new();
+        let stats = run_stdin(
+            &mut "one/a.txt\none/nested/b.txt\ntwo/c.txt\nd.txt\n".as_bytes(),
+            &plan,
+            &mut roots,
+            &Criteria::new(CriteriaConfig::default()),
+            RunOptions::default(),
+            &mut output::PathWriter::new(&mut out, output::Delimiter::Newline),
+        )
+        .unwrap();
+
+        // `b.txt` is one level below `one/nested` (but two below `one`), `two/c.txt` is
+        // ignored by `two/.ignore`, and `d.txt` is outside every search path.
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "one/a.txt\none/nested/b.txt\n"
+        );
+        assert_eq!(stats.candidates, 3);
+    }
+
+    #[test]
+    fn threads_find_the_same_results_in_any_order() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        let mut rels = Vec::new();
+        for dir in ["src", "src/deep", "build", ".cache"] {
+            for i in 0..40 {
+                rels.push(format!("{dir}/f{i}.rs"));
+                rels.push(format!("{dir}/f{i}.md"));
+            }
+        }
+        for rel in &rels {
+            let p = root.join(rel);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(p, "").unwrap();
+        }
+        let rels: Vec<&str> = rels.iter().map(String::as_str).collect();
+        let plan =
+            build_stdin_plan(&[root.to_path_buf()], Some("rs"), QueryOptions::default()).unwrap();
+
+        let search = |opts: RunOptions| {
+            let mut filter = filter_for_test(root, root, IgnoreSources::ALL, true);
+            let criteria = Criteria::new(CriteriaConfig::default());
+            let (out, stats) = run_consume(&mut filter, &plan, &criteria, opts, &rels);
+            let mut lines: Vec<String> = out.lines().map(str::to_owned).collect();
+            lines.sort();
+            (lines, stats)
+        };
+
+        let (want, want_stats) = search(RunOptions::default());
+        assert_eq!(want.len(), 80);
+        assert_eq!(want_stats.rejected(Rejection::Ignored), 40);
+        assert_eq!(want_stats.rejected(Rejection::Hidden), 40);
+        let (got, got_stats) = search(RunOptions::default().with_threads(4));
+        assert_eq!(got, want);
+        assert_eq!(got_stats, want_stats);
+
+        // A limit still means exactly that many results, whichever threads found them.
+        let (got, got_stats) = search(RunOptions::default().with_threads(4).with_limit(Some(5)));
+        assert_eq!(got.len(), 5);
+        assert_eq!(got_stats.matched, 5);
+        assert!(got.iter().all(|line| want.contains(line)));
+    }
+
+    #[test]
+    fn max_buffer_time_writes_results_out_while_the_search_stalls() {
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        impl Shared {
+            fn contents(&self) -> String {
+                String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+            }
+        }
+
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("a.txt"), "").unwrap();
+        fs::write(root.join("b.txt"), "").unwrap();
+
+        let mut filter = filter_for_test(root, root, IgnoreSources::NONE, false);
+        let out_style = relative_style(root, root);
+        let plan =
+            build_stdin_plan(&[root.to_path_buf()], Some("txt"), QueryOptions::default()).unwrap();
+
+        let written = Shared::default();
+        let mut out = output::TimedBuffer::new(written.clone(), Duration::from_millis(20));
+        let mut paths = [root.join("a.txt"), root.join("b.txt")].into_iter();
+        // Spotlight goes quiet after the first result; it must not sit in the buffer.
+        let stalled = std::iter::from_fn(|| {
+            let path = paths.next()?;
+            if path.ends_with("b.txt") {
+                thread::sleep(Duration::from_millis(300));
+                assert_eq!(written.contents(), "a.txt\n");
+            }
+            Some(Ok(path))
+        });
+        consume(
+            stalled,
+            &plan,
+            &mut [Root {
+                filter: &mut filter,
+                out_style: &out_style,
+            }],
+            &Criteria::new(CriteriaConfig::default()),
+            RunOptions::default(),
+            &mut output::PathWriter::new(&mut out, output::Delimiter::Newline),
+        )
+        .unwrap();
+        drop(out);
+        assert_eq!(written.contents(), "a.txt\nb.txt\n");
+    }
+
+    #[test]
+    fn unique_reports_each_file_once() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.txt"), "").unwrap();
+        fs::write(root.join("b.txt"), "").unwrap();
+        fs::hard_link(root.join("b.txt"), root.join("sub/b-link.txt")).unwrap();
+
+        // `sub` is also searched on its own, so Spotlight may report its files twice.
+        let bases = [root.to_path_buf(), root.join("sub")];
+        let mut filters: Vec<Filter> = bases
+            .iter()
+            .map(|base| filter_for_test(root, base, IgnoreSources::NONE, false))
+            .collect();
+        let out_styles: Vec<output::OutputStyle> = bases
+            .iter()
+            .map(|base| relative_style(root, base))
+            .collect();
+        let plan = build_stdin_plan(&bases, Some("txt"), QueryOptions::default()).unwrap();
+        let input = nul_separated(
+            root,
+            &[
+                "sub/a.txt",
+                "b.txt",
+                "sub/a.txt",
+                "sub/b-link.txt",
+                "sub/./a.txt",
+            ],
+        );
+
+        for threads in [1, 3] {
+            let mut roots: Vec<Root> = filters
+                .iter_mut()
+                .zip(&out_styles)
+                .map(|(filter, out_style)| Root { filter, out_style })
+                .collect();
+            let mut out = Vec::new();
+            let stats = consume(
+                read_candidates(&mut &input[..], b'\0'),
+                &plan,
+                &mut roots,
+                &Criteria::new(CriteriaConfig::default()),
+                RunOptions::default()
+                    .with_unique(true)
+                    .with_threads(threads),
+                &mut output::PathWriter::new(&mut out, output::Delimiter::Newline),
+            )
+            .unwrap();
+
+            let mut lines: Vec<String> = String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .map(str::to_owned)
+                .collect();
+            lines.sort();
+            assert_eq!(lines, ["b.txt", "sub/a.txt"], "threads={threads}");
+            assert_eq!(stats.matched, 2);
+            assert_eq!(stats.rejected(Rejection::Duplicate), 3);
+        }
+    }
+
+    #[test]
+    fn prune_skips_the_contents_of_matched_directories_in_any_order() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        for dir in ["src/test", "src/test/test_data", "src/lib", "docs"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["src/test/test_a.rs", "src/lib/test_b.rs", "docs/test.md"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let mut filter = filter_for_test(root, root, IgnoreSources::NONE, true).with_prune(true);
+        let plan =
+            build_stdin_plan(&[root.to_path_buf()], Some("test"), QueryOptions::default()).unwrap();
+
+        // Children before parents, as Spotlight may report them.
+        let (out, stats) = run_consume(
+            &mut filter,
+            &plan,
+            &Criteria::new(CriteriaConfig::default()),
+            RunOptions::default(),
+            &[
+                "src/test/test_data",
+                "src/lib/test_b.rs",
+                "src/test/test_a.rs",
+                "docs/test.md",
+                "src/test",
+                "src/lib",
+            ],
+        );
+        assert_eq!(out, "docs/test.md\nsrc/lib/test_b.rs\nsrc/test\n");
+        assert_eq!(stats.rejected(Rejection::Pruned), 2);
+        assert_eq!(stats.rejected(Rejection::Pattern), 1);
+    }
+
+    #[test]
+    fn relative_to_repo_renders_against_the_innermost_repo_root() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        for repo in ["mono", "mono/vendor/lib"] {
+            fs::create_dir_all(root.join(repo).join(".git")).unwrap();
+            fs::write(root.join(repo).join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        }
+        let files = [
+            "notes/todo.txt",
+            "mono/top.txt",
+            "mono/pkg/src/a.txt",
+            "mono/vendor/lib/src/b.txt",
+        ];
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let mut filter = filter_for_test(&root.join("notes"), root, IgnoreSources::NONE, true);
+        let plan = build_stdin_plan(&[root.to_path_buf()], None, QueryOptions::default()).unwrap();
+        let out_style = output::OutputStyle::new(
+            root.join("notes"),
+            root.to_path_buf(),
+            None,
+            output::PathMode::RepoRelative,
+        )
+        .with_trailing_slash(true);
+
+        let mut candidates = files.to_vec();
+        // The nested repository's own directory belongs to the outer one.
+        candidates.push("mono/vendor");
+        let input = nul_separated(root, &candidates);
+        let mut out = Vec::new();
+        consume(
+            read_candidates(&mut &input[..], b'\0'),
+            &plan,
+            &mut [Root {
+                filter: &mut filter,
+                out_style: &out_style,
+            }],
+            &Criteria::new(CriteriaConfig::default()),
+            RunOptions::default(),
+            &mut output::PathWriter::new(&mut out, output::Delimiter::Newline),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "todo.txt\ntop.txt\npkg/src/a.txt\nsrc/b.txt\nvendor/\n"
+        );
+    }
+
+    #[test]
+    fn exec_batch_gets_each_duplicate_candidate_once() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        for name in ["b.txt", "a.txt", "c.txt"] {
+            fs::write(root.join(name), "").unwrap();
+        }
+        let args_file = root.join("args");
+        let argv: Vec<String> = [
+            "sh",
+            "-c",
+            r#"out="$1"; shift; printf '%s\n' "$@" > "$out""#,
+            "sh",
+            args_file.to_str().unwrap(),
+            "{}",
+        ]
+        .map(String::from)
+        .to_vec();
+        let plan =
+            build_stdin_plan(&[root.to_path_buf()], Some(".txt"), QueryOptions::default()).unwrap();
+        let out_style = relative_style(root, root);
+        let input = nul_separated(
+            root,
+            &["b.txt", "a.txt", "b.txt", "c.txt", "a.txt", "b.txt"],
+        );
+
+        for (sort, want) in [
+            (false, "b.txt\na.txt\nc.txt\n"),
+            (true, "a.txt\nb.txt\nc.txt\n"),
+        ] {
+            let mut filter = filter_for_test(root, root, IgnoreSources::NONE, true);
+            let batch = exec::Batch::new(
+                exec::CommandTemplate::new_batch(&argv).unwrap(),
+                exec::Launcher::Spawn,
+            );
+            let mut sink: Box<dyn output::Sink> = Box::new(batch);
+            if sort {
+                sink = Box::new(output::Sorted::new(sink, output::SortKey::Path, false));
+            }
+            let stats = consume(
+                read_candidates(&mut &input[..], b'\0'),
+                &plan,
+                &mut [Root {
+                    filter: &mut filter,
+                    out_style: &out_style,
+                }],
+                &Criteria::new(CriteriaConfig::default()),
+                RunOptions::default(),
+                &mut *sink,
+            )
+            .unwrap();
+            sink.finish().unwrap();
+
+            assert_eq!(stats.matched, 6);
+            assert_eq!(fs::read_to_string(&args_file).unwrap(), want, "sort={sort}");
+        }
+    }
+
+    #[test]
+    fn first_result_limit_applies_after_every_filter() {
+        // `--quiet` is a limit of one: only a candidate that passes the whole pipeline
+        // counts as found.
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(root.join(".gitignore"), "ignored-config\n").unwrap();
+        for name in [
+            ".config",
+            "ignored-config",
+            "config-dir/",
+            "config.txt",
+            "config.md",
+        ] {
+            match name.strip_suffix('/') {
+                Some(dir) => fs::create_dir_all(root.join(dir)).unwrap(),
+                None => fs::write(root.join(name), "").unwrap(),
+            }
+        }
+
+        let first_match = |input: &str| {
+            let mut filter = filter_for_test(root, root, IgnoreSources::ALL, true);
+            let criteria = Criteria::new(CriteriaConfig {
+                file_types: vec![FileType::File],
+                ..Default::default()
+            });
+            let plan = build_stdin_plan(
+                &[root.to_path_buf()],
+                Some("config"),
+                QueryOptions::default(),
+            )
+            .unwrap();
+            let out_style = relative_style(root, root);
+            run_stdin(
+                &mut input.as_bytes(),
+                &plan,
+                &mut [Root {
+                    filter: &mut filter,
+                    out_style: &out_style,
+                }],
+                &criteria,
+                RunOptions::default().with_limit(Some(1)),
+                &mut output::Discard,
+            )
+            .unwrap()
+        };
+
+        let stats = first_match(".config\nignored-config\nconfig-dir\nREADME\n");
+        assert_eq!(stats.matched, 0);
+        assert_eq!(stats.candidates, 4);
+
+        let stats = first_match(".config\nconfig.txt\nconfig.md\n");
+        assert_eq!(stats.matched, 1);
+        assert_eq!(stats.candidates, 2);
+    }
+}
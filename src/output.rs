@@ -1,10 +1,646 @@
 use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    fs,
     io::{self, Write},
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
 };
 
 #[cfg(unix)]
-use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+use crate::{
+    criteria,
+    ls_colors::LsColors,
+    template::{self, Token},
+};
+
+/// Destination for accepted results: stdout, or a command consuming them.
+pub trait Sink {
+    /// Handle one result. `rendered` is the path as `sf` would print it, and `meta` its
+    /// `lstat` from filtering (`None` if that failed).
+    fn accept(
+        &mut self,
+        abs_path: &Path,
+        rendered: &Path,
+        meta: Option<&fs::Metadata>,
+    ) -> anyhow::Result<()>;
+
+    /// Called once after the last result.
+    fn finish(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Push what has been written so far through to its destination.
+    fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Default sink: print each rendered path followed by the delimiter.
+pub struct PathWriter<'a> {
+    out: &'a mut dyn Write,
+    delimiter: Delimiter,
+    colors: Option<LsColors>,
+    separator: Option<OsString>,
+    format: Option<Format>,
+    details: bool,
+    hyperlinks: bool,
+    /// A colored write started and no final reset has been written since.
+    needs_reset: bool,
+}
+
+impl<'a> PathWriter<'a> {
+    pub fn new(out: &'a mut dyn Write, delimiter: Delimiter) -> Self {
+        Self {
+            out,
+            delimiter,
+            colors: None,
+            separator: None,
+            format: None,
+            details: false,
+            hyperlinks: false,
+            needs_reset: false,
+        }
+    }
+
+    /// Color each path with these styles (`--color`).
+    pub fn with_colors(mut self, colors: Option<LsColors>) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    /// Print `sep` in place of each `/` (`--path-separator`).
+    pub fn with_path_separator(mut self, sep: Option<OsString>) -> Self {
+        self.separator = sep;
+        self
+    }
+
+    /// Print each result through a `--format` template instead of as a bare path.
+    pub fn with_format(mut self, format: Option<Format>) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Prefix each path with its mode, size in bytes and modification time, like `ls -l`
+    /// (`--list-details`).
+    pub fn with_details(mut self, on: bool) -> Self {
+        self.details = on;
+        self
+    }
+
+    /// Wrap each path in an OSC 8 terminal hyperlink to its `file://` URI (`--hyperlink`).
+    pub fn with_hyperlinks(mut self, on: bool) -> Self {
+        self.hyperlinks = on;
+        self
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        if !self.needs_reset {
+            return Ok(());
+        }
+        self.needs_reset = false;
+        self.out.write_all(SGR_RESET)?;
+        self.out.flush()
+    }
+}
+
+impl Sink for PathWriter<'_> {
+    fn accept(
+        &mut self,
+        abs_path: &Path,
+        rendered: &Path,
+        meta: Option<&fs::Metadata>,
+    ) -> anyhow::Result<()> {
+        let sep = self.separator.as_deref();
+        if self.details {
+            write_details(self.out, meta)?;
+        }
+        if self.hyperlinks {
+            write!(self.out, "\x1b]8;;{}\x1b\\", file_uri(abs_path))?;
+        }
+        if let Some(format) = &self.format {
+            let line = PathBuf::from(format.render(rendered, sep));
+            write_bare_path(self.out, &line)?;
+        } else if let Some(colors) = &self.colors {
+            let styles = Styles {
+                parent: colors.dir_style(),
+                name: colors.style_for(abs_path, meta),
+            };
+            self.needs_reset |= styles.parent.is_some() || styles.name.is_some();
+            write_colored_path(self.out, rendered, styles, sep)?;
+        } else {
+            write_bare_path(self.out, &with_separator(rendered, sep))?;
+        }
+        if self.hyperlinks {
+            self.out.write_all(b"\x1b]8;;\x1b\\")?;
+        }
+        self.out.write_all(self.delimiter.as_bytes())?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.reset()?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for PathWriter<'_> {
+    /// An early exit (e.g. a broken pipe under `| head`) skips `finish`; still try not to
+    /// leave the terminal colored. Errors are moot by now.
+    fn drop(&mut self) {
+        let _ = self.reset();
+    }
+}
+
+/// `--json`/`--json-array`: one object per result, as NDJSON or a single array.
+///
+/// Each object has the printed `path`, the `absolute` path and `is_dir`. Paths that aren't
+/// valid UTF-8 are converted lossily and flagged with `"lossy": true`.
+pub struct JsonWriter<'a> {
+    out: &'a mut dyn Write,
+    array: bool,
+    written: u64,
+}
+
+impl<'a> JsonWriter<'a> {
+    pub fn new(out: &'a mut dyn Write, array: bool) -> Self {
+        Self {
+            out,
+            array,
+            written: 0,
+        }
+    }
+}
+
+impl Sink for JsonWriter<'_> {
+    fn accept(
+        &mut self,
+        abs_path: &Path,
+        rendered: &Path,
+        meta: Option<&fs::Metadata>,
+    ) -> anyhow::Result<()> {
+        if self.array {
+            self.out
+                .write_all(if self.written == 0 { b"[" } else { b"," })?;
+        }
+        self.written += 1;
+        let path = rendered.to_string_lossy();
+        let absolute = abs_path.to_string_lossy();
+        let is_dir = meta.is_some_and(|m| m.is_dir());
+        self.out.write_all(b"{\"path\":")?;
+        write_json_string(self.out, &path)?;
+        self.out.write_all(b",\"absolute\":")?;
+        write_json_string(self.out, &absolute)?;
+        write!(self.out, ",\"is_dir\":{is_dir}")?;
+        if matches!(path, Cow::Owned(_)) || matches!(absolute, Cow::Owned(_)) {
+            self.out.write_all(b",\"lossy\":true")?;
+        }
+        self.out.write_all(if self.array { b"}" } else { b"}\n" })?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if self.array {
+            self.out
+                .write_all(if self.written == 0 { b"[]\n" } else { b"]\n" })?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// `s` as a quoted JSON string.
+fn write_json_string(out: &mut dyn Write, s: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    let mut plain = 0;
+    for (i, c) in s.char_indices() {
+        let escaped: Cow<'_, str> = match c {
+            '"' => "\\\"".into(),
+            '\\' => "\\\\".into(),
+            '\n' => "\\n".into(),
+            '\r' => "\\r".into(),
+            '\t' => "\\t".into(),
+            c if c < ' ' => format!("\\u{:04x}", c as u32).into(),
+            _ => continue,
+        };
+        out.write_all(&s.as_bytes()[plain..i])?;
+        out.write_all(escaped.as_bytes())?;
+        plain = i + c.len_utf8();
+    }
+    out.write_all(&s.as_bytes()[plain..])?;
+    out.write_all(b"\"")
+}
+
+/// What `--count-by` groups results by.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, clap::ValueEnum)]
+pub enum CountKey {
+    /// The file name's extension, lowercased (`(none)` without one).
+    #[value(alias = "ext")]
+    Extension,
+    /// The entry type: file, directory, symlink, socket, pipe or other.
+    Type,
+}
+
+impl CountKey {
+    fn key(self, abs_path: &Path, meta: Option<&fs::Metadata>) -> String {
+        match self {
+            CountKey::Extension => abs_path.extension().map_or_else(
+                || "(none)".to_owned(),
+                |e| e.to_string_lossy().to_lowercase(),
+            ),
+            CountKey::Type => {
+                let Some(ft) = meta.map(fs::Metadata::file_type) else {
+                    return "other".to_owned();
+                };
+                let name = if ft.is_file() {
+                    "file"
+                } else if ft.is_dir() {
+                    "directory"
+                } else if ft.is_symlink() {
+                    "symlink"
+                } else {
+                    special_file_type(&ft)
+                };
+                name.to_owned()
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn special_file_type(ft: &fs::FileType) -> &'static str {
+    use std::os::unix::fs::FileTypeExt;
+
+    if ft.is_socket() {
+        "socket"
+    } else if ft.is_fifo() {
+        "pipe"
+    } else {
+        "other"
+    }
+}
+
+#[cfg(not(unix))]
+fn special_file_type(_ft: &fs::FileType) -> &'static str {
+    "other"
+}
+
+/// How `--count-by` prints its tally (`--tally-format`).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum TallyFormat {
+    /// `key: count` records.
+    #[default]
+    Human,
+    /// `key<TAB>count` records, with `\`, tabs and line breaks in keys escaped as `\\`,
+    /// `\t`, `\n` and `\r`.
+    Tsv,
+    /// A single array of `{"key": ..., "count": ...}` objects and a newline.
+    Json,
+}
+
+/// `--count-by`: tally results by a key and print one record per key at the end, most
+/// frequent first (ties by key, so the output is stable).
+pub struct CountBy<'a> {
+    out: &'a mut dyn Write,
+    key: CountKey,
+    delimiter: Delimiter,
+    format: TallyFormat,
+    counts: BTreeMap<String, u64>,
+}
+
+impl<'a> CountBy<'a> {
+    pub fn new(out: &'a mut dyn Write, key: CountKey, delimiter: Delimiter) -> Self {
+        Self {
+            out,
+            key,
+            delimiter,
+            format: TallyFormat::Human,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Print the tally as `format` (`--tally-format`). The records of `human` and `tsv`
+    /// end with the delimiter; `json` ignores it.
+    pub fn with_format(mut self, format: TallyFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl Sink for CountBy<'_> {
+    fn accept(
+        &mut self,
+        abs_path: &Path,
+        _rendered: &Path,
+        meta: Option<&fs::Metadata>,
+    ) -> anyhow::Result<()> {
+        *self.counts.entry(self.key.key(abs_path, meta)).or_default() += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let mut tally: Vec<_> = std::mem::take(&mut self.counts).into_iter().collect();
+        // Stable sort over the key-ordered map: equal counts stay in key order.
+        tally.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+        if self.format == TallyFormat::Json {
+            self.out.write_all(b"[")?;
+            for (i, (key, n)) in tally.iter().enumerate() {
+                self.out
+                    .write_all(if i == 0 { b"{\"key\":" } else { b",{\"key\":" })?;
+                write_json_string(self.out, key)?;
+                write!(self.out, ",\"count\":{n}}}")?;
+            }
+            self.out.write_all(b"]\n")?;
+            return Ok(());
+        }
+        for (key, n) in tally {
+            match self.format {
+                TallyFormat::Tsv => write!(self.out, "{}\t{n}", escape_tsv(&key))?,
+                _ => write!(self.out, "{key}: {n}")?,
+            }
+            self.out.write_all(self.delimiter.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// `field` with the characters that would break a TSV record backslash-escaped.
+fn escape_tsv(field: &str) -> Cow<'_, str> {
+    if !field.contains(['\\', '\t', '\n', '\r']) {
+        return Cow::Borrowed(field);
+    }
+    let mut out = String::with_capacity(field.len() + 2);
+    for c in field.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// What `--sort` orders results by.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, clap::ValueEnum)]
+pub enum SortKey {
+    /// The printed path, byte by byte.
+    Path,
+    /// The file name, then the printed path.
+    Name,
+    /// Size in bytes, smallest first.
+    Size,
+    /// Modification time, oldest first.
+    Mtime,
+}
+
+struct Buffered {
+    abs_path: PathBuf,
+    rendered: PathBuf,
+    meta: Option<fs::Metadata>,
+}
+
+/// `--sort`: hold every result until the search ends, then hand them to `inner` in order.
+///
+/// Size and time come from the `lstat` made while filtering; a result without one sorts
+/// first, as if empty and from the epoch.
+pub struct Sorted<'a> {
+    inner: Box<dyn Sink + 'a>,
+    key: SortKey,
+    reverse: bool,
+    results: Vec<Buffered>,
+}
+
+impl<'a> Sorted<'a> {
+    pub fn new(inner: Box<dyn Sink + 'a>, key: SortKey, reverse: bool) -> Self {
+        Self {
+            inner,
+            key,
+            reverse,
+            results: Vec::new(),
+        }
+    }
+}
+
+impl Sink for Sorted<'_> {
+    fn accept(
+        &mut self,
+        abs_path: &Path,
+        rendered: &Path,
+        meta: Option<&fs::Metadata>,
+    ) -> anyhow::Result<()> {
+        self.results.push(Buffered {
+            abs_path: abs_path.to_owned(),
+            rendered: rendered.to_owned(),
+            meta: meta.cloned(),
+        });
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let mut results = std::mem::take(&mut self.results);
+        let by_path =
+            |a: &Buffered, b: &Buffered| a.rendered.as_os_str().cmp(b.rendered.as_os_str());
+        match self.key {
+            SortKey::Path => results.sort_by(by_path),
+            SortKey::Name => results.sort_by(|a, b| {
+                a.rendered
+                    .file_name()
+                    .cmp(&b.rendered.file_name())
+                    .then_with(|| by_path(a, b))
+            }),
+            SortKey::Size => results.sort_by(|a, b| {
+                let size = |r: &Buffered| r.meta.as_ref().map_or(0, fs::Metadata::len);
+                size(a).cmp(&size(b)).then_with(|| by_path(a, b))
+            }),
+            SortKey::Mtime => results.sort_by(|a, b| {
+                let mtime = |r: &Buffered| r.meta.as_ref().and_then(|m| m.modified().ok());
+                mtime(a).cmp(&mtime(b)).then_with(|| by_path(a, b))
+            }),
+        }
+        if self.reverse {
+            results.reverse();
+        }
+        for r in &results {
+            self.inner
+                .accept(&r.abs_path, &r.rendered, r.meta.as_ref())?;
+        }
+        self.inner.finish()
+    }
+}
+
+/// `--flush-every`: the sink writes into a buffer, which is pushed through after every
+/// `every` results and at the end.
+pub struct Coalesced<'a> {
+    inner: Box<dyn Sink + 'a>,
+    every: u64,
+    pending: u64,
+}
+
+impl<'a> Coalesced<'a> {
+    pub fn new(inner: Box<dyn Sink + 'a>, every: u64) -> Self {
+        Self {
+            inner,
+            every,
+            pending: 0,
+        }
+    }
+}
+
+impl Sink for Coalesced<'_> {
+    fn accept(
+        &mut self,
+        abs_path: &Path,
+        rendered: &Path,
+        meta: Option<&fs::Metadata>,
+    ) -> anyhow::Result<()> {
+        self.inner.accept(abs_path, rendered, meta)?;
+        self.pending += 1;
+        if self.pending >= self.every {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.inner.finish()?;
+        self.flush()
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.pending = 0;
+        self.inner.flush()
+    }
+}
+
+/// `--max-buffer-time`: a buffered writer whose contents are never held longer than
+/// `max_wait`. A background thread writes them out once the oldest unwritten byte is that
+/// old, even while the search is waiting for its next result.
+///
+/// A failed background write is returned by the next `write` or `flush`.
+pub struct TimedBuffer {
+    shared: Arc<(Mutex<Pending>, Condvar)>,
+    flusher: Option<thread::JoinHandle<()>>,
+}
+
+struct Pending {
+    out: io::BufWriter<Box<dyn Write + Send>>,
+    /// When the oldest byte not yet written out was buffered.
+    since: Option<Instant>,
+    error: Option<io::Error>,
+    done: bool,
+}
+
+impl TimedBuffer {
+    pub fn new(out: impl Write + Send + 'static, max_wait: Duration) -> Self {
+        let pending = Pending {
+            out: io::BufWriter::new(Box::new(out)),
+            since: None,
+            error: None,
+            done: false,
+        };
+        let shared = Arc::new((Mutex::new(pending), Condvar::new()));
+        let flusher = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || flush_when_due(&shared, max_wait))
+        };
+        Self {
+            shared,
+            flusher: Some(flusher),
+        }
+    }
+
+    fn pending(&self) -> MutexGuard<'_, Pending> {
+        self.shared.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn flush_when_due((lock, wake): &(Mutex<Pending>, Condvar), max_wait: Duration) {
+    let mut pending = lock.lock().unwrap_or_else(PoisonError::into_inner);
+    while !pending.done {
+        let Some(since) = pending.since else {
+            pending = wake.wait(pending).unwrap_or_else(PoisonError::into_inner);
+            continue;
+        };
+        let now = Instant::now();
+        let due = since + max_wait;
+        if now < due {
+            pending = wake
+                .wait_timeout(pending, due - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+            continue;
+        }
+        pending.since = None;
+        if let Err(err) = pending.out.flush() {
+            pending.error.get_or_insert(err);
+        }
+    }
+}
+
+impl Write for TimedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pending = self.pending();
+        if let Some(err) = pending.error.take() {
+            return Err(err);
+        }
+        let n = pending.out.write(buf)?;
+        if n > 0 && pending.since.is_none() {
+            pending.since = Some(Instant::now());
+            self.shared.1.notify_one();
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut pending = self.pending();
+        if let Some(err) = pending.error.take() {
+            return Err(err);
+        }
+        pending.since = None;
+        pending.out.flush()
+    }
+}
+
+impl Drop for TimedBuffer {
+    /// Stops the flusher; dropping the `BufWriter` writes out whatever is left.
+    fn drop(&mut self) {
+        self.pending().done = true;
+        self.shared.1.notify_one();
+        if let Some(flusher) = self.flusher.take() {
+            let _ = flusher.join();
+        }
+    }
+}
+
+/// `--count`: results are only tallied (in `Stats`), never printed.
+pub struct Discard;
+
+impl Sink for Discard {
+    fn accept(
+        &mut self,
+        _abs_path: &Path,
+        _rendered: &Path,
+        _meta: Option<&fs::Metadata>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Delimiter {
@@ -12,31 +648,153 @@ pub enum Delimiter {
     Nul,
 }
 
+impl Delimiter {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Delimiter::Newline => b"\n",
+            Delimiter::Nul => b"\0",
+        }
+    }
+}
+
+/// How result paths are rendered.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PathMode {
+    /// fd-like: relative to the CWD, or prefixed with the `path` argument as given.
+    #[default]
+    Relative,
+    /// Always the absolute path (`--absolute-path`).
+    Absolute,
+    /// Relative to the enclosing git repository's root, or `Relative` outside any repo
+    /// (`--relative-to-repo`).
+    RepoRelative,
+}
+
+/// Whether relative results keep a leading `./` (`--strip-cwd-prefix`), like fd.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum StripCwdPrefix {
+    /// Strip it only when no `path` is given; an explicit `./dir` stays as typed.
+    #[default]
+    Auto,
+    Always,
+    /// Prefix every path below the current directory with `./`.
+    Never,
+}
+
 #[derive(Clone, Debug)]
 pub struct OutputStyle {
     cwd: PathBuf,
     search_base: PathBuf,
     path_arg: Option<PathBuf>,
+    base_directory: Option<PathBuf>,
+    mode: PathMode,
+    trailing_slash: bool,
+    strip_cwd_prefix: StripCwdPrefix,
 }
 
 impl OutputStyle {
-    pub fn new(cwd: PathBuf, search_base: PathBuf, path_arg: Option<&Path>) -> Self {
+    pub fn new(
+        cwd: PathBuf,
+        search_base: PathBuf,
+        path_arg: Option<&Path>,
+        mode: PathMode,
+    ) -> Self {
         Self {
             cwd,
             search_base,
             path_arg: path_arg.map(|p| p.to_path_buf()),
+            base_directory: None,
+            mode,
+            trailing_slash: false,
+            strip_cwd_prefix: StripCwdPrefix::Auto,
         }
     }
 
+    /// Whether relative paths keep a leading `./` (`--strip-cwd-prefix`).
+    pub fn with_strip_cwd_prefix(mut self, when: StripCwdPrefix) -> Self {
+        self.strip_cwd_prefix = when;
+        self
+    }
+
+    /// Print relative paths against this directory instead of the cwd or the typed `path`
+    /// (`--base-directory`). Results outside it are printed absolute.
+    pub fn with_base_directory(mut self, dir: Option<PathBuf>) -> Self {
+        self.base_directory = dir;
+        self
+    }
+
+    /// fd-style `dir/` output for directories (`--trailing-slash`).
+    pub fn with_trailing_slash(mut self, on: bool) -> Self {
+        self.trailing_slash = on;
+        self
+    }
+
+    /// Whether `render_entry_in` wants the repository root of each result.
+    pub fn is_repo_relative(&self) -> bool {
+        self.mode == PathMode::RepoRelative
+    }
+
+    /// `render`, plus the trailing slash for directories when enabled.
+    #[cfg(test)]
+    pub fn render_entry(&self, abs_path: &Path, is_dir: bool) -> PathBuf {
+        self.render_entry_in(abs_path, is_dir, None)
+    }
+
+    /// `render_entry` for a result inside the git repository at `repo_root`, which
+    /// `--relative-to-repo` renders against instead.
+    pub fn render_entry_in(
+        &self,
+        abs_path: &Path,
+        is_dir: bool,
+        repo_root: Option<&Path>,
+    ) -> PathBuf {
+        let in_repo = repo_root
+            .filter(|_| self.is_repo_relative())
+            .and_then(|root| abs_path.strip_prefix(root).ok())
+            .filter(|rel| !rel.as_os_str().is_empty());
+        let rendered = match in_repo {
+            Some(rel) => rel.to_path_buf(),
+            None => self.render(abs_path),
+        };
+        if !self.trailing_slash || !is_dir || ends_with_separator(&rendered) {
+            return rendered;
+        }
+        let mut s = rendered.into_os_string();
+        s.push(std::path::MAIN_SEPARATOR_STR);
+        PathBuf::from(s)
+    }
+
     pub fn render(&self, abs_path: &Path) -> PathBuf {
+        if self.mode == PathMode::Absolute {
+            return abs_path.to_path_buf();
+        }
+
+        let rendered = self.render_relative(abs_path);
+        match self.strip_cwd_prefix {
+            StripCwdPrefix::Auto => rendered,
+            StripCwdPrefix::Always => match rendered.strip_prefix(".") {
+                Ok(rest) if !rest.as_os_str().is_empty() => rest.to_path_buf(),
+                _ => rendered,
+            },
+            StripCwdPrefix::Never => match rendered.components().next() {
+                Some(Component::Normal(_)) => Path::new(".").join(rendered),
+                _ => rendered,
+            },
+        }
+    }
+
+    fn render_relative(&self, abs_path: &Path) -> PathBuf {
+        if let Some(dir) = &self.base_directory {
+            return strip_prefix_or_abs(abs_path, dir);
+        }
         match self.path_arg.as_deref() {
             None => {
                 // Omitted `path`: print relative to CWD, but without a leading "./".
                 strip_prefix_or_abs(abs_path, &self.cwd)
             }
-            Some(p) if p.is_absolute() => abs_path.to_path_buf(),
             Some(p) => {
-                // Explicit relative `path`: preserve the prefix (including "./" when `path` is ".").
+                // Explicit `path`: preserve the prefix as typed (including "./" when `path`
+                // is ".", or a symlink the canonical `search_base` resolved).
                 let rel_to_base = strip_prefix_or_abs(abs_path, &self.search_base);
                 if rel_to_base.as_os_str().is_empty() || rel_to_base == Path::new(".") {
                     return p.to_path_buf();
@@ -47,6 +805,18 @@ impl OutputStyle {
     }
 }
 
+fn ends_with_separator(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        path.as_os_str().as_bytes().ends_with(b"/")
+    }
+
+    #[cfg(not(unix))]
+    {
+        path.to_string_lossy().ends_with(std::path::is_separator)
+    }
+}
+
 fn strip_prefix_or_abs(path: &Path, base: &Path) -> PathBuf {
     if let Ok(rest) = path.strip_prefix(base) {
         if rest.as_os_str().is_empty() {
@@ -57,29 +827,237 @@ fn strip_prefix_or_abs(path: &Path, base: &Path) -> PathBuf {
     path.to_path_buf()
 }
 
-pub fn write_path(out: &mut dyn Write, path: &Path, delim: Delimiter) -> io::Result<()> {
-    let suffix: &[u8] = match delim {
-        Delimiter::Newline => b"\n",
-        Delimiter::Nul => b"\0",
+/// A `--format` template: text with fd's `{}`, `{/}`, `{//}`, `{.}` and `{/.}`, plus `{..}`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Format(Vec<Token>);
+
+impl Format {
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        Ok(Self(template::tokenize_strict(template)?))
+    }
+
+    /// The line for one result. `--path-separator` applies to the expanded paths only.
+    fn render(&self, path: &Path, sep: Option<&OsStr>) -> OsString {
+        let mut out = OsString::new();
+        for token in &self.0 {
+            match token {
+                Token::Text(text) => out.push(text),
+                Token::Placeholder(p) => {
+                    out.push(with_separator(Path::new(&p.expand(path)), sep).as_os_str())
+                }
+            }
+        }
+        out
+    }
+}
+
+/// When to color output (`--color`) or link it (`--hyperlink`), like fd.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum ColorWhen {
+    /// Only when stdout is a terminal and `NO_COLOR` is unset.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorWhen {
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorWhen::Always => true,
+            ColorWhen::Never => false,
+            ColorWhen::Auto => is_terminal && std::env::var_os("NO_COLOR").is_none(),
+        }
+    }
+}
+
+const SGR_RESET: &[u8] = b"\x1b[0m";
+
+/// `path` with every `/` replaced by `sep`, or unchanged without one.
+///
+/// Works on raw bytes: `/` never occurs inside a multibyte UTF-8 sequence, and non-UTF-8
+/// names pass through untouched.
+pub fn with_separator<'p>(path: &'p Path, sep: Option<&OsStr>) -> Cow<'p, Path> {
+    let Some(sep) = sep else {
+        return Cow::Borrowed(path);
     };
 
     #[cfg(unix)]
     {
-        out.write_all(path.as_os_str().as_bytes())?;
-        out.write_all(suffix)?;
+        let bytes = path.as_os_str().as_bytes();
+        if !bytes.contains(&b'/') {
+            return Cow::Borrowed(path);
+        }
+        let mut out = Vec::with_capacity(bytes.len());
+        for (i, part) in bytes.split(|&b| b == b'/').enumerate() {
+            if i > 0 {
+                out.extend_from_slice(sep.as_bytes());
+            }
+            out.extend_from_slice(part);
+        }
+        Cow::Owned(PathBuf::from(OsString::from_vec(out)))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let s = path.to_string_lossy().replace('/', &sep.to_string_lossy());
+        Cow::Owned(PathBuf::from(s))
+    }
+}
+
+/// `LS_COLORS` styles for one colored result.
+#[derive(Clone, Copy)]
+struct Styles<'c> {
+    /// The directories leading up to the name, separators included.
+    parent: Option<&'c str>,
+    name: Option<&'c str>,
+}
+
+/// `write_path` with the parent directories and the name each in their own style, like fd.
+fn write_colored_path(
+    out: &mut dyn Write,
+    path: &Path,
+    styles: Styles<'_>,
+    sep: Option<&OsStr>,
+) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let bytes = path.as_os_str().as_bytes();
+        // A trailing slash (`--trailing-slash`) belongs to the name, not the parent.
+        let search = bytes.strip_suffix(b"/").unwrap_or(bytes);
+        let (parent, name) = match search.iter().rposition(|&b| b == b'/') {
+            Some(i) => bytes.split_at(i + 1),
+            None => (&b""[..], bytes),
+        };
+        for (part, style) in [(parent, styles.parent), (name, styles.name)] {
+            if part.is_empty() {
+                continue;
+            }
+            let part = with_separator(Path::new(OsStr::from_bytes(part)), sep);
+            write_styled(out, part.as_os_str().as_bytes(), style)?;
+        }
         Ok(())
     }
 
     #[cfg(not(unix))]
     {
-        use std::fmt::Write as _;
+        let _ = styles;
+        write_bare_path(out, &with_separator(path, sep))
+    }
+}
+
+/// `file://` URI for `abs_path`, with every byte outside the unreserved set and `/`
+/// percent-encoded (non-UTF-8 bytes included).
+fn file_uri(abs_path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = abs_path.as_os_str().as_bytes();
+    #[cfg(not(unix))]
+    let lossy = abs_path.to_string_lossy();
+    #[cfg(not(unix))]
+    let bytes = lossy.as_bytes();
 
-        let mut s = String::new();
-        s.push_str(&path.display().to_string());
+    let mut uri = String::from("file://");
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            uri.push(char::from(b));
+        } else {
+            uri.push_str(&format!("%{b:02X}"));
+        }
+    }
+    uri
+}
+
+/// `drwxr-xr-x       4096 2024-01-31 12:00 ` for `--list-details`, with `?` for whatever
+/// the failed `lstat` can't tell. Times are UTC.
+fn write_details(out: &mut dyn Write, meta: Option<&fs::Metadata>) -> io::Result<()> {
+    let Some(meta) = meta else {
+        return write!(out, "{:<10} {:>10} {:<16} ", "?", "?", "?");
+    };
+    let mtime = meta.modified().map_or_else(|_| "?".to_owned(), format_utc);
+    write!(out, "{} {:>10} {mtime:<16} ", mode_string(meta), meta.len())
+}
+
+/// `ls`-style type and permission characters, e.g. `-rwxr-xr-x`.
+#[cfg(unix)]
+fn mode_string(meta: &fs::Metadata) -> String {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let ft = meta.file_type();
+    let kind = if ft.is_dir() {
+        'd'
+    } else if ft.is_symlink() {
+        'l'
+    } else if ft.is_fifo() {
+        'p'
+    } else if ft.is_socket() {
+        's'
+    } else if ft.is_block_device() {
+        'b'
+    } else if ft.is_char_device() {
+        'c'
+    } else {
+        '-'
+    };
+    let mode = meta.permissions().mode();
+    let mut s = String::with_capacity(10);
+    s.push(kind);
+    // (read, write, execute bit, special bit, special char) for user, group and other.
+    for (shift, special, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = (mode >> shift) & 0o7;
+        s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        s.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    s
+}
+
+#[cfg(not(unix))]
+fn mode_string(meta: &fs::Metadata) -> String {
+    let kind = if meta.is_dir() { 'd' } else { '-' };
+    let write = if meta.permissions().readonly() {
+        '-'
+    } else {
+        'w'
+    };
+    format!("{kind}r{write}-------")
+}
+
+/// `2024-01-31 12:00`, in UTC.
+fn format_utc(time: std::time::SystemTime) -> String {
+    let (date, hour, minute, _) = criteria::utc_fields(time);
+    format!("{date} {hour:02}:{minute:02}")
+}
+
+fn write_styled(out: &mut dyn Write, text: &[u8], style: Option<&str>) -> io::Result<()> {
+    let Some(style) = style else {
+        return out.write_all(text);
+    };
+    write!(out, "\x1b[{style}m")?;
+    out.write_all(text)?;
+    out.write_all(SGR_RESET)
+}
+
+pub fn write_path(out: &mut dyn Write, path: &Path, delim: Delimiter) -> io::Result<()> {
+    write_bare_path(out, path)?;
+    out.write_all(delim.as_bytes())
+}
+
+/// `path`'s bytes, without a delimiter.
+fn write_bare_path(out: &mut dyn Write, path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        out.write_all(path.as_os_str().as_bytes())
+    }
+
+    #[cfg(not(unix))]
+    {
         // Lossy for non-unix targets; `sf` is macOS-only anyway.
-        s.push_str(&String::from_utf8_lossy(suffix));
-        out.write_all(s.as_bytes())?;
-        Ok(())
+        out.write_all(path.display().to_string().as_bytes())
     }
 }
 
@@ -94,21 +1072,548 @@ mod tests {
         assert_eq!(buf, b"a b\0");
     }
 
+    #[test]
+    fn colored_output_colors_parents_and_ends_with_a_reset() {
+        let mut buf = Vec::new();
+        let mut writer = PathWriter::new(&mut buf, Delimiter::Newline)
+            .with_colors(Some(LsColors::parse("di=34")));
+        writer
+            .accept(Path::new("/x"), Path::new("src/lib.rs"), None)
+            .unwrap();
+        writer
+            .accept(Path::new("/y"), Path::new("Cargo.toml"), None)
+            .unwrap();
+        writer
+            .accept(Path::new("/z"), Path::new("a/b/"), None)
+            .unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        assert_eq!(
+            buf,
+            b"\x1b[34msrc/\x1b[0mlib.rs\nCargo.toml\n\x1b[34ma/\x1b[0mb/\n\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn colored_output_styles_the_name_by_its_metadata() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let file = tmp.path().join("lib.rs");
+        fs::write(&file, "").unwrap();
+        let meta = fs::symlink_metadata(&file).unwrap();
+
+        let mut buf = Vec::new();
+        let mut writer = PathWriter::new(&mut buf, Delimiter::Newline)
+            .with_colors(Some(LsColors::parse("di=34:*.rs=33")));
+        writer
+            .accept(&file, Path::new("src/lib.rs"), Some(&meta))
+            .unwrap();
+        writer
+            .accept(
+                tmp.path(),
+                Path::new("src/"),
+                fs::symlink_metadata(tmp.path()).ok().as_ref(),
+            )
+            .unwrap();
+        drop(writer);
+        assert_eq!(
+            buf,
+            b"\x1b[34msrc/\x1b[0m\x1b[33mlib.rs\x1b[0m\n\x1b[34msrc/\x1b[0m\n\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn json_writes_one_escaped_object_per_result() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let dir_meta = fs::symlink_metadata(tmp.path()).unwrap();
+        let results: [(&Path, &Path, Option<&fs::Metadata>); 2] = [
+            (Path::new("/a/src"), Path::new("src"), Some(&dir_meta)),
+            (
+                Path::new("/a/q\"\\\n\u{1}"),
+                Path::new("q\"\\\n\u{1}"),
+                None,
+            ),
+        ];
+
+        let mut buf = Vec::new();
+        let mut writer = JsonWriter::new(&mut buf, false);
+        for (abs, rendered, meta) in results {
+            writer.accept(abs, rendered, meta).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"path\":\"src\",\"absolute\":\"/a/src\",\"is_dir\":true}\n\
+             {\"path\":\"q\\\"\\\\\\n\\u0001\",\"absolute\":\"/a/q\\\"\\\\\\n\\u0001\",\"is_dir\":false}\n"
+        );
+
+        let mut buf = Vec::new();
+        let mut writer = JsonWriter::new(&mut buf, true);
+        writer.finish().unwrap();
+        assert_eq!(buf, b"[]\n");
+
+        let mut buf = Vec::new();
+        let mut writer = JsonWriter::new(&mut buf, true);
+        for (abs, rendered, meta) in results {
+            writer.accept(abs, rendered, meta).unwrap();
+        }
+        writer.finish().unwrap();
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.starts_with("[{\"path\":\"src\","), "{out}");
+        assert!(out.contains("},{\"path\":\"q"), "{out}");
+        assert!(out.ends_with("false}]\n"), "{out}");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn json_flags_lossy_paths() {
+        let raw = Path::new(OsStr::from_bytes(b"bad\xff"));
+        let mut buf = Vec::new();
+        JsonWriter::new(&mut buf, false)
+            .accept(Path::new("/a/b"), raw, None)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"path\":\"bad\u{fffd}\",\"absolute\":\"/a/b\",\"is_dir\":false,\"lossy\":true}\n"
+        );
+    }
+
+    #[test]
+    fn sorted_orders_by_key_and_reverses() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir(root.join("b")).unwrap();
+        let files = [("b/a.txt", 30), ("a.txt", 20), ("c", 10), ("a-z", 20)];
+        for (rel, len) in files {
+            fs::write(root.join(rel), vec![b'x'; len]).unwrap();
+        }
+
+        let sorted = |key, reverse| {
+            let mut buf = Vec::new();
+            let mut sink = Sorted::new(
+                Box::new(PathWriter::new(&mut buf, Delimiter::Newline)),
+                key,
+                reverse,
+            );
+            for (rel, _) in files {
+                let abs = root.join(rel);
+                let meta = fs::symlink_metadata(&abs).ok();
+                sink.accept(&abs, Path::new(rel), meta.as_ref()).unwrap();
+            }
+            sink.finish().unwrap();
+            drop(sink);
+            String::from_utf8(buf).unwrap()
+        };
+        // Bytes, not components: `-` (0x2d) sorts before `.` and `/`.
+        assert_eq!(sorted(SortKey::Path, false), "a-z\na.txt\nb/a.txt\nc\n");
+        assert_eq!(sorted(SortKey::Name, false), "a-z\na.txt\nb/a.txt\nc\n");
+        assert_eq!(sorted(SortKey::Size, false), "c\na-z\na.txt\nb/a.txt\n");
+        assert_eq!(sorted(SortKey::Size, true), "b/a.txt\na.txt\na-z\nc\n");
+    }
+
+    #[test]
+    fn coalesced_flushes_every_n_results_and_at_the_end() {
+        /// Records how many results had been written at each flush.
+        struct Flushes<'a>(Vec<u8>, &'a mut Vec<usize>);
+        impl Write for Flushes<'_> {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                self.1.push(self.0.iter().filter(|&&b| b == b'\n').count());
+                Ok(())
+            }
+        }
+
+        let mut flushes = Vec::new();
+        let mut out = Flushes(Vec::new(), &mut flushes);
+        let mut sink = Coalesced::new(Box::new(PathWriter::new(&mut out, Delimiter::Newline)), 2);
+        for name in ["a", "b", "c", "d", "e"] {
+            sink.accept(Path::new(name), Path::new(name), None).unwrap();
+        }
+        sink.finish().unwrap();
+        drop(sink);
+        assert_eq!(out.0, b"a\nb\nc\nd\ne\n");
+        assert_eq!(flushes, [2, 4, 5]);
+    }
+
+    #[test]
+    fn coalesced_reports_a_broken_pipe_from_the_flush() {
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+        }
+
+        let mut out = Closed;
+        let mut sink = Coalesced::new(Box::new(PathWriter::new(&mut out, Delimiter::Newline)), 1);
+        let err = sink
+            .accept(Path::new("a"), Path::new("a"), None)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<io::Error>().map(io::Error::kind),
+            Some(io::ErrorKind::BrokenPipe)
+        );
+    }
+
+    #[test]
+    fn count_by_tallies_most_frequent_first_with_ties_by_key() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let root = tmp.path();
+        fs::create_dir(root.join("src")).unwrap();
+        let rels = [
+            "src", "a.rs", "b.RS", "c.ts", "d.rs", "e.md", "Makefile", "f.ts",
+        ];
+        for rel in &rels[1..] {
+            fs::write(root.join(rel), "").unwrap();
+        }
+
+        let tally = |key, delimiter| {
+            let mut buf = Vec::new();
+            let mut sink = CountBy::new(&mut buf, key, delimiter);
+            for rel in rels {
+                let abs = root.join(rel);
+                let meta = fs::symlink_metadata(&abs).ok();
+                sink.accept(&abs, Path::new(rel), meta.as_ref()).unwrap();
+            }
+            sink.finish().unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            tally(CountKey::Extension, Delimiter::Newline),
+            "rs: 3\n(none): 2\nts: 2\nmd: 1\n"
+        );
+        assert_eq!(
+            tally(CountKey::Type, Delimiter::Nul),
+            "file: 7\0directory: 1\0"
+        );
+    }
+
+    #[test]
+    fn tally_formats_escape_keys() {
+        let tally = |format, delimiter| {
+            let mut buf = Vec::new();
+            let mut sink =
+                CountBy::new(&mut buf, CountKey::Extension, delimiter).with_format(format);
+            for name in ["a.rs", "b.rs", "c.t\tx", "d.a\"b", "e.c\\d", "f.n\nl"] {
+                sink.accept(Path::new(name), Path::new(name), None).unwrap();
+            }
+            sink.finish().unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            tally(TallyFormat::Tsv, Delimiter::Newline),
+            "rs\t2\na\"b\t1\nc\\\\d\t1\nn\\nl\t1\nt\\tx\t1\n"
+        );
+        assert_eq!(
+            tally(TallyFormat::Tsv, Delimiter::Nul),
+            "rs\t2\0a\"b\t1\0c\\\\d\t1\0n\\nl\t1\0t\\tx\t1\0"
+        );
+        let json = "[{\"key\":\"rs\",\"count\":2},{\"key\":\"a\\\"b\",\"count\":1},\
+                    {\"key\":\"c\\\\d\",\"count\":1},{\"key\":\"n\\nl\",\"count\":1},\
+                    {\"key\":\"t\\tx\",\"count\":1}]\n";
+        assert_eq!(tally(TallyFormat::Json, Delimiter::Newline), json);
+        assert_eq!(tally(TallyFormat::Json, Delimiter::Nul), json);
+
+        let mut buf = Vec::new();
+        let mut empty = CountBy::new(&mut buf, CountKey::Type, Delimiter::Newline)
+            .with_format(TallyFormat::Json);
+        empty.finish().unwrap();
+        assert_eq!(buf, b"[]\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn details_show_mode_size_and_utc_mtime_or_placeholders() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let file = tmp.path().join("run.sh");
+        fs::write(&file, "echo hi\n").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o4755)).unwrap();
+        let when = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_706_702_430);
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(when)
+            .unwrap();
+        let dir = tmp.path().join("src");
+        fs::create_dir(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o1770)).unwrap();
+
+        let mut buf = Vec::new();
+        let mut writer = PathWriter::new(&mut buf, Delimiter::Newline).with_details(true);
+        let meta = fs::symlink_metadata(&file).unwrap();
+        writer
+            .accept(&file, Path::new("run.sh"), Some(&meta))
+            .unwrap();
+        let meta = fs::symlink_metadata(&dir).unwrap();
+        writer.accept(&dir, Path::new("src/"), Some(&meta)).unwrap();
+        writer
+            .accept(Path::new("/gone"), Path::new("gone"), None)
+            .unwrap();
+        drop(writer);
+
+        let out = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "-rwsr-xr-x          8 2024-01-31 12:00 run.sh");
+        assert!(lines[1].starts_with("drwxrwx--T "), "{}", lines[1]);
+        assert!(lines[1].ends_with(" src/"), "{}", lines[1]);
+        assert_eq!(lines[2], "?                   ? ?                gone");
+        // Every column lines up.
+        assert!(
+            lines
+                .iter()
+                .all(|l| l.len() - l.rsplit(' ').next().unwrap().len() == 39)
+        );
+    }
+
+    #[test]
+    fn utc_dates_around_the_epoch_and_leap_days() {
+        let at = |secs: i64| {
+            let d = std::time::Duration::from_secs(secs.unsigned_abs());
+            format_utc(if secs < 0 {
+                std::time::UNIX_EPOCH - d
+            } else {
+                std::time::UNIX_EPOCH + d
+            })
+        };
+        assert_eq!(at(0), "1970-01-01 00:00");
+        assert_eq!(at(-60), "1969-12-31 23:59");
+        assert_eq!(at(951_782_400), "2000-02-29 00:00");
+        assert_eq!(at(1_709_251_199), "2024-02-29 23:59");
+        assert_eq!(at(4_102_444_800), "2100-01-01 00:00");
+    }
+
+    #[test]
+    fn colored_output_is_reset_even_without_finish() {
+        // The broken-pipe path: `accept` fails or is cut short and `finish` never runs.
+        let mut buf = Vec::new();
+        let mut writer = PathWriter::new(&mut buf, Delimiter::Newline)
+            .with_colors(Some(LsColors::parse("di=34")));
+        writer
+            .accept(Path::new("/x"), Path::new("src/lib.rs"), None)
+            .unwrap();
+        drop(writer);
+        assert!(buf.ends_with(b"\x1b[0m"));
+
+        let mut buf = Vec::new();
+        let mut writer = PathWriter::new(&mut buf, Delimiter::Newline);
+        writer
+            .accept(Path::new("/x"), Path::new("src/lib.rs"), None)
+            .unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        assert_eq!(buf, b"src/lib.rs\n");
+    }
+
+    #[test]
+    fn path_separator_replaces_only_separator_bytes() {
+        let backslash = Some(OsStr::new("\\"));
+        assert_eq!(
+            with_separator(Path::new("src/café/ü.rs"), backslash),
+            Path::new("src\\café\\ü.rs")
+        );
+        assert_eq!(
+            with_separator(Path::new("/abs/dir/"), Some(OsStr::new("::"))),
+            Path::new("::abs::dir::")
+        );
+        assert_eq!(with_separator(Path::new("src/a"), None), Path::new("src/a"));
+
+        // Bytes that aren't valid UTF-8 pass through untouched.
+        let raw = Path::new(OsStr::from_bytes(b"d\xff/\xc3x"));
+        assert_eq!(
+            with_separator(raw, backslash).as_os_str().as_bytes(),
+            b"d\xff\\\xc3x"
+        );
+
+        let mut buf = Vec::new();
+        let mut writer = PathWriter::new(&mut buf, Delimiter::Newline)
+            .with_path_separator(Some("\\".into()))
+            .with_colors(Some(LsColors::parse("di=34")));
+        writer
+            .accept(Path::new("/x"), Path::new("a/b/c"), None)
+            .unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        assert_eq!(buf, b"\x1b[34ma\\b\\\x1b[0mc\n\x1b[0m");
+    }
+
+    #[test]
+    fn format_expands_placeholders_in_place_of_the_path() {
+        let format = Format::parse("{/} in {//} ({/.}, {.}) {{}}").unwrap();
+        let mut buf = Vec::new();
+        let mut writer = PathWriter::new(&mut buf, Delimiter::Nul)
+            .with_format(Some(format))
+            .with_path_separator(Some(":".into()))
+            .with_colors(Some(LsColors::parse("di=34")));
+        writer
+            .accept(Path::new("/x"), Path::new("src/a/lib.rs"), None)
+            .unwrap();
+        writer
+            .accept(Path::new("/y"), Path::new("top"), None)
+            .unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        assert_eq!(
+            buf,
+            b"lib.rs in src:a (lib, src:a:lib) {}\0top in . (top, top) {}\0"
+        );
+
+        assert!(Format::parse("{path}").is_err());
+    }
+
+    #[test]
+    fn hyperlinks_wrap_the_path_but_not_the_delimiter() {
+        let mut buf = Vec::new();
+        let mut writer = PathWriter::new(&mut buf, Delimiter::Nul)
+            .with_hyperlinks(true)
+            .with_colors(Some(LsColors::parse("di=34")));
+        writer
+            .accept(Path::new("/Users/me/a b/c.rs"), Path::new("a b/c.rs"), None)
+            .unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        assert_eq!(
+            buf,
+            b"\x1b]8;;file:///Users/me/a%20b/c.rs\x1b\\\x1b[34ma b/\x1b[0mc.rs\x1b]8;;\x1b\\\0\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn file_uris_percent_encode_every_reserved_or_non_ascii_byte() {
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(
+            file_uri(Path::new("/tmp/r\u{e9}sum\u{e9} #1?&%.txt")),
+            "file:///tmp/r%C3%A9sum%C3%A9%20%231%3F%26%25.txt"
+        );
+        assert_eq!(
+            file_uri(Path::new(OsStr::from_bytes(b"/x/\xff~_-"))),
+            "file:///x/%FF~_-"
+        );
+    }
+
+    #[test]
+    fn format_follows_the_path_mode() {
+        let abs = Path::new("/Users/me/proj/src/lib.rs");
+        let format = || Format::parse("{} {//} {..}").unwrap();
+        for (mode, want) in [
+            (
+                PathMode::Absolute,
+                "/Users/me/proj/src/lib.rs /Users/me/proj/src /Users/me/proj\n",
+            ),
+            (PathMode::Relative, "src/lib.rs src .\n"),
+        ] {
+            let style = OutputStyle::new(
+                PathBuf::from("/Users/me/proj"),
+                PathBuf::from("/Users/me/proj"),
+                None,
+                mode,
+            );
+            let mut buf = Vec::new();
+            let mut writer =
+                PathWriter::new(&mut buf, Delimiter::Newline).with_format(Some(format()));
+            writer
+                .accept(abs, &style.render_entry(abs, false), None)
+                .unwrap();
+            drop(writer);
+            assert_eq!(String::from_utf8(buf).unwrap(), want, "{mode:?}");
+        }
+    }
+
     #[test]
     fn omitted_path_is_relative_to_cwd_without_dot_slash() {
-        let style = OutputStyle::new(PathBuf::from("/a/b"), PathBuf::from("/a/b"), None);
+        let style = OutputStyle::new(
+            PathBuf::from("/a/b"),
+            PathBuf::from("/a/b"),
+            None,
+            PathMode::Relative,
+        );
         assert_eq!(
             style.render(Path::new("/a/b/c/d.txt")),
             PathBuf::from("c/d.txt")
         );
     }
 
+    #[test]
+    fn search_root_equal_to_cwd_renders_per_mode() {
+        let cwd = Path::new("/a/b");
+        let render = |path_arg: Option<&Path>, mode| {
+            OutputStyle::new(cwd.to_path_buf(), cwd.to_path_buf(), path_arg, mode).render(cwd)
+        };
+        // Omitted `path` strips the cwd, which leaves nothing but `.` for the root.
+        assert_eq!(render(None, PathMode::Relative), PathBuf::from("."));
+        assert_eq!(
+            render(Some(Path::new(".")), PathMode::Relative),
+            PathBuf::from(".")
+        );
+        assert_eq!(render(None, PathMode::Absolute), PathBuf::from("/a/b"));
+        assert_eq!(
+            render(Some(Path::new(".")), PathMode::Absolute),
+            PathBuf::from("/a/b")
+        );
+    }
+
+    #[test]
+    fn strip_cwd_prefix_modes() {
+        let render = |path_arg: Option<&str>, when, abs: &str| {
+            OutputStyle::new(
+                PathBuf::from("/a/b"),
+                PathBuf::from("/a/b"),
+                path_arg.map(Path::new),
+                PathMode::Relative,
+            )
+            .with_strip_cwd_prefix(when)
+            .render(Path::new(abs))
+        };
+        for (path_arg, abs, auto, always, never) in [
+            (
+                Some("."),
+                "/a/b/c/d.txt",
+                "./c/d.txt",
+                "c/d.txt",
+                "./c/d.txt",
+            ),
+            (None, "/a/b/c/d.txt", "c/d.txt", "c/d.txt", "./c/d.txt"),
+            (
+                Some("./c"),
+                "/a/b/d.txt",
+                "./c/d.txt",
+                "c/d.txt",
+                "./c/d.txt",
+            ),
+            (Some(".."), "/a/b/d.txt", "../d.txt", "../d.txt", "../d.txt"),
+            // The search root itself stays `.`.
+            (Some("."), "/a/b", ".", ".", "."),
+            (None, "/a/b", ".", ".", "."),
+        ] {
+            for (when, want) in [
+                (StripCwdPrefix::Auto, auto),
+                (StripCwdPrefix::Always, always),
+                (StripCwdPrefix::Never, never),
+            ] {
+                assert_eq!(
+                    render(path_arg, when, abs),
+                    PathBuf::from(want),
+                    "{path_arg:?} {when:?} {abs}"
+                );
+            }
+        }
+    }
+
     #[test]
     fn explicit_dot_path_preserves_dot_slash_prefix() {
         let style = OutputStyle::new(
             PathBuf::from("/a/b"),
             PathBuf::from("/a/b"),
             Some(Path::new(".")),
+            PathMode::Relative,
         );
         assert_eq!(
             style.render(Path::new("/a/b/c.txt")),
@@ -122,6 +1627,7 @@ mod tests {
             PathBuf::from("/a/b"),
             PathBuf::from("/a/b/src"),
             Some(Path::new("src")),
+            PathMode::Relative,
         );
         assert_eq!(
             style.render(Path::new("/a/b/src/lib.rs")),
@@ -129,13 +1635,106 @@ mod tests {
         );
     }
 
+    #[test]
+    fn base_directory_is_the_relative_root_over_cwd_and_path() {
+        let abs = Path::new("/a/b/src/lib.rs");
+        for path_arg in [None, Some(Path::new(".")), Some(Path::new("b/src"))] {
+            let style = |mode| {
+                OutputStyle::new(PathBuf::from("/a"), PathBuf::from("/a/b"), path_arg, mode)
+                    .with_base_directory(Some(PathBuf::from("/a/b/src")))
+            };
+            assert_eq!(style(PathMode::Relative).render(abs), Path::new("lib.rs"));
+            // `--absolute-path` still wins.
+            assert_eq!(style(PathMode::Absolute).render(abs), abs);
+            // Outside the base directory there is no relative path to print.
+            assert_eq!(
+                style(PathMode::Relative).render(Path::new("/a/b/x.rs")),
+                Path::new("/a/b/x.rs")
+            );
+        }
+    }
+
     #[test]
     fn explicit_absolute_path_outputs_absolute() {
         let style = OutputStyle::new(
             PathBuf::from("/a/b"),
             PathBuf::from("/x/y"),
             Some(Path::new("/x/y")),
+            PathMode::Relative,
         );
         assert_eq!(style.render(Path::new("/x/y/z")), PathBuf::from("/x/y/z"));
     }
+
+    #[test]
+    fn absolute_mode_ignores_cwd_and_path_arg() {
+        let abs = Path::new("/a/b/src/lib.rs");
+        for path_arg in [None, Some(Path::new(".")), Some(Path::new("src"))] {
+            let style = OutputStyle::new(
+                PathBuf::from("/a/b"),
+                PathBuf::from("/a/b"),
+                path_arg,
+                PathMode::Absolute,
+            );
+            assert_eq!(style.render(abs), PathBuf::from("/a/b/src/lib.rs"));
+        }
+    }
+
+    #[test]
+    fn trailing_slash_marks_directories_only() {
+        let style = OutputStyle::new(
+            PathBuf::from("/a/b"),
+            PathBuf::from("/a/b"),
+            None,
+            PathMode::Relative,
+        )
+        .with_trailing_slash(true);
+        assert_eq!(
+            style.render_entry(Path::new("/a/b/src"), true),
+            PathBuf::from("src/")
+        );
+        assert_eq!(
+            style.render_entry(Path::new("/a/b/src/lib.rs"), false),
+            PathBuf::from("src/lib.rs")
+        );
+
+        let mut buf = Vec::new();
+        write_path(
+            &mut buf,
+            &style.render_entry(Path::new("/a/b/src"), true),
+            Delimiter::Nul,
+        )
+        .unwrap();
+        assert_eq!(buf, b"src/\0");
+    }
+
+    #[test]
+    fn trailing_slash_is_not_doubled_for_the_path_argument_itself() {
+        let style = OutputStyle::new(
+            PathBuf::from("/a/b"),
+            PathBuf::from("/a/b"),
+            Some(Path::new(".")),
+            PathMode::Relative,
+        )
+        .with_trailing_slash(true);
+        assert_eq!(
+            style.render_entry(Path::new("/a/b/c"), true),
+            PathBuf::from("./c/")
+        );
+        assert_eq!(
+            style.render_entry(Path::new("/a/b"), true),
+            PathBuf::from("./")
+        );
+
+        let style = OutputStyle::new(
+            PathBuf::from("/a"),
+            PathBuf::from("/a/b"),
+            Some(Path::new("b/")),
+            PathMode::Relative,
+        )
+        .with_trailing_slash(true);
+        assert_eq!(
+            style.render_entry(Path::new("/a/b"), true),
+            PathBuf::from("b/")
+        );
+    }
 }
@@ -0,0 +1,211 @@
+use std::{fs::Metadata, os::unix::fs::MetadataExt, str::FromStr};
+
+use anyhow::{Context, Result, bail};
+
+/// One side (user or group) of an `--owner` filter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Check {
+    Equal(u32),
+    NotEq(u32),
+    Ignore,
+}
+
+impl Check {
+    fn matches(self, id: u32) -> bool {
+        match self {
+            Check::Equal(want) => id == want,
+            Check::NotEq(unwanted) => id != unwanted,
+            Check::Ignore => true,
+        }
+    }
+}
+
+/// fd-style `--owner`: `user`, `user:group`, `:group`, with `!` negating either side and
+/// numeric ids accepted in place of names (e.g. `alice:!staff`, `!0`, `:20`). `!:group`
+/// is accepted as a spelling of `:!group`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OwnerFilter {
+    uid: Check,
+    gid: Check,
+}
+
+impl OwnerFilter {
+    /// Parse with custom name lookups (the `FromStr` impl uses the system databases).
+    fn parse_with(
+        s: &str,
+        user_id: impl Fn(&str) -> Result<Option<u32>>,
+        group_id: impl Fn(&str) -> Result<Option<u32>>,
+    ) -> Result<Self> {
+        let (user, group) = s.split_once(':').unwrap_or((s, ""));
+        // `!:group` reads as "not in group", the same as `:!group`.
+        let (user, group, negate_group) = match (user, group) {
+            ("!", g) if !g.is_empty() && !g.starts_with('!') => ("", g, true),
+            (u, g) => (u, g, false),
+        };
+        let uid = parse_check(user, "user", user_id)?;
+        let gid = match parse_check(group, "group", group_id)? {
+            Check::Equal(id) if negate_group => Check::NotEq(id),
+            check => check,
+        };
+        if uid == Check::Ignore && gid == Check::Ignore {
+            bail!("'{s}' is not a valid owner (expected user, user:group or :group)");
+        }
+        Ok(Self { uid, gid })
+    }
+
+    /// Whether an entry with this `lstat` has the wanted owner. Spotlight's copy of the
+    /// owner can be stale, so results are checked against the filesystem too.
+    pub fn matches(&self, meta: &Metadata) -> bool {
+        self.uid.matches(meta.uid()) && self.gid.matches(meta.gid())
+    }
+
+    /// Spotlight predicate clauses, AND-ed together.
+    pub fn predicate(&self) -> String {
+        let clauses: Vec<String> = [
+            ("kMDItemFSOwnerUserID", self.uid),
+            ("kMDItemFSOwnerGroupID", self.gid),
+        ]
+        .into_iter()
+        .filter_map(|(attr, check)| match check {
+            Check::Equal(id) => Some(format!("{attr} == {id}")),
+            Check::NotEq(id) => Some(format!("{attr} != {id}")),
+            Check::Ignore => None,
+        })
+        .collect();
+        clauses.join(" && ")
+    }
+}
+
+impl FromStr for OwnerFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse_with(
+            s,
+            |name| {
+                let user = nix::unistd::User::from_name(name)
+                    .with_context(|| format!("failed to look up user '{name}'"))?;
+                Ok(user.map(|u| u.uid.as_raw()))
+            },
+            |name| {
+                let group = nix::unistd::Group::from_name(name)
+                    .with_context(|| format!("failed to look up group '{name}'"))?;
+                Ok(group.map(|g| g.gid.as_raw()))
+            },
+        )
+    }
+}
+
+fn parse_check(
+    spec: &str,
+    what: &str,
+    lookup: impl Fn(&str) -> Result<Option<u32>>,
+) -> Result<Check> {
+    let (negated, name) = match spec.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, spec),
+    };
+    if name.is_empty() {
+        if negated {
+            bail!("'!' must be followed by a {what} name or id");
+        }
+        return Ok(Check::Ignore);
+    }
+    let id = match name.parse::<u32>() {
+        Ok(id) => id,
+        Err(_) => lookup(name)?.with_context(|| format!("'{name}' is not a known {what}"))?,
+    };
+    Ok(if negated {
+        Check::NotEq(id)
+    } else {
+        Check::Equal(id)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<OwnerFilter> {
+        OwnerFilter::parse_with(
+            s,
+            |name| Ok((name == "alice").then_some(501)),
+            |name| Ok((name == "staff").then_some(20)),
+        )
+    }
+
+    #[test]
+    fn every_form_builds_the_expected_predicate() {
+        let cases = [
+            ("alice", "kMDItemFSOwnerUserID == 501"),
+            ("alice:", "kMDItemFSOwnerUserID == 501"),
+            ("!alice", "kMDItemFSOwnerUserID != 501"),
+            (":staff", "kMDItemFSOwnerGroupID == 20"),
+            ("!:staff", "kMDItemFSOwnerGroupID != 20"),
+            (":!staff", "kMDItemFSOwnerGroupID != 20"),
+            (
+                "alice:staff",
+                "kMDItemFSOwnerUserID == 501 && kMDItemFSOwnerGroupID == 20",
+            ),
+            (
+                "alice:!staff",
+                "kMDItemFSOwnerUserID == 501 && kMDItemFSOwnerGroupID != 20",
+            ),
+            (
+                "!alice:!staff",
+                "kMDItemFSOwnerUserID != 501 && kMDItemFSOwnerGroupID != 20",
+            ),
+            ("0", "kMDItemFSOwnerUserID == 0"),
+            (
+                "!1000:80",
+                "kMDItemFSOwnerUserID != 1000 && kMDItemFSOwnerGroupID == 80",
+            ),
+        ];
+        for (spec, want) in cases {
+            assert_eq!(parse(spec).unwrap().predicate(), want, "{spec}");
+        }
+    }
+
+    #[test]
+    fn unknown_names_and_empty_specs_are_clear_errors() {
+        let err = parse("bob").unwrap_err().to_string();
+        assert_eq!(err, "'bob' is not a known user");
+        let err = parse("alice:wheel").unwrap_err().to_string();
+        assert_eq!(err, "'wheel' is not a known group");
+        assert!(parse(":").is_err());
+        assert!(parse("").is_err());
+        assert!(parse("!").is_err());
+    }
+
+    #[test]
+    fn matches_compares_the_owner_ids_of_the_metadata() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let meta = tmp.as_file().metadata().unwrap();
+        let (uid, gid) = (meta.uid(), meta.gid());
+        let other = |id: u32| id.wrapping_add(1);
+
+        for (spec, want) in [
+            (format!("{uid}"), true),
+            (format!("!{uid}"), false),
+            (format!("{}", other(uid)), false),
+            (format!("!{}", other(uid)), true),
+            (format!(":{gid}"), true),
+            (format!(":!{gid}"), false),
+            (format!("!:{}", other(gid)), true),
+            (format!("{uid}:{gid}"), true),
+            (format!("{uid}:{}", other(gid)), false),
+            (format!("{}:{gid}", other(uid)), false),
+        ] {
+            assert_eq!(parse(&spec).unwrap().matches(&meta), want, "{spec}");
+        }
+    }
+
+    #[test]
+    fn system_lookup_resolves_root() {
+        assert_eq!(
+            "root".parse::<OwnerFilter>().unwrap().predicate(),
+            "kMDItemFSOwnerUserID == 0"
+        );
+        assert!("no-such-user-for-sf-tests".parse::<OwnerFilter>().is_err());
+    }
+}
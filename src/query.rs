@@ -1,8 +1,18 @@
 use std::{
+    borrow::Cow,
     ffi::{OsStr, OsString},
-    path::Path,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
+use anyhow::{Context, Result};
+use globset::{Glob, GlobBuilder, GlobMatcher};
+use unicode_normalization::{
+    IsNormalized, UnicodeNormalization, char::is_combining_mark, is_nfc_quick,
+};
+
+use crate::{criteria::format_iso_utc, owner::OwnerFilter};
+
 #[derive(Debug, Clone)]
 pub struct QueryPlan {
     pub args: Vec<OsString>,
@@ -13,80 +23,732 @@ pub struct QueryPlan {
     pub rust_matcher: Option<RustMatcher>,
 }
 
+/// How letter case is treated when matching the pattern.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CaseMode {
+    /// fd-like smart case: case-insensitive unless the pattern contains uppercase (or a
+    /// `[:upper:]`/`[:lower:]` class, which would mean nothing without case).
+    #[default]
+    Smart,
+    /// Always case-sensitive (`--case-sensitive`).
+    Sensitive,
+    /// Always case-insensitive (`--ignore-case`).
+    Insensitive,
+}
+
+impl CaseMode {
+    pub fn is_case_sensitive(self, pattern: &str) -> bool {
+        match self {
+            CaseMode::Smart => {
+                has_uppercase(pattern)
+                    || pattern.contains("[:upper:]")
+                    || pattern.contains("[:lower:]")
+            }
+            CaseMode::Sensitive => true,
+            CaseMode::Insensitive => false,
+        }
+    }
+}
+
+/// How the pattern is interpreted.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PatternSyntax {
+    /// A glob if it contains `*`, `?` or a POSIX class, otherwise a substring.
+    #[default]
+    Auto,
+    /// Always a glob, matched against the whole file name (`--glob`).
+    Glob,
+    /// Always a literal substring, even with `*` or `?` in it (`--fixed-strings`).
+    Fixed,
+}
+
+impl PatternSyntax {
+    fn is_glob(self, pattern: &str) -> bool {
+        match self {
+            PatternSyntax::Auto => is_glob(pattern),
+            PatternSyntax::Glob => true,
+            PatternSyntax::Fixed => false,
+        }
+    }
+}
+
+/// Knobs that change how the user's pattern is interpreted and what Spotlight is asked for.
+#[derive(Clone, Debug, Default)]
+pub struct QueryOptions {
+    /// Match the pattern against the path relative to the search base instead of the
+    /// basename (`--full-path`). Globs containing `/` imply this.
+    pub full_path: bool,
+    /// Match the pattern against the file stem, the name without its last extension
+    /// (`--match-stem`).
+    pub match_stem: bool,
+    pub case: CaseMode,
+    pub syntax: PatternSyntax,
+    /// Ignore accents and other diacritics (`--ignore-diacritics`), so `cafe` matches
+    /// `café`.
+    pub ignore_diacritics: bool,
+    /// Only match items conforming to any of these UTIs (`--content-type`, `--kind`).
+    pub content_types: Vec<String>,
+    /// Only match items with this owner (`--owner`).
+    pub owner: Option<OwnerFilter>,
+    /// Only match items carrying every one of these Finder tags (`--tag`).
+    pub tags: Vec<String>,
+    /// Only match items Spotlight records as modified at or after this instant
+    /// (`--changed-within`), less `time_slop`.
+    pub changed_after: Option<SystemTime>,
+    /// Only match items Spotlight records as modified before this instant
+    /// (`--changed-before`), plus `time_slop`.
+    pub changed_before: Option<SystemTime>,
+    /// How far to widen the modification-time window (`--time-slop`), so items whose
+    /// index entry lags behind the file system aren't left out. The exact window is
+    /// checked against `lstat` afterwards.
+    pub time_slop: Duration,
+    /// Only match items whose indexed text contains this, ignoring case (`--content`).
+    pub content: Option<String>,
+    /// A Spotlight predicate to use as is instead of one built from the pattern
+    /// (`--query`). Only used without a pattern; other clauses still narrow it.
+    pub raw_query: Option<String>,
+    /// Reject items matching any of these patterns (`--not`). Each is interpreted like the
+    /// main pattern, with its own smart case.
+    pub not_patterns: Vec<String>,
+    /// Only match items also matching every one of these patterns (`--and`). Each is
+    /// interpreted like the main pattern, with its own smart case.
+    pub and_patterns: Vec<String>,
+    /// Match whole words only (`--word`): Spotlight's `w` modifier for `--content`, and a
+    /// word-boundary check for plain (non-glob) name patterns.
+    pub word: bool,
+}
+
+impl QueryOptions {
+    /// A Spotlight predicate for the names pattern `p` could match, if it can narrow the
+    /// query: full-path patterns and globs Spotlight can't evaluate have none. The
+    /// Rust-side matcher still enforces `p` exactly.
+    fn name_clause(&self, p: &str) -> Option<String> {
+        if self.full_path {
+            return None;
+        }
+        let mods = Modifiers {
+            case_insensitive: !self.case.is_case_sensitive(p),
+            diacritic_insensitive: self.ignore_diacritics,
+        };
+        if !self.syntax.is_glob(p) {
+            // As for the main pattern, `?` stands in for wildcards Spotlight can't escape.
+            Some(name_predicate(
+                &format!("*{}*", p.replace(['*', '?'], "?")),
+                mods,
+            ))
+        } else if is_simple_name_glob(p) && !spans_directories(p) {
+            Some(name_predicate(p, mods))
+        } else {
+            None
+        }
+    }
+
+    /// Attribute clauses to AND with the name predicate, which rules out `mdfind -name`.
+    fn extra_clauses(&self) -> Vec<String> {
+        let mut clauses = Vec::new();
+        if !self.content_types.is_empty() {
+            let types = self
+                .content_types
+                .iter()
+                .map(|uti| format!("kMDItemContentTypeTree == \"{}\"", escape_query_string(uti)))
+                .collect::<Vec<_>>()
+                .join(" || ");
+            clauses.push(types);
+        }
+        if let Some(owner) = &self.owner {
+            clauses.push(owner.predicate());
+        }
+        clauses.extend(
+            self.and_patterns
+                .iter()
+                .filter_map(|p| self.name_clause(&nfc(p))),
+        );
+        if let Some(after) = self
+            .changed_after
+            .and_then(|t| t.checked_sub(self.time_slop))
+        {
+            clauses.push(format!(
+                "kMDItemFSContentChangeDate >= $time.iso({})",
+                format_iso_utc(after)
+            ));
+        }
+        if let Some(before) = self
+            .changed_before
+            .and_then(|t| t.checked_add(self.time_slop))
+        {
+            clauses.push(format!(
+                "kMDItemFSContentChangeDate <= $time.iso({})",
+                format_iso_utc(before)
+            ));
+        }
+        for tag in &self.tags {
+            clauses.push(format!(
+                "kMDItemUserTags == \"{}\"",
+                escape_query_string(tag)
+            ));
+        }
+        if let Some(text) = &self.content {
+            let text = escape_query_string(text);
+            clauses.push(if self.word {
+                format!("kMDItemTextContent == \"{text}\"cw")
+            } else {
+                format!("kMDItemTextContent == \"*{text}*\"c")
+            });
+        }
+        clauses
+    }
+}
+
+/// The pattern checks done in Rust, as a tree of `All`, `Any` and `Not` over leaf matchers.
+///
+/// Matchers only ever see the path: they run before the candidate's `lstat`, so anything
+/// they reject is never stat'ed. `-p` is covered by the `FullPath*` leaves; `--extension`
+/// and the filters that need metadata (type, size, time, owner) stay in `Criteria`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RustMatcher {
-    CaseSensitiveSubstring { needle: String },
+    CaseSensitiveSubstring {
+        needle: String,
+    },
+    /// `needle` is stored lowercased.
+    CaseInsensitiveSubstring {
+        needle: String,
+    },
+    /// Smart-case substring match against the path relative to the closest of `bases`.
+    FullPathSubstring {
+        bases: Vec<PathBuf>,
+        needle: String,
+        case_sensitive: bool,
+    },
+    /// Glob match against the path relative to the closest of `bases`. `*` and `?` don't
+    /// cross `/`.
+    FullPathGlob {
+        bases: Vec<PathBuf>,
+        glob: PathGlob,
+    },
+    /// Smart-case glob match against the file name: for globs Spotlight can't evaluate
+    /// (bracket expressions, POSIX classes, alternations), and to check the ones it does.
+    NameGlob {
+        glob: PathGlob,
+    },
+    /// Strip diacritics from the candidate path before handing it to the inner matcher,
+    /// which must have been built from a folded pattern (and base).
+    IgnoreDiacritics(Box<RustMatcher>),
+    /// `needle` appears in the file name as a whole word: not directly preceded or
+    /// followed by a letter or digit (`--word`). Stored lowercased unless case-sensitive.
+    NameWord {
+        needle: String,
+        case_sensitive: bool,
+    },
+    /// Hand the candidate to the inner matcher without its last extension, so name
+    /// matchers see the file stem (`--match-stem`).
+    Stem(Box<RustMatcher>),
+    /// Candidates the inner matcher rejects (`--not`).
+    Not(Box<RustMatcher>),
+    /// Candidates every one of the matchers accepts.
+    All(Vec<RustMatcher>),
+    /// Candidates at least one of the matchers accepts.
+    Any(Vec<RustMatcher>),
 }
 
 impl RustMatcher {
+    /// `path` is compared in NFC, like the pattern the matcher was built from.
     pub fn matches(&self, path: &Path) -> bool {
+        let path = &*nfc_path(path);
         match self {
             RustMatcher::CaseSensitiveSubstring { needle } => path
                 .file_name()
                 .and_then(OsStr::to_str)
                 .is_some_and(|name| name.contains(needle)),
+            RustMatcher::CaseInsensitiveSubstring { needle } => path
+                .file_name()
+                .and_then(OsStr::to_str)
+                .is_some_and(|name| name.to_lowercase().contains(needle.as_str())),
+            RustMatcher::FullPathSubstring {
+                bases,
+                needle,
+                case_sensitive,
+            } => relative_to(path, bases).to_str().is_some_and(|rel| {
+                if *case_sensitive {
+                    rel.contains(needle.as_str())
+                } else {
+                    rel.to_lowercase().contains(&needle.to_lowercase())
+                }
+            }),
+            RustMatcher::FullPathGlob { bases, glob } => {
+                glob.matcher.is_match(relative_to(path, bases))
+            }
+            RustMatcher::NameGlob { glob } => path
+                .file_name()
+                .is_some_and(|name| glob.matcher.is_match(name)),
+            RustMatcher::IgnoreDiacritics(inner) => path
+                .to_str()
+                .is_some_and(|s| inner.matches(Path::new(&fold_diacritics(s)))),
+            RustMatcher::NameWord {
+                needle,
+                case_sensitive,
+            } => path
+                .file_name()
+                .and_then(OsStr::to_str)
+                .is_some_and(|name| {
+                    if *case_sensitive {
+                        contains_word(name, needle)
+                    } else {
+                        contains_word(&name.to_lowercase(), needle)
+                    }
+                }),
+            RustMatcher::Stem(inner) => inner.matches(&path.with_extension("")),
+            RustMatcher::Not(inner) => !inner.matches(path),
+            RustMatcher::All(matchers) => matchers.iter().all(|m| m.matches(path)),
+            RustMatcher::Any(matchers) => matchers.iter().any(|m| m.matches(path)),
         }
     }
 }
 
+/// Whether `needle` occurs in `haystack` with no letter or digit right before or after it.
+fn contains_word(haystack: &str, needle: &str) -> bool {
+    haystack.match_indices(needle).any(|(start, _)| {
+        let before = haystack[..start].chars().next_back();
+        let after = haystack[start + needle.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// Compose to NFC, so a pattern typed as `café` matches a name stored decomposed (as
+/// HFS+ does) and vice versa.
+fn nfc(s: &str) -> Cow<'_, str> {
+    if is_nfc_quick(s.chars()) == IsNormalized::Yes {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(s.nfc().collect())
+    }
+}
+
+/// `nfc` for paths; non-UTF-8 paths are left alone.
+fn nfc_path(path: &Path) -> Cow<'_, Path> {
+    match path.to_str().map(nfc) {
+        Some(Cow::Owned(s)) => Cow::Owned(PathBuf::from(s)),
+        _ => Cow::Borrowed(path),
+    }
+}
+
+/// Decompose and drop combining marks: `café` (either NFC or NFD) becomes `cafe`.
+fn fold_diacritics(s: &str) -> String {
+    s.nfd().filter(|c| !is_combining_mark(*c)).collect()
+}
+
+/// A compiled glob that compares equal by its source pattern (so plans stay comparable
+/// in tests).
+#[derive(Debug, Clone)]
+pub struct PathGlob {
+    glob: Glob,
+    matcher: GlobMatcher,
+}
+
+impl PathGlob {
+    fn new(pattern: &str, case_sensitive: bool) -> Result<Self> {
+        let expanded = expand_posix_classes(pattern)?;
+        let glob = GlobBuilder::new(&expanded)
+            .case_insensitive(!case_sensitive)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("invalid glob pattern: {pattern}"))?;
+        let matcher = glob.compile_matcher();
+        Ok(Self { glob, matcher })
+    }
+}
+
+/// Rewrite POSIX character classes (`[[:digit:]]`) into ranges, since globset only knows
+/// plain bracket expressions. Classes are ASCII-only, as in the C locale.
+fn expand_posix_classes(pattern: &str) -> Result<Cow<'_, str>> {
+    if !pattern.contains("[:") {
+        return Ok(Cow::Borrowed(pattern));
+    }
+
+    let mut out = String::with_capacity(pattern.len());
+    let mut in_class = false;
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        if in_class && let Some(after) = rest.strip_prefix("[:") {
+            let end = after
+                .find(":]")
+                .with_context(|| format!("unterminated character class in: {pattern}"))?;
+            let name = &after[..end];
+            let ranges = posix_class_ranges(name)
+                .with_context(|| format!("unknown character class [:{name}:] in: {pattern}"))?;
+            out.push_str(ranges);
+            rest = &after[end + 2..];
+            continue;
+        }
+
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '\\' if !in_class => {
+                if let Some(escaped) = rest.chars().next() {
+                    out.push(escaped);
+                    rest = &rest[escaped.len_utf8()..];
+                }
+            }
+            '[' if !in_class => {
+                in_class = true;
+                // A leading `!` negates, and a `]` right after that is a literal member.
+                for special in ['!', ']'] {
+                    if let Some(r) = rest.strip_prefix(special) {
+                        out.push(special);
+                        rest = r;
+                    }
+                }
+            }
+            ']' if in_class => in_class = false,
+            _ => {}
+        }
+    }
+    Ok(Cow::Owned(out))
+}
+
+fn posix_class_ranges(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "a-zA-Z",
+        "digit" => "0-9",
+        "alnum" => "a-zA-Z0-9",
+        "upper" => "A-Z",
+        "lower" => "a-z",
+        "xdigit" => "0-9a-fA-F",
+        "space" => " \t\n\r\x0b\x0c",
+        "blank" => " \t",
+        _ => return None,
+    })
+}
+
+impl PartialEq for PathGlob {
+    fn eq(&self, other: &Self) -> bool {
+        self.glob == other.glob
+    }
+}
+
+impl Eq for PathGlob {}
+
+/// `path` relative to the longest of `bases` containing it (search paths may nest).
+fn relative_to<'a>(path: &'a Path, bases: &[PathBuf]) -> &'a Path {
+    bases
+        .iter()
+        .filter_map(|base| path.strip_prefix(base).ok())
+        .min_by_key(|rel| rel.components().count())
+        .unwrap_or(path)
+}
+
 /// Build a query plan for `mdfind`.
 ///
-/// We prefer `mdfind -name <pattern>` for non-glob patterns because it has
+/// We prefer `mdfind -name <pattern>` for substrings and simple globs because it has
 /// dramatically lower fixed overhead than a full predicate query on many systems.
 ///
 /// Example (shell):
 /// `mdfind -onlyin $BASE -name Cargo.toml`
-pub fn build_mdfind_plan(base: &Path, pattern: Option<&str>) -> QueryPlan {
+///
+/// Each of `bases` gets its own `-onlyin`. Full-path matching can't be expressed against
+/// `kMDItemFSName`, so those plans widen the Spotlight query and rely on a Rust-side
+/// matcher instead.
+pub fn build_mdfind_plan(
+    bases: &[PathBuf],
+    pattern: Option<&str>,
+    opts: QueryOptions,
+) -> Result<QueryPlan> {
     // Always request NUL-separated output from `mdfind` so we can parse paths robustly
     // (paths may contain newlines).
-    let mut args = vec![
-        OsString::from("-0"),
-        OsString::from("-onlyin"),
-        OsString::from(base.as_os_str()),
-    ];
+    let mut args = vec![OsString::from("-0")];
+    for base in bases {
+        args.push(OsString::from("-onlyin"));
+        args.push(OsString::from(base.as_os_str()));
+    }
 
-    match pattern {
-        // "List everything": stick with a predicate query. `-name` doesn't accept globs
-        // like `*` in a way we can rely on.
-        None => {
-            args.push(OsString::from(build_query(None)));
-            QueryPlan {
-                args,
-                rust_matcher: None,
-            }
-        }
-        Some(p) if is_glob(p) => {
-            args.push(OsString::from(build_query(Some(p))));
-            QueryPlan {
-                args,
-                rust_matcher: None,
+    let Some(p) = pattern else {
+        // "List everything" (or whatever the raw query asks for): stick with a predicate
+        // query. `-name` doesn't accept globs like `*` in a way we can rely on.
+        let query = match &opts.raw_query {
+            Some(raw) => raw.clone(),
+            None => build_query(None, Modifiers::default()),
+        };
+        args.push(OsString::from(query));
+        restrict(&mut args, &opts.extra_clauses());
+        return Ok(QueryPlan {
+            args,
+            rust_matcher: with_extra_patterns(None, bases, &opts)?,
+        });
+    };
+
+    let p = &*nfc(p);
+    if opts.match_stem {
+        return stem_plan(bases, p, opts);
+    }
+    let glob = opts.syntax.is_glob(p);
+    let case_sensitive = opts.case.is_case_sensitive(p);
+    let mods = Modifiers {
+        case_insensitive: !case_sensitive,
+        diacritic_insensitive: opts.ignore_diacritics,
+    };
+
+    let (match_pattern, match_bases) = matcher_inputs(bases, p, &opts);
+
+    let rust_matcher = if glob && (opts.full_path || spans_directories(p)) {
+        // Spotlight only knows basenames. If the last path segment is a plain
+        // glob, it still narrows the candidate set; otherwise ask for everything.
+        let last = p.rsplit('/').next().unwrap_or(p);
+        let name_query = if is_simple_name_glob(last) {
+            name_predicate(last, mods)
+        } else {
+            build_query(None, Modifiers::default())
+        };
+        args.push(OsString::from(name_query));
+        Some(RustMatcher::FullPathGlob {
+            bases: match_bases,
+            glob: PathGlob::new(&match_pattern, case_sensitive)?,
+        })
+    } else if opts.full_path {
+        // A substring may match any ancestor directory name, so every candidate under
+        // the base is a potential match.
+        args.push(OsString::from(build_query(None, Modifiers::default())));
+        Some(RustMatcher::FullPathSubstring {
+            bases: match_bases,
+            needle: match_pattern,
+            case_sensitive,
+        })
+    } else if glob && !is_simple_name_glob(p) {
+        // Spotlight has no bracket expressions or alternations, so let it list everything
+        // and match the name here.
+        args.push(OsString::from(build_query(None, Modifiers::default())));
+        Some(RustMatcher::NameGlob {
+            glob: PathGlob::new(&match_pattern, case_sensitive)?,
+        })
+    } else if glob && is_glob(p) && name_fast_path_allowed(bases, &opts) {
+        // `mdfind -name` takes simple wildcards, but matches loosely (ignoring case, and
+        // not reliably anchored), so the Rust-side matcher enforces the exact glob.
+        args.push(OsString::from("-name"));
+        args.push(OsString::from(p));
+        Some(RustMatcher::NameGlob {
+            glob: PathGlob::new(p, case_sensitive)?,
+        })
+    } else if glob {
+        // Used as is: a forced glob without wildcards must match the whole name. Spotlight
+        // evaluates it, but `c` and `d` fold more than smart case and diacritic folding
+        // do, so the same glob is enforced here too.
+        args.push(OsString::from(name_predicate(p, mods)));
+        Some(RustMatcher::NameGlob {
+            glob: PathGlob::new(&match_pattern, case_sensitive)?,
+        })
+    } else if is_glob(p) {
+        // Spotlight has no reliable escape for wildcards in a string. `?` also matches
+        // the literal character, so use it as a looser stand-in and let the Rust-side
+        // matcher enforce the exact substring.
+        let loose = format!("*{}*", p.replace(['*', '?'], "?"));
+        args.push(OsString::from(name_predicate(&loose, mods)));
+        Some(if case_sensitive {
+            RustMatcher::CaseSensitiveSubstring {
+                needle: match_pattern,
             }
-        }
-        Some(p) => {
-            if should_avoid_name_fast_path(base) {
-                args.push(OsString::from(build_query(Some(p))));
-                return QueryPlan {
-                    args,
-                    rust_matcher: None,
-                };
+        } else {
+            RustMatcher::CaseInsensitiveSubstring {
+                needle: match_pattern.to_lowercase(),
             }
+        })
+    } else if !name_fast_path_allowed(bases, &opts) {
+        // The predicate evaluates exactly.
+        args.push(OsString::from(build_query(Some(p), mods)));
+        None
+    } else {
+        // `mdfind -name` is (effectively) case-insensitive, so we apply a Rust-side
+        // matcher when the match has to be case-sensitive.
+        args.push(OsString::from("-name"));
+        args.push(OsString::from(p));
+        case_sensitive.then(|| RustMatcher::CaseSensitiveSubstring {
+            needle: p.to_owned(),
+        })
+    };
 
-            // `mdfind -name` is (effectively) case-insensitive, so we apply a Rust-side
-            // matcher for smart-case when the user's pattern contains uppercase.
-            args.push(OsString::from("-name"));
-            args.push(OsString::from(p));
+    // Every substring query above is a superset of the whole-word matches.
+    let rust_matcher = word_matcher(p, &opts).or(rust_matcher);
+    // `--and` and `--not` only ever remove candidates, so the query above stays a superset.
+    let rust_matcher = rust_matcher.map(|m| fold_candidates(m, &opts));
+    let rust_matcher = with_extra_patterns(rust_matcher, bases, &opts)?;
+    restrict(&mut args, &opts.extra_clauses());
 
-            let rust_matcher = if has_uppercase(p) {
-                Some(RustMatcher::CaseSensitiveSubstring {
-                    needle: p.to_owned(),
-                })
+    Ok(QueryPlan { args, rust_matcher })
+}
+
+/// `--match-stem`: Spotlight only knows whole names, so ask it for every name `p` could be
+/// the stem of (`p` itself is a substring of them; a glob needs a trailing `*`) and match
+/// the stem here.
+fn stem_plan(bases: &[PathBuf], p: &str, opts: QueryOptions) -> Result<QueryPlan> {
+    let names = if opts.syntax.is_glob(p) {
+        format!("{p}*")
+    } else {
+        p.to_owned()
+    };
+    let name_opts = QueryOptions {
+        match_stem: false,
+        ..opts.clone()
+    };
+    let QueryPlan { args, .. } = build_mdfind_plan(bases, Some(&names), name_opts)?;
+    let stem = RustMatcher::Stem(Box::new(exact_matcher(bases, p, &opts)?));
+    Ok(QueryPlan {
+        args,
+        rust_matcher: with_extra_patterns(Some(stem), bases, &opts)?,
+    })
+}
+
+/// Build a plan for candidates that don't come from Spotlight (`--from-stdin`, `--walk`):
+/// there are no `mdfind` arguments, and the Rust-side matcher enforces the whole pattern.
+///
+/// Content types, tags and file contents can only be checked by Spotlight, so
+/// `opts` must not ask for them.
+pub fn build_stdin_plan(
+    bases: &[PathBuf],
+    pattern: Option<&str>,
+    opts: QueryOptions,
+) -> Result<QueryPlan> {
+    let matcher = pattern
+        .map(|p| exact_matcher(bases, &nfc(p), &opts))
+        .transpose()?
+        .map(|m| {
+            if opts.match_stem {
+                RustMatcher::Stem(Box::new(m))
             } else {
-                None
-            };
+                m
+            }
+        });
+    Ok(QueryPlan {
+        args: Vec::new(),
+        rust_matcher: with_extra_patterns(matcher, bases, &opts)?,
+    })
+}
 
-            QueryPlan { args, rust_matcher }
+/// A matcher enforcing the whole of pattern `p` on its own, without help from Spotlight.
+fn exact_matcher(bases: &[PathBuf], p: &str, opts: &QueryOptions) -> Result<RustMatcher> {
+    let case_sensitive = opts.case.is_case_sensitive(p);
+    let glob = opts.syntax.is_glob(p);
+    let (match_pattern, match_bases) = matcher_inputs(bases, p, opts);
+    let matcher = if let Some(words) = word_matcher(p, opts) {
+        words
+    } else if glob && (opts.full_path || spans_directories(p)) {
+        RustMatcher::FullPathGlob {
+            bases: match_bases,
+            glob: PathGlob::new(&match_pattern, case_sensitive)?,
+        }
+    } else if opts.full_path {
+        RustMatcher::FullPathSubstring {
+            bases: match_bases,
+            needle: match_pattern,
+            case_sensitive,
+        }
+    } else if glob {
+        RustMatcher::NameGlob {
+            glob: PathGlob::new(&match_pattern, case_sensitive)?,
         }
+    } else if case_sensitive {
+        RustMatcher::CaseSensitiveSubstring {
+            needle: match_pattern,
+        }
+    } else {
+        RustMatcher::CaseInsensitiveSubstring {
+            needle: match_pattern.to_lowercase(),
+        }
+    };
+    Ok(fold_candidates(matcher, opts))
+}
+
+/// Under `--word`, the matcher for a plain name pattern `p`. Globs already match the whole
+/// name, and full paths have no single name to check, so they are left alone.
+fn word_matcher(p: &str, opts: &QueryOptions) -> Option<RustMatcher> {
+    if !opts.word || opts.full_path || opts.syntax.is_glob(p) {
+        return None;
+    }
+    let case_sensitive = opts.case.is_case_sensitive(p);
+    let (needle, _) = matcher_inputs(&[], p, opts);
+    Some(RustMatcher::NameWord {
+        needle: if case_sensitive {
+            needle
+        } else {
+            needle.to_lowercase()
+        },
+        case_sensitive,
+    })
+}
+
+/// AND `matcher` with each `--and` pattern and the negation of the `--not` patterns: a
+/// candidate any of the latter matches is rejected.
+fn with_extra_patterns(
+    matcher: Option<RustMatcher>,
+    bases: &[PathBuf],
+    opts: &QueryOptions,
+) -> Result<Option<RustMatcher>> {
+    let mut all: Vec<RustMatcher> = matcher.into_iter().collect();
+    for p in &opts.and_patterns {
+        all.push(exact_matcher(bases, &nfc(p), opts)?);
+    }
+    let mut excluded = opts
+        .not_patterns
+        .iter()
+        .map(|p| exact_matcher(bases, &nfc(p), opts))
+        .collect::<Result<Vec<_>>>()?;
+    let excluded = match excluded.len() {
+        0 => None,
+        1 => excluded.pop(),
+        _ => Some(RustMatcher::Any(excluded)),
+    };
+    all.extend(excluded.map(|m| RustMatcher::Not(Box::new(m))));
+    Ok(match all.len() {
+        0 | 1 => all.pop(),
+        _ => Some(RustMatcher::All(all)),
+    })
+}
+
+/// The pattern and bases a Rust-side matcher is built from. Under `--ignore-diacritics`
+/// it sees folded candidates, so they are folded too.
+fn matcher_inputs(bases: &[PathBuf], pattern: &str, opts: &QueryOptions) -> (String, Vec<PathBuf>) {
+    if opts.ignore_diacritics {
+        let bases = bases
+            .iter()
+            .map(|base| {
+                base.to_str()
+                    .map_or_else(|| base.clone(), |b| PathBuf::from(fold_diacritics(b)))
+            })
+            .collect();
+        (fold_diacritics(pattern), bases)
+    } else {
+        let bases = bases.iter().map(|b| nfc_path(b).into_owned()).collect();
+        (pattern.to_owned(), bases)
+    }
+}
+
+fn fold_candidates(matcher: RustMatcher, opts: &QueryOptions) -> RustMatcher {
+    if opts.ignore_diacritics {
+        RustMatcher::IgnoreDiacritics(Box::new(matcher))
+    } else {
+        matcher
+    }
+}
+
+/// AND the name predicate (the last argument) with each of `clauses`.
+fn restrict(args: &mut [OsString], clauses: &[String]) {
+    if clauses.is_empty() {
+        return;
+    }
+    let Some(name_query) = args.last_mut() else {
+        return;
+    };
+    let mut combined = OsString::from("(");
+    combined.push(&*name_query);
+    combined.push(")");
+    for clause in clauses {
+        combined.push(format!(" && ({clause})"));
     }
+    *name_query = combined;
+}
+
+/// `-name` has no diacritic-insensitive mode and can't be combined with other clauses.
+fn name_fast_path_allowed(bases: &[PathBuf], opts: &QueryOptions) -> bool {
+    !opts.ignore_diacritics
+        && opts.extra_clauses().is_empty()
+        && !bases.iter().any(|b| should_avoid_name_fast_path(b))
 }
 
 fn should_avoid_name_fast_path(base: &Path) -> bool {
@@ -99,24 +761,60 @@ fn should_avoid_name_fast_path(base: &Path) -> bool {
         || base.starts_with("/private/tmp")
 }
 
-fn build_query(pattern: Option<&str>) -> String {
-    let pat = match pattern {
-        None => String::from("*"),
-        Some(p) if is_glob(p) => p.to_owned(),
-        Some(p) => format!("*{p}*"),
-    };
+/// Spotlight string comparison modifiers, appended after the closing quote.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Modifiers {
+    case_insensitive: bool,
+    diacritic_insensitive: bool,
+}
 
-    let escaped = escape_query_string(&pat);
-    let case_insensitive = pattern.is_some_and(|p| !has_uppercase(p));
-    if case_insensitive {
-        format!("kMDItemFSName == \"{escaped}\"c")
-    } else {
-        format!("kMDItemFSName == \"{escaped}\"")
+impl Modifiers {
+    fn suffix(self) -> &'static str {
+        match (self.case_insensitive, self.diacritic_insensitive) {
+            (false, false) => "",
+            (true, false) => "c",
+            (false, true) => "d",
+            (true, true) => "cd",
+        }
     }
 }
 
+/// "Match everything" (`pattern == None`) never needs modifiers.
+fn build_query(pattern: Option<&str>, mods: Modifiers) -> String {
+    match pattern {
+        None => name_predicate("*", Modifiers::default()),
+        Some(p) if is_glob(p) => name_predicate(p, mods),
+        Some(p) => name_predicate(&format!("*{p}*"), mods),
+    }
+}
+
+/// `kMDItemFSName` predicate for a Spotlight glob (only `*` and `?` are special).
+fn name_predicate(glob: &str, mods: Modifiers) -> String {
+    let escaped = escape_query_string(glob);
+    format!("kMDItemFSName == \"{escaped}\"{}", mods.suffix())
+}
+
 fn is_glob(pattern: &str) -> bool {
-    pattern.contains('*') || pattern.contains('?')
+    pattern.contains('*') || pattern.contains('?') || has_posix_class(pattern)
+}
+
+/// `[[:digit:]]` and friends make a pattern a glob even without `*` or `?`.
+fn has_posix_class(pattern: &str) -> bool {
+    pattern
+        .split_once("[:")
+        .is_some_and(|(_, rest)| rest.contains(":]"))
+}
+
+/// Whether `segment` is a glob Spotlight evaluates the same way we do: only `*`/`?`
+/// wildcards and no recursive `**`.
+fn is_simple_name_glob(segment: &str) -> bool {
+    !segment.is_empty() && !segment.contains("**") && !segment.contains(['[', ']', '{', '}', '\\'])
+}
+
+/// Globs with `/` or `**` only make sense against the relative path, so they imply
+/// `--full-path`.
+fn spans_directories(glob: &str) -> bool {
+    glob.contains('/') || glob.contains("**")
 }
 
 fn has_uppercase(s: &str) -> bool {
@@ -134,34 +832,41 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    fn insensitive() -> Modifiers {
+        Modifiers {
+            case_insensitive: true,
+            diacritic_insensitive: false,
+        }
+    }
+
     #[test]
     fn substring_wraps_in_wildcards() {
-        let q = build_query(Some("config"));
+        let q = build_query(Some("config"), insensitive());
         assert_eq!(q, "kMDItemFSName == \"*config*\"c");
     }
 
     #[test]
     fn glob_used_as_is() {
-        let q = build_query(Some("*.ts"));
+        let q = build_query(Some("*.ts"), insensitive());
         assert_eq!(q, "kMDItemFSName == \"*.ts\"c");
     }
 
     #[test]
     fn smart_case_uppercase_is_case_sensitive() {
-        let q = build_query(Some("SPEC"));
+        let q = build_query(Some("SPEC"), Modifiers::default());
         assert_eq!(q, "kMDItemFSName == \"*SPEC*\"");
     }
 
     #[test]
     fn no_pattern_matches_everything() {
-        let q = build_query(None);
+        let q = build_query(None, insensitive());
         assert_eq!(q, "kMDItemFSName == \"*\"");
     }
 
     #[test]
     fn plan_uses_predicate_when_no_pattern() {
-        let base = PathBuf::from("/tmp");
-        let plan = build_mdfind_plan(&base, None);
+        let base = [PathBuf::from("/tmp")];
+        let plan = build_mdfind_plan(&base, None, QueryOptions::default()).unwrap();
         assert_eq!(plan.rust_matcher, None);
         assert_eq!(plan.args.len(), 4);
         assert_eq!(plan.args[0], OsString::from("-0"));
@@ -170,19 +875,105 @@ mod tests {
         assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*\""));
     }
 
+    #[test]
+    fn plan_scopes_to_every_search_path() {
+        let bases = [PathBuf::from("/Users/alice/a"), PathBuf::from("/tmp/b")];
+        let plan = build_mdfind_plan(&bases, Some("foo"), QueryOptions::default()).unwrap();
+        assert_eq!(
+            plan.args,
+            [
+                "-0",
+                "-onlyin",
+                "/Users/alice/a",
+                "-onlyin",
+                "/tmp/b",
+                "kMDItemFSName == \"*foo*\"c",
+            ]
+            .map(OsString::from)
+        );
+    }
+
+    #[test]
+    fn full_path_matches_relative_to_the_closest_search_path() {
+        let bases = [PathBuf::from("/p"), PathBuf::from("/p/sub")];
+        let opts = QueryOptions {
+            full_path: true,
+            ..Default::default()
+        };
+        let m = build_mdfind_plan(&bases, Some("sub/"), opts)
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/p/x/sub/a.rs")));
+        // Relative to `/p/sub`, this is just `a.rs`.
+        assert!(!m.matches(Path::new("/p/sub/a.rs")));
+    }
+
     #[test]
     fn plan_uses_predicate_for_globs() {
-        let base = PathBuf::from("/tmp");
-        let plan = build_mdfind_plan(&base, Some("*.ts"));
-        assert_eq!(plan.rust_matcher, None);
+        let base = [PathBuf::from("/tmp")];
+        let plan = build_mdfind_plan(&base, Some("*.ts"), QueryOptions::default()).unwrap();
         assert_eq!(plan.args.len(), 4);
         assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*.ts\"c"));
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/tmp/a.TS")));
+        assert!(!m.matches(Path::new("/tmp/a.tsx")));
+    }
+
+    #[test]
+    fn predicate_globs_enforce_smart_case() {
+        let base = [PathBuf::from("/tmp")];
+        let m = build_mdfind_plan(&base, Some("*.Foo"), QueryOptions::default())
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/tmp/x.Foo")));
+        assert!(!m.matches(Path::new("/tmp/x.foo")));
+        assert!(!m.matches(Path::new("/tmp/x.FOO")));
+
+        let opts = QueryOptions {
+            ignore_diacritics: true,
+            ..Default::default()
+        };
+        let m = build_mdfind_plan(&base, Some("Café*"), opts)
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/tmp/Cafe.txt")));
+        assert!(m.matches(Path::new("/tmp/Café.txt")));
+        assert!(!m.matches(Path::new("/tmp/cafe.txt")));
+    }
+
+    #[test]
+    fn plan_uses_name_fast_path_for_simple_globs_with_a_guard() {
+        let base = [PathBuf::from("/Users/alice")];
+        let plan = build_mdfind_plan(&base, Some("*.rs"), QueryOptions::default()).unwrap();
+        assert_eq!(plan.args[3..], ["-name", "*.rs"].map(OsString::from));
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/src/main.RS")));
+        // `-name` may also report names that merely contain the pattern's pieces.
+        assert!(!m.matches(Path::new("/Users/alice/main.rs.bak")));
+        assert!(!m.matches(Path::new("/Users/alice/rs")));
+
+        let plan = build_mdfind_plan(&base, Some("Read?e*"), QueryOptions::default()).unwrap();
+        assert_eq!(plan.args[3..], ["-name", "Read?e*"].map(OsString::from));
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/ReadMe.md")));
+        assert!(!m.matches(Path::new("/Users/alice/readme.md")));
+
+        // Globs Spotlight can't evaluate, and bases where `-name` is unreliable, keep the
+        // predicate.
+        let plan = build_mdfind_plan(&base, Some("*.{rs,md}"), QueryOptions::default()).unwrap();
+        assert_ne!(plan.args[3], OsString::from("-name"));
+        let tmp = [PathBuf::from("/tmp")];
+        let plan = build_mdfind_plan(&tmp, Some("*.rs"), QueryOptions::default()).unwrap();
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*.rs\"c"));
     }
 
     #[test]
     fn plan_uses_name_fast_path_for_substrings() {
-        let base = PathBuf::from("/Users/alice");
-        let plan = build_mdfind_plan(&base, Some("foo"));
+        let base = [PathBuf::from("/Users/alice")];
+        let plan = build_mdfind_plan(&base, Some("foo"), QueryOptions::default()).unwrap();
         assert_eq!(plan.rust_matcher, None);
         assert_eq!(plan.args.len(), 5);
         assert_eq!(plan.args[3], OsString::from("-name"));
@@ -191,8 +982,8 @@ mod tests {
 
     #[test]
     fn plan_adds_case_sensitive_matcher_for_uppercase_substrings() {
-        let base = PathBuf::from("/Users/alice");
-        let plan = build_mdfind_plan(&base, Some("Foo"));
+        let base = [PathBuf::from("/Users/alice")];
+        let plan = build_mdfind_plan(&base, Some("Foo"), QueryOptions::default()).unwrap();
         assert!(matches!(
             plan.rust_matcher,
             Some(RustMatcher::CaseSensitiveSubstring { .. })
@@ -201,8 +992,8 @@ mod tests {
 
     #[test]
     fn plan_avoids_name_fast_path_for_tmp_like_dirs() {
-        let base = PathBuf::from("/var/folders/abc");
-        let plan = build_mdfind_plan(&base, Some("foo"));
+        let base = [PathBuf::from("/var/folders/abc")];
+        let plan = build_mdfind_plan(&base, Some("foo"), QueryOptions::default()).unwrap();
         assert_eq!(plan.args.len(), 4);
         assert!(
             plan.args[3]
@@ -213,7 +1004,932 @@ mod tests {
 
     #[test]
     fn escapes_quotes_and_backslashes() {
-        let q = build_query(Some("a\"b\\c"));
+        let q = build_query(Some("a\"b\\c"), insensitive());
         assert_eq!(q, "kMDItemFSName == \"*a\\\"b\\\\c*\"c");
     }
+
+    #[test]
+    fn trailing_spaces_and_dots_are_kept_verbatim() {
+        let q = build_query(Some("report "), insensitive());
+        assert_eq!(q, "kMDItemFSName == \"*report *\"c");
+        assert_eq!(escape_query_string("weird."), "weird.");
+
+        let base = [PathBuf::from("/Users/alice")];
+        let plan = build_mdfind_plan(&base, Some("report "), QueryOptions::default()).unwrap();
+        assert_eq!(plan.args[3..], [OsString::from("-name"), "report ".into()]);
+
+        let opts = QueryOptions {
+            syntax: PatternSyntax::Glob,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("weird."), opts).unwrap();
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"weird.\"c"));
+
+        let opts = QueryOptions {
+            case: CaseMode::Sensitive,
+            ..Default::default()
+        };
+        let m = build_mdfind_plan(&base, Some("report "), opts)
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/Users/alice/report ")));
+        assert!(!m.matches(Path::new("/Users/alice/report")));
+
+        let opts = QueryOptions {
+            full_path: true,
+            ..Default::default()
+        };
+        let m = build_mdfind_plan(&base, Some("dir/weird."), opts)
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/Users/alice/dir/weird.")));
+        assert!(!m.matches(Path::new("/Users/alice/dir/weird")));
+    }
+
+    #[test]
+    fn raw_query_replaces_the_name_predicate() {
+        let base = [PathBuf::from("/Users/alice")];
+        let raw = r#"kMDItemFSName == "*.rs" && kMDItemContentType == "public.source-code""#;
+        let opts = QueryOptions {
+            raw_query: Some(raw.into()),
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, None, opts.clone()).unwrap();
+        assert_eq!(plan.args[3], OsString::from(raw));
+        assert!(plan.rust_matcher.is_none());
+
+        // Other Spotlight filters are ANDed on, and `--not` is still checked here.
+        let opts = QueryOptions {
+            tags: vec!["Red".into()],
+            not_patterns: vec!["test".into()],
+            ..opts
+        };
+        let plan = build_mdfind_plan(&base, None, opts).unwrap();
+        assert_eq!(
+            plan.args[3],
+            OsString::from(format!("({raw}) && (kMDItemUserTags == \"Red\")"))
+        );
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/lib.rs")));
+        assert!(!m.matches(Path::new("/Users/alice/test.rs")));
+    }
+
+    #[test]
+    fn full_path_substring_widens_query_and_matches_relative_path() {
+        let base = [PathBuf::from("/Users/alice/proj")];
+        let opts = QueryOptions {
+            full_path: true,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("src/lib"), opts).unwrap();
+        assert_eq!(plan.args.len(), 4);
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*\""));
+
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/proj/src/lib.rs")));
+        assert!(m.matches(Path::new("/Users/alice/proj/SRC/lib/mod.rs")));
+        // The base itself is not part of the matched text.
+        assert!(!m.matches(Path::new("/Users/alice/proj/lib.rs")));
+    }
+
+    #[test]
+    fn full_path_substring_is_smart_case() {
+        let base = [PathBuf::from("/p")];
+        let opts = QueryOptions {
+            full_path: true,
+            ..Default::default()
+        };
+        let m = build_mdfind_plan(&base, Some("Src/"), opts)
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/p/Src/a.rs")));
+        assert!(!m.matches(Path::new("/p/src/a.rs")));
+    }
+
+    #[test]
+    fn glob_with_slash_implies_full_path() {
+        let base = [PathBuf::from("/p")];
+        let plan = build_mdfind_plan(&base, Some("src/*.rs"), QueryOptions::default()).unwrap();
+        // The last segment still narrows the Spotlight query.
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*.rs\"c"));
+
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/p/src/main.rs")));
+        assert!(!m.matches(Path::new("/p/src/nested/main.rs")));
+        assert!(!m.matches(Path::new("/p/other/main.rs")));
+    }
+
+    #[test]
+    fn recursive_globs_with_classes_and_alternations_match_the_relative_path() {
+        let base = [PathBuf::from("/p")];
+        let plan =
+            build_mdfind_plan(&base, Some("src/**/*.{rs,[ch]}"), QueryOptions::default()).unwrap();
+        // `{...}` and `[...]` are beyond Spotlight, so it lists everything.
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*\""));
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/p/src/main.rs")));
+        assert!(m.matches(Path::new("/p/src/a/b/util.c")));
+        assert!(m.matches(Path::new("/p/src/a/util.h")));
+        assert!(!m.matches(Path::new("/p/src/a/util.cc")));
+        assert!(!m.matches(Path::new("/p/lib/src/main.rs")));
+
+        // `**` alone still means "any depth" rather than a name glob.
+        let m = build_mdfind_plan(&base, Some("**"), QueryOptions::default())
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(matches!(m, RustMatcher::FullPathGlob { .. }));
+        assert!(m.matches(Path::new("/p/a/b/c")));
+    }
+
+    #[test]
+    fn full_path_glob_without_slash_matches_whole_relative_path() {
+        let base = [PathBuf::from("/p")];
+        let opts = QueryOptions {
+            full_path: true,
+            ..Default::default()
+        };
+        let m = build_mdfind_plan(&base, Some("*.rs"), opts)
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/p/main.rs")));
+        assert!(!m.matches(Path::new("/p/src/main.rs")));
+    }
+
+    #[test]
+    fn full_path_glob_with_recursive_last_segment_matches_everything() {
+        let base = [PathBuf::from("/p")];
+        let plan = build_mdfind_plan(&base, Some("src/**"), QueryOptions::default()).unwrap();
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*\""));
+    }
+
+    #[test]
+    fn invalid_full_path_glob_is_an_error() {
+        let base = [PathBuf::from("/p")];
+        assert!(build_mdfind_plan(&base, Some("src/[*.rs"), QueryOptions::default()).is_err());
+    }
+
+    #[test]
+    fn smart_case_mode_follows_uppercase() {
+        assert!(!CaseMode::Smart.is_case_sensitive("foo"));
+        assert!(CaseMode::Smart.is_case_sensitive("Foo"));
+        assert!(CaseMode::Sensitive.is_case_sensitive("foo"));
+        assert!(!CaseMode::Insensitive.is_case_sensitive("Foo"));
+    }
+
+    #[test]
+    fn ignore_case_drops_case_sensitive_matcher_for_uppercase_substrings() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            case: CaseMode::Insensitive,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("Foo"), opts).unwrap();
+        assert_eq!(plan.rust_matcher, None);
+        assert_eq!(plan.args[3], OsString::from("-name"));
+    }
+
+    #[test]
+    fn case_sensitive_adds_matcher_for_lowercase_substrings() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            case: CaseMode::Sensitive,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("foo"), opts).unwrap();
+        assert_eq!(
+            plan.rust_matcher,
+            Some(RustMatcher::CaseSensitiveSubstring {
+                needle: "foo".into()
+            })
+        );
+    }
+
+    #[test]
+    fn case_mode_controls_predicate_modifier() {
+        let base = [PathBuf::from("/tmp")];
+        let insensitive = QueryOptions {
+            case: CaseMode::Insensitive,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("*.TS"), insensitive).unwrap();
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*.TS\"c"));
+
+        let sensitive = QueryOptions {
+            case: CaseMode::Sensitive,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("conf"), sensitive).unwrap();
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*conf*\""));
+    }
+
+    #[test]
+    fn forced_glob_matches_the_whole_name() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            syntax: PatternSyntax::Glob,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("config"), opts.clone()).unwrap();
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"config\"c"));
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/Config")));
+        assert!(!m.matches(Path::new("/Users/alice/config.toml")));
+
+        let m = build_mdfind_plan(
+            &base,
+            Some("src/main.rs"),
+            QueryOptions {
+                full_path: true,
+                ..opts
+            },
+        )
+        .unwrap()
+        .rust_matcher
+        .unwrap();
+        assert!(m.matches(Path::new("/Users/alice/src/main.rs")));
+        assert!(!m.matches(Path::new("/Users/alice/src/main.rs.bak")));
+        assert!(!m.matches(Path::new("/Users/alice/x/src/main.rs")));
+    }
+
+    #[test]
+    fn match_stem_applies_the_pattern_to_the_name_without_its_extension() {
+        let base = [PathBuf::from("/Users/alice")];
+        let stem = |syntax| QueryOptions {
+            match_stem: true,
+            syntax,
+            not_patterns: vec!["*.test".into()],
+            ..Default::default()
+        };
+
+        let plan = build_mdfind_plan(&base, Some("mod"), stem(PatternSyntax::Glob)).unwrap();
+        // Spotlight is asked for names starting with the glob; the stem must equal it.
+        assert_eq!(plan.args[3], OsString::from("-name"));
+        assert_eq!(plan.args[4], OsString::from("mod*"));
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/mod.rs")));
+        assert!(m.matches(Path::new("/Users/alice/Mod.rs")));
+        assert!(m.matches(Path::new("/Users/alice/mod")));
+        assert!(!m.matches(Path::new("/Users/alice/module.rs")));
+        assert!(!m.matches(Path::new("/Users/alice/mod.rs.bak")));
+        // `--not` still sees the whole name.
+        assert!(!m.matches(Path::new("/Users/alice/mod.test")));
+
+        // A substring must appear in the stem, with smart case from the pattern.
+        let plan = build_mdfind_plan(&base, Some("Rs"), stem(PatternSyntax::Auto)).unwrap();
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/Rsync.txt")));
+        assert!(!m.matches(Path::new("/Users/alice/lib.Rs")));
+        assert!(!m.matches(Path::new("/Users/alice/rsync.txt")));
+
+        let plan = build_stdin_plan(&base, Some("rs"), stem(PatternSyntax::Auto)).unwrap();
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/RSS.xml")));
+        assert!(!m.matches(Path::new("/Users/alice/lib.rs")));
+        assert!(m.matches(Path::new("/Users/alice/.rsyncrc")));
+    }
+
+    #[test]
+    fn fixed_strings_treat_wildcards_literally() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            syntax: PatternSyntax::Fixed,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("a*b"), opts).unwrap();
+        assert_eq!(plan.args.len(), 4);
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*a?b*\"c"));
+
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/a*b.txt")));
+        assert!(m.matches(Path::new("/Users/alice/A*B.txt")));
+        assert!(!m.matches(Path::new("/Users/alice/axb.txt")));
+        assert!(!m.matches(Path::new("/Users/alice/ab.txt")));
+    }
+
+    #[test]
+    fn fixed_strings_keep_smart_case() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            syntax: PatternSyntax::Fixed,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("What?"), opts).unwrap();
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*What?*\""));
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/What?.md")));
+        assert!(!m.matches(Path::new("/Users/alice/what?.md")));
+        assert!(!m.matches(Path::new("/Users/alice/Whats.md")));
+    }
+
+    #[test]
+    fn name_fast_path_and_matcher_agree_on_wildcards() {
+        let base = [PathBuf::from("/Users/alice")];
+        // As a glob, `Foo?` goes to `-name` with a matcher enforcing the same glob.
+        let plan = build_mdfind_plan(&base, Some("Foo?"), QueryOptions::default()).unwrap();
+        assert_eq!(plan.args[3..], [OsString::from("-name"), "Foo?".into()]);
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/Food")));
+        assert!(!m.matches(Path::new("/Users/alice/Foo?.txt")));
+
+        // As a substring, it never reaches `-name`, which would read `?` as a wildcard.
+        for syntax in [
+            PatternSyntax::Auto,
+            PatternSyntax::Glob,
+            PatternSyntax::Fixed,
+        ] {
+            for p in ["Foo?", "Foo*", "foo?", "Foo"] {
+                let opts = QueryOptions {
+                    syntax,
+                    ..Default::default()
+                };
+                let plan = build_mdfind_plan(&base, Some(p), opts).unwrap();
+                let substring = matches!(
+                    plan.rust_matcher,
+                    None | Some(RustMatcher::CaseSensitiveSubstring { .. })
+                );
+                if plan.args.contains(&OsString::from("-name")) && substring {
+                    assert!(!is_glob(p), "{syntax:?} {p}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_strings_without_wildcards_use_name_fast_path() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            syntax: PatternSyntax::Fixed,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("a.b"), opts).unwrap();
+        assert_eq!(plan.args[3], OsString::from("-name"));
+        assert_eq!(plan.rust_matcher, None);
+    }
+
+    #[test]
+    fn fixed_strings_with_full_path_match_literally() {
+        let base = [PathBuf::from("/p")];
+        let opts = QueryOptions {
+            syntax: PatternSyntax::Fixed,
+            full_path: true,
+            ..Default::default()
+        };
+        let m = build_mdfind_plan(&base, Some("src/*"), opts)
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/p/src/*.rs")));
+        assert!(!m.matches(Path::new("/p/src/main.rs")));
+    }
+
+    #[test]
+    fn ignore_diacritics_adds_d_modifier_and_skips_name_fast_path() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            ignore_diacritics: true,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("cafe"), opts.clone()).unwrap();
+        assert_eq!(plan.args.len(), 4);
+        assert_eq!(
+            plan.args[3],
+            OsString::from("kMDItemFSName == \"*cafe*\"cd")
+        );
+        assert_eq!(plan.rust_matcher, None);
+
+        let plan = build_mdfind_plan(&base, Some("Cafe"), opts).unwrap();
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*Cafe*\"d"));
+    }
+
+    #[test]
+    fn fold_diacritics_handles_nfc_and_nfd() {
+        assert_eq!(fold_diacritics("café"), "cafe");
+        assert_eq!(fold_diacritics("cafe\u{301}"), "cafe");
+        assert_eq!(fold_diacritics("Ångström"), "Angstrom");
+    }
+
+    #[test]
+    fn names_and_patterns_match_in_either_normalization_form() {
+        let nfc_name = "/p/Caf\u{e9}.txt";
+        let nfd_name = "/p/Cafe\u{301}.txt";
+        for pattern in ["Caf\u{e9}", "Cafe\u{301}"] {
+            // Uppercase keeps a case-sensitive Rust-side matcher on the `-name` path.
+            let m = build_mdfind_plan(
+                &[PathBuf::from("/p")],
+                Some(pattern),
+                QueryOptions::default(),
+            )
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+            assert!(m.matches(Path::new(nfc_name)), "{pattern:?}");
+            assert!(m.matches(Path::new(nfd_name)), "{pattern:?}");
+
+            let opts = QueryOptions {
+                full_path: true,
+                ..Default::default()
+            };
+            let m = build_mdfind_plan(
+                &[PathBuf::from("/p\u{e9}")],
+                Some(&format!("*{pattern}*")),
+                opts,
+            )
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+            assert!(
+                m.matches(Path::new("/pe\u{301}/Cafe\u{301}.txt")),
+                "{pattern:?}"
+            );
+            assert!(
+                m.matches(Path::new("/p\u{e9}/Caf\u{e9}.txt")),
+                "{pattern:?}"
+            );
+        }
+        assert_eq!(
+            build_mdfind_plan(
+                &[PathBuf::from("/p")],
+                Some("cafe\u{301}"),
+                QueryOptions::default()
+            )
+            .unwrap()
+            .args[4],
+            OsString::from("caf\u{e9}")
+        );
+    }
+
+    #[test]
+    fn ignore_diacritics_folds_case_sensitive_rust_matcher() {
+        let base = [PathBuf::from("/Users/josé")];
+        let opts = QueryOptions {
+            ignore_diacritics: true,
+            syntax: PatternSyntax::Fixed,
+            ..Default::default()
+        };
+        let m = build_mdfind_plan(&base, Some("Café*"), opts)
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/Users/josé/Cafe*.txt")));
+        assert!(m.matches(Path::new("/Users/josé/Cafe\u{301}*.txt")));
+        assert!(!m.matches(Path::new("/Users/josé/cafe*.txt")));
+    }
+
+    #[test]
+    fn ignore_diacritics_folds_full_path_base_and_pattern() {
+        let base = [PathBuf::from("/Users/josé")];
+        let opts = QueryOptions {
+            ignore_diacritics: true,
+            full_path: true,
+            ..Default::default()
+        };
+        let m = build_mdfind_plan(&base, Some("resume/"), opts)
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/Users/josé/Résumé/cv.pdf")));
+        assert!(m.matches(Path::new("/Users/jose\u{301}/Re\u{301}sume\u{301}/cv.pdf")));
+        // The (folded) base itself is never part of the matched text.
+        assert!(!m.matches(Path::new("/Users/josé/cv.pdf")));
+    }
+
+    #[test]
+    fn content_types_are_or_ed_and_anded_with_the_name_predicate() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            content_types: vec!["com.adobe.pdf".into(), "public.plain-text".into()],
+            ..Default::default()
+        };
+        // No `-name` fast path: it can't be combined with other clauses.
+        let plan = build_mdfind_plan(&base, Some("report"), opts.clone()).unwrap();
+        assert_eq!(plan.args.len(), 4);
+        assert_eq!(
+            plan.args[3],
+            OsString::from(
+                "(kMDItemFSName == \"*report*\"c) && \
+                 (kMDItemContentTypeTree == \"com.adobe.pdf\" || \
+                 kMDItemContentTypeTree == \"public.plain-text\")"
+            )
+        );
+
+        let plan = build_mdfind_plan(&base, None, opts).unwrap();
+        assert_eq!(
+            plan.args[3],
+            OsString::from(
+                "(kMDItemFSName == \"*\") && \
+                 (kMDItemContentTypeTree == \"com.adobe.pdf\" || \
+                 kMDItemContentTypeTree == \"public.plain-text\")"
+            )
+        );
+    }
+
+    #[test]
+    fn kind_document_expands_to_its_uti_set() {
+        let base = [PathBuf::from("/Users/alice")];
+        let document = crate::kind::parse_kind("document").unwrap();
+        let opts = QueryOptions {
+            content_types: document.utis.iter().map(|u| u.to_string()).collect(),
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, None, opts).unwrap();
+        let query = plan.args[3].to_str().unwrap();
+        let (_, types) = query.split_once(" && ").unwrap();
+        let clauses: Vec<&str> = types
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .split(" || ")
+            .collect();
+        assert_eq!(
+            clauses,
+            [
+                "kMDItemContentTypeTree == \"com.adobe.pdf\"",
+                "kMDItemContentTypeTree == \"com.microsoft.word.doc\"",
+                "kMDItemContentTypeTree == \"org.openxmlformats.wordprocessingml.document\"",
+                "kMDItemContentTypeTree == \"com.apple.iwork.pages.sffpages\"",
+                "kMDItemContentTypeTree == \"com.apple.iwork.pages.pages\"",
+                "kMDItemContentTypeTree == \"public.rtf\"",
+                "kMDItemContentTypeTree == \"public.plain-text\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn posix_classes_expand_inside_bracket_expressions_only() {
+        assert_eq!(expand_posix_classes("[[:digit:]]*").unwrap(), "[0-9]*");
+        assert_eq!(
+            expand_posix_classes("[![:alpha:]_]x").unwrap(),
+            "[!a-zA-Z_]x"
+        );
+        // An escaped `[` doesn't start a bracket expression.
+        assert_eq!(expand_posix_classes(r"\[:x").unwrap(), r"\[:x");
+        assert!(expand_posix_classes("[[:nope:]]").is_err());
+    }
+
+    #[test]
+    fn posix_bracket_globs_widen_spotlight_and_match_in_rust() {
+        let base = [PathBuf::from("/Users/alice")];
+        let plan = build_mdfind_plan(&base, Some("[[:alpha:]]*"), QueryOptions::default()).unwrap();
+        assert_eq!(plan.args[3], OsString::from("kMDItemFSName == \"*\""));
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/src/abc1")));
+        assert!(m.matches(Path::new("/Users/alice/Zed")));
+        assert!(!m.matches(Path::new("/Users/alice/1abc")));
+        assert!(!m.matches(Path::new("/Users/alice/_x")));
+
+        // A POSIX class alone makes the pattern a glob, anchored to the whole name.
+        let m = build_mdfind_plan(&base, Some("v[[:digit:]]"), QueryOptions::default())
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/Users/alice/v1")));
+        assert!(m.matches(Path::new("/Users/alice/V2")));
+        assert!(!m.matches(Path::new("/Users/alice/v10")));
+        assert!(!m.matches(Path::new("/Users/alice/vx")));
+    }
+
+    #[test]
+    fn case_classes_make_smart_case_sensitive() {
+        let base = [PathBuf::from("/x")];
+        let matcher = |p, opts| {
+            build_mdfind_plan(&base, Some(p), opts)
+                .unwrap()
+                .rust_matcher
+                .unwrap()
+        };
+        let m = matcher("[[:upper:]]*", QueryOptions::default());
+        assert!(m.matches(Path::new("/x/Abc")));
+        assert!(!m.matches(Path::new("/x/abc")));
+        let m = matcher("[[:lower:]]*", QueryOptions::default());
+        assert!(!m.matches(Path::new("/x/Abc")));
+
+        // An explicit `-i` still wins.
+        let opts = QueryOptions {
+            case: CaseMode::Insensitive,
+            ..Default::default()
+        };
+        assert!(matcher("[[:upper:]]*", opts).matches(Path::new("/x/abc")));
+    }
+
+    #[test]
+    fn posix_classes_work_in_full_path_globs() {
+        let base = [PathBuf::from("/Users/alice")];
+        let m = build_mdfind_plan(&base, Some("log/[[:digit:]]*.txt"), QueryOptions::default())
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/Users/alice/log/2024.txt")));
+        assert!(!m.matches(Path::new("/Users/alice/log/old.txt")));
+    }
+
+    #[test]
+    fn owner_clauses_combine_with_content_types() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            content_types: vec!["public.image".into()],
+            owner: Some("0:!0".parse().unwrap()),
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("x"), opts).unwrap();
+        assert_eq!(
+            plan.args[3],
+            OsString::from(
+                "(kMDItemFSName == \"*x*\"c) && \
+                 (kMDItemContentTypeTree == \"public.image\") && \
+                 (kMDItemFSOwnerUserID == 0 && kMDItemFSOwnerGroupID != 0)"
+            )
+        );
+    }
+
+    #[test]
+    fn tags_are_and_ed_with_each_other_and_the_name_predicate() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            tags: vec!["Important".into(), "Work \"Q3\"".into()],
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("report"), opts.clone()).unwrap();
+        assert_eq!(
+            plan.args[3],
+            OsString::from(
+                "(kMDItemFSName == \"*report*\"c) && \
+                 (kMDItemUserTags == \"Important\") && \
+                 (kMDItemUserTags == \"Work \\\"Q3\\\"\")"
+            )
+        );
+
+        // Tags alone still scope the query to the search path.
+        let plan = build_mdfind_plan(&base, None, opts).unwrap();
+        assert_eq!(
+            plan.args[1..3],
+            ["-onlyin", "/Users/alice"].map(OsString::from)
+        );
+        assert!(
+            plan.args[3]
+                .to_str()
+                .unwrap()
+                .starts_with("(kMDItemFSName == \"*\") && (kMDItemUserTags")
+        );
+    }
+
+    #[test]
+    fn content_search_is_a_spotlight_clause_without_a_rust_matcher() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            content: Some("TODO: \"refactor\"".into()),
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, None, opts.clone()).unwrap();
+        assert_eq!(plan.rust_matcher, None);
+        assert_eq!(
+            plan.args,
+            [
+                "-0",
+                "-onlyin",
+                "/Users/alice",
+                "(kMDItemFSName == \"*\") && \
+                 (kMDItemTextContent == \"*TODO: \\\"refactor\\\"*\"c)",
+            ]
+            .map(OsString::from)
+        );
+
+        let plan = build_mdfind_plan(&base, Some("*.rs"), opts).unwrap();
+        assert_eq!(
+            plan.args[3],
+            OsString::from(
+                "(kMDItemFSName == \"*.rs\"c) && \
+                 (kMDItemTextContent == \"*TODO: \\\"refactor\\\"*\"c)"
+            )
+        );
+    }
+
+    #[test]
+    fn word_search_adds_the_w_modifier_to_content() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            content: Some("cat".into()),
+            word: true,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("*.txt"), opts).unwrap();
+        assert_eq!(
+            plan.args[3],
+            OsString::from("(kMDItemFSName == \"*.txt\"c) && (kMDItemTextContent == \"cat\"cw)")
+        );
+        // The glob is untouched by `--word`.
+        let m = plan.rust_matcher.unwrap();
+        assert!(m.matches(Path::new("/Users/alice/concatenate.txt")));
+    }
+
+    #[test]
+    fn word_search_matches_names_at_word_boundaries() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            word: true,
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("cat"), opts.clone()).unwrap();
+        // Spotlight still gets the plain substring query.
+        assert_eq!(plan.args[3..], ["-name", "cat"].map(OsString::from));
+        let m = plan.rust_matcher.unwrap();
+        for name in [
+            "cat",
+            "Cat.txt",
+            "my-cat",
+            "my_cat.jpg",
+            "dog cat",
+            "cat(1)",
+        ] {
+            assert!(m.matches(Path::new(name)), "{name}");
+        }
+        for name in ["category", "concat.rs", "cats", "bobcat2", "catécat"] {
+            assert!(!m.matches(Path::new(name)), "{name}");
+        }
+        // Later occurrences count too.
+        assert!(m.matches(Path::new("/x/concat-cat")));
+        // Only the name is checked, not its directories.
+        assert!(!m.matches(Path::new("/cat/category")));
+
+        let plan = build_mdfind_plan(&base, Some("Cat"), opts.clone()).unwrap();
+        assert!(
+            plan.rust_matcher
+                .as_ref()
+                .unwrap()
+                .matches(Path::new("Cat.txt"))
+        );
+        assert!(!plan.rust_matcher.unwrap().matches(Path::new("cat.txt")));
+
+        let plan = build_stdin_plan(&base, Some("cat"), opts).unwrap();
+        assert!(
+            plan.rust_matcher
+                .as_ref()
+                .unwrap()
+                .matches(Path::new("a.cat"))
+        );
+        assert!(!plan.rust_matcher.unwrap().matches(Path::new("cats")));
+    }
+
+    #[test]
+    fn not_patterns_reject_matches_without_narrowing_spotlight() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            not_patterns: vec!["draft".into()],
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, None, opts.clone()).unwrap();
+        assert_eq!(
+            plan.args,
+            build_mdfind_plan(&base, None, QueryOptions::default())
+                .unwrap()
+                .args
+        );
+        let m = plan.rust_matcher.unwrap();
+        assert!(matches!(m, RustMatcher::Not(_)));
+        assert!(m.matches(Path::new("/Users/alice/report.txt")));
+        assert!(!m.matches(Path::new("/Users/alice/DRAFT.txt")));
+
+        // Each term gets its own smart case: `Old` only excludes an uppercase `O`.
+        let opts = QueryOptions {
+            not_patterns: vec!["draft".into(), "Old".into(), "*.bak".into()],
+            ..Default::default()
+        };
+        for (plan, spotlight_matches_name) in [
+            (
+                build_mdfind_plan(&base, Some("report"), opts.clone()).unwrap(),
+                true,
+            ),
+            (
+                build_stdin_plan(&base, Some("report"), opts).unwrap(),
+                false,
+            ),
+        ] {
+            let m = plan.rust_matcher.unwrap();
+            let matches = |name: &str| m.matches(&base[0].join(name));
+            assert!(matches("report.txt"));
+            assert!(matches("report-old.txt"));
+            assert!(!matches("report-Old.txt"));
+            assert!(!matches("Report-Draft.txt"));
+            assert!(!matches("report.txt.bak"));
+            // `mdfind -name report` already left this out.
+            assert_eq!(matches("notes.txt"), spotlight_matches_name);
+        }
+    }
+
+    #[test]
+    fn and_patterns_narrow_spotlight_and_require_every_term() {
+        let base = [PathBuf::from("/Users/alice")];
+        let opts = QueryOptions {
+            and_patterns: vec!["bar".into(), "Baz".into(), "*[[:digit:]]*".into()],
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("foo"), opts.clone()).unwrap();
+        assert_eq!(
+            plan.args.last().unwrap(),
+            "(kMDItemFSName == \"*foo*\"c) && (kMDItemFSName == \"*bar*\"c) \
+             && (kMDItemFSName == \"*Baz*\")"
+        );
+        for plan in [plan, build_stdin_plan(&base, Some("foo"), opts).unwrap()] {
+            let m = plan.rust_matcher.unwrap();
+            let matches = |name: &str| m.matches(&base[0].join(name));
+            assert!(matches("FOO-bar-Baz-1.txt"));
+            assert!(!matches("foo-bar-baz-1.txt"));
+            assert!(!matches("foo-bar-Baz.txt"));
+            assert!(!matches("foo-Baz-1.txt"));
+        }
+
+        // Without a main pattern, the terms alone decide.
+        let opts = QueryOptions {
+            and_patterns: vec!["bar".into()],
+            not_patterns: vec!["baz".into()],
+            ..Default::default()
+        };
+        let m = build_mdfind_plan(&base, None, opts)
+            .unwrap()
+            .rust_matcher
+            .unwrap();
+        assert!(m.matches(Path::new("/Users/alice/bar.txt")));
+        assert!(!m.matches(Path::new("/Users/alice/bar-baz.txt")));
+        assert!(!m.matches(Path::new("/Users/alice/foo.txt")));
+    }
+
+    #[test]
+    fn time_window_is_widened_by_the_slop() {
+        let base = [PathBuf::from("/Users/alice")];
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let opts = QueryOptions {
+            changed_after: Some(at(1_700_000_000)),
+            changed_before: Some(at(1_700_086_400)),
+            time_slop: Duration::from_secs(90),
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&base, Some("log"), opts).unwrap();
+        assert_eq!(
+            plan.args.last().unwrap(),
+            "(kMDItemFSName == \"*log*\"c) \
+             && (kMDItemFSContentChangeDate >= $time.iso(2023-11-14T22:11:50Z)) \
+             && (kMDItemFSContentChangeDate <= $time.iso(2023-11-15T22:14:50Z))"
+        );
+    }
+
+    #[test]
+    fn time_window_scopes_a_patternless_query_to_every_base() {
+        let bases = [PathBuf::from("/Users/alice"), PathBuf::from("/tmp/x y")];
+        let opts = QueryOptions {
+            changed_after: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            ..Default::default()
+        };
+        let plan = build_mdfind_plan(&bases, None, opts).unwrap();
+        // Each base is its own argument, so the predicate needs no path quoting.
+        assert_eq!(
+            plan.args,
+            [
+                "-0",
+                "-onlyin",
+                "/Users/alice",
+                "-onlyin",
+                "/tmp/x y",
+                "(kMDItemFSName == \"*\") \
+                 && (kMDItemFSContentChangeDate >= $time.iso(2023-11-14T22:13:20Z))",
+            ]
+        );
+    }
+
+    #[test]
+    fn matchers_compose_into_nested_trees() {
+        let name = |needle: &str| RustMatcher::CaseSensitiveSubstring {
+            needle: needle.into(),
+        };
+        // (report OR summary) AND NOT draft AND NOT (old OR bak)
+        let m = RustMatcher::All(vec![
+            RustMatcher::Any(vec![name("report"), name("summary")]),
+            RustMatcher::Not(Box::new(name("draft"))),
+            RustMatcher::Not(Box::new(RustMatcher::Any(vec![name("old"), name("bak")]))),
+        ]);
+        let matches = |p: &str| m.matches(Path::new(p));
+        assert!(matches("/a/report.txt"));
+        assert!(matches("/a/summary.md"));
+        assert!(!matches("/a/notes.txt"));
+        assert!(!matches("/a/report-draft.txt"));
+        assert!(!matches("/a/summary.old"));
+        assert!(!matches("/a/report.bak"));
+
+        // Empty combinators are the identities of their operation.
+        assert!(RustMatcher::All(vec![]).matches(Path::new("/a")));
+        assert!(!RustMatcher::Any(vec![]).matches(Path::new("/a")));
+        assert!(RustMatcher::Not(Box::new(RustMatcher::Any(vec![]))).matches(Path::new("/a")));
+
+        // Trees compare structurally.
+        assert_eq!(
+            RustMatcher::Any(vec![name("a"), RustMatcher::Not(Box::new(name("b")))]),
+            RustMatcher::Any(vec![name("a"), RustMatcher::Not(Box::new(name("b")))]),
+        );
+        assert_ne!(
+            RustMatcher::Any(vec![name("a"), name("b")]),
+            RustMatcher::All(vec![name("a"), name("b")]),
+        );
+    }
 }
@@ -0,0 +1,328 @@
+//! The embeddable API: a builder for one search, run through the same pipeline as `sf`.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    criteria::Criteria,
+    filter::{DepthRange, Filter, FilterConfig, IgnoreSources},
+    mdfind::{self, Root, RunOptions},
+    output::{OutputStyle, PathMode, Sink},
+    query::{self, CaseMode, QueryOptions},
+};
+
+/// One search: a pattern, the directories to search, and fd-like filtering options.
+///
+/// Defaults match `sf` without flags: smart case, hidden files and ignored files left out,
+/// the current directory as the search path.
+#[derive(Clone, Debug, Default)]
+pub struct Search {
+    pattern: Option<String>,
+    paths: Vec<PathBuf>,
+    hidden: bool,
+    no_ignore: bool,
+    full_path: bool,
+    case: CaseMode,
+    max_depth: Option<usize>,
+    limit: Option<u64>,
+}
+
+impl Search {
+    /// Find names matching `pattern`: a glob if it contains `*` or `?`, otherwise a
+    /// substring.
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: Some(pattern.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Find everything under the search paths.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Search under `path` (relative to the current directory) instead of the current
+    /// directory. Call again to search several.
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Include hidden files and directories, like `--hidden`.
+    pub fn with_hidden(mut self, on: bool) -> Self {
+        self.hidden = on;
+        self
+    }
+
+    /// Don't respect ignore files, like `--no-ignore`.
+    pub fn with_no_ignore(mut self, on: bool) -> Self {
+        self.no_ignore = on;
+        self
+    }
+
+    /// Match the pattern against the path relative to the search path, like
+    /// `--full-path`.
+    pub fn with_full_path(mut self, on: bool) -> Self {
+        self.full_path = on;
+        self
+    }
+
+    /// Force case-sensitive (`true`) or case-insensitive (`false`) matching instead of
+    /// smart case.
+    pub fn with_case_sensitive(mut self, on: bool) -> Self {
+        self.case = if on {
+            CaseMode::Sensitive
+        } else {
+            CaseMode::Insensitive
+        };
+        self
+    }
+
+    /// Only report results at most `depth` levels below the search path.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Stop after `n` results.
+    pub fn with_limit(mut self, n: u64) -> Self {
+        self.limit = Some(n);
+        self
+    }
+
+    /// Ask Spotlight and call `f` with the absolute path of every result, as they arrive.
+    /// An error from `f` stops the search and is returned.
+    #[cfg(target_os = "macos")]
+    pub fn run(&self, f: impl FnMut(&Path) -> Result<()>) -> Result<()> {
+        let (bases, mut filters, styles) = self.prepare()?;
+        let plan = query::build_mdfind_plan(&bases, self.pattern.as_deref(), self.options())?;
+        let mut roots = roots(&mut filters, &styles);
+        mdfind::run(
+            &plan,
+            None,
+            &mut roots,
+            &Criteria::default(),
+            RunOptions::default().with_limit(self.limit),
+            &mut Callback(f),
+        )?;
+        Ok(())
+    }
+
+    /// `run`, collecting the results.
+    #[cfg(target_os = "macos")]
+    pub fn collect(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        self.run(|path| {
+            paths.push(path.to_path_buf());
+            Ok(())
+        })?;
+        Ok(paths)
+    }
+
+    /// Like `run`, but filter `candidates` instead of asking Spotlight (as `sf
+    /// --from-stdin` does). Relative candidates are resolved against the current
+    /// directory; those outside every search path are skipped.
+    pub fn run_on(
+        &self,
+        candidates: impl IntoIterator<Item = PathBuf>,
+        f: impl FnMut(&Path) -> Result<()>,
+    ) -> Result<()> {
+        let (bases, mut filters, styles) = self.prepare()?;
+        let plan = query::build_stdin_plan(&bases, self.pattern.as_deref(), self.options())?;
+        let mut roots = roots(&mut filters, &styles);
+        mdfind::run_paths(
+            candidates,
+            &plan,
+            &mut roots,
+            &Criteria::default(),
+            RunOptions::default().with_limit(self.limit),
+            &mut Callback(f),
+        )?;
+        Ok(())
+    }
+
+    fn options(&self) -> QueryOptions {
+        QueryOptions {
+            full_path: self.full_path,
+            case: self.case,
+            ..QueryOptions::default()
+        }
+    }
+
+    /// The canonical search bases, with a filter and (unused) output style for each.
+    fn prepare(&self) -> Result<(Vec<PathBuf>, Vec<Filter>, Vec<OutputStyle>)> {
+        let cwd = std::env::current_dir().context("failed to read current directory")?;
+        let bases = if self.paths.is_empty() {
+            vec![make_absolute_dir(&cwd, None)?]
+        } else {
+            self.paths
+                .iter()
+                .map(|p| make_absolute_dir(&cwd, Some(p)))
+                .collect::<Result<_>>()?
+        };
+        let filters = bases
+            .iter()
+            .map(|base| {
+                Filter::new(
+                    FilterConfig {
+                        cwd: cwd.clone(),
+                        search_base: base.clone(),
+                        include_hidden: self.hidden,
+                        ignores: if self.no_ignore {
+                            IgnoreSources::NONE
+                        } else {
+                            IgnoreSources::ALL
+                        },
+                        parent_ignores: true,
+                        depth: DepthRange {
+                            min: 0,
+                            max: self.max_depth,
+                        },
+                    },
+                    &[],
+                )
+            })
+            .collect::<Result<_>>()?;
+        let styles = bases
+            .iter()
+            .map(|base| OutputStyle::new(cwd.clone(), base.clone(), None, PathMode::Absolute))
+            .collect();
+        Ok((bases, filters, styles))
+    }
+}
+
+fn roots<'a>(filters: &'a mut [Filter], styles: &'a [OutputStyle]) -> Vec<Root<'a>> {
+    filters
+        .iter_mut()
+        .zip(styles)
+        .map(|(filter, out_style)| Root { filter, out_style })
+        .collect()
+}
+
+/// Hands each result's absolute path to a closure.
+struct Callback<F>(F);
+
+impl<F: FnMut(&Path) -> Result<()>> Sink for Callback<F> {
+    fn accept(
+        &mut self,
+        abs_path: &Path,
+        _rendered: &Path,
+        _meta: Option<&fs::Metadata>,
+    ) -> Result<()> {
+        (self.0)(abs_path)
+    }
+}
+
+/// Resolve a search path (`None` for the current directory) to the canonical directory
+/// Spotlight will report results under.
+pub fn make_absolute_dir(cwd: &Path, path: Option<&Path>) -> Result<PathBuf> {
+    let base = match path {
+        None => cwd.to_path_buf(),
+        Some(p) if p.is_absolute() => p.to_path_buf(),
+        Some(p) => cwd.join(p),
+    };
+
+    match std::fs::metadata(&base) {
+        Ok(m) if m.is_dir() => {}
+        Ok(_) => anyhow::bail!("path is not a directory: {}", base.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            anyhow::bail!("path does not exist: {}", base.display())
+        }
+        Err(e) => return Err(anyhow::Error::new(e)).context("failed to stat path"),
+    }
+
+    // Spotlight reports canonical paths, so the base must be canonical for prefix checks
+    // (hidden, ignores, rendering) to line up. Output keeps the `path` as typed.
+    std::fs::canonicalize(&base)
+        .with_context(|| format!("failed to resolve path: {}", base.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn run_on_applies_the_pattern_hidden_ignore_and_depth_rules() {
+        let tmp = TempDir::new().unwrap();
+        let root = fs::canonicalize(tmp.path()).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::create_dir_all(root.join("src/deep")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        for rel in [
+            "src/lib.rs",
+            "src/deep/mod.rs",
+            "target/out.rs",
+            ".hidden.rs",
+            "a.txt",
+        ] {
+            fs::write(root.join(rel), "").unwrap();
+        }
+        let candidates: Vec<PathBuf> = [
+            "src/lib.rs",
+            "src/deep/mod.rs",
+            "target/out.rs",
+            ".hidden.rs",
+            "a.txt",
+        ]
+        .iter()
+        .map(|rel| root.join(rel))
+        .chain([PathBuf::from("/elsewhere/x.rs")])
+        .collect();
+
+        let found = |search: Search| {
+            let mut found = Vec::new();
+            search
+                .run_on(candidates.clone(), |p| {
+                    found.push(p.strip_prefix(&root).unwrap().to_path_buf());
+                    Ok(())
+                })
+                .unwrap();
+            found
+        };
+        let search = Search::new("*.rs").with_path(&root);
+        assert_eq!(
+            found(search.clone()),
+            [
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("src/deep/mod.rs")
+            ]
+        );
+        assert_eq!(
+            found(search.clone().with_max_depth(2)),
+            [PathBuf::from("src/lib.rs")]
+        );
+        assert_eq!(
+            found(search.clone().with_hidden(true).with_no_ignore(true)).len(),
+            4
+        );
+        assert_eq!(found(search.with_limit(1)).len(), 1);
+        assert_eq!(found(Search::all().with_path(&root)).len(), 3);
+    }
+
+    #[test]
+    fn run_on_stops_at_a_callback_error() {
+        let tmp = TempDir::new().unwrap();
+        let root = fs::canonicalize(tmp.path()).unwrap();
+        fs::write(root.join("a"), "").unwrap();
+        fs::write(root.join("b"), "").unwrap();
+
+        let mut seen = 0;
+        let err = Search::all()
+            .with_path(&root)
+            .run_on([root.join("a"), root.join("b")], |_| {
+                seen += 1;
+                anyhow::bail!("enough")
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "enough");
+        assert_eq!(seen, 1);
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::{self, Write};
+
+/// Why a Spotlight candidate didn't make it to the output.
+///
+/// Reasons are attributed to the first stage of the pipeline that rejected the candidate,
+/// in this order.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Rejection {
+    Pattern,
+    Extension,
+    Pruned,
+    Depth,
+    Hidden,
+    Ignored,
+    Type,
+    Size,
+    Time,
+    Owner,
+    Binary,
+    /// Already accepted under another name or search path (`--unique`).
+    Duplicate,
+}
+
+impl Rejection {
+    const ALL: [Rejection; 12] = [
+        Rejection::Pattern,
+        Rejection::Extension,
+        Rejection::Pruned,
+        Rejection::Depth,
+        Rejection::Hidden,
+        Rejection::Ignored,
+        Rejection::Type,
+        Rejection::Size,
+        Rejection::Time,
+        Rejection::Owner,
+        Rejection::Binary,
+        Rejection::Duplicate,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Rejection::Pruned => "prune",
+            Rejection::Depth => "depth",
+            Rejection::Hidden => "hidden",
+            Rejection::Ignored => "ignore",
+            Rejection::Type => "type",
+            Rejection::Extension => "extension",
+            Rejection::Size => "size",
+            Rejection::Time => "time",
+            Rejection::Owner => "owner",
+            Rejection::Binary => "binary",
+            Rejection::Pattern => "pattern",
+            Rejection::Duplicate => "duplicate",
+        }
+    }
+}
+
+/// Per-run counters reported by `--stats`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    pub candidates: u64,
+    pub matched: u64,
+    rejected: [u64; Rejection::ALL.len()],
+}
+
+impl Stats {
+    pub fn record(&mut self, outcome: Result<(), Rejection>) {
+        self.candidates += 1;
+        match outcome {
+            Ok(()) => self.matched += 1,
+            Err(r) => self.rejected[r as usize] += 1,
+        }
+    }
+
+    pub fn rejected(&self, reason: Rejection) -> u64 {
+        self.rejected[reason as usize]
+    }
+
+    /// Human-readable summary. Reasons that rejected nothing are omitted.
+    pub fn write_report(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "candidates: {}", self.candidates)?;
+        writeln!(out, "matched: {}", self.matched)?;
+        for reason in Rejection::ALL {
+            let n = self.rejected(reason);
+            if n > 0 {
+                writeln!(out, "rejected by {}: {n}", reason.label())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lists_only_nonzero_reasons_in_pipeline_order() {
+        let mut stats = Stats::default();
+        stats.record(Ok(()));
+        stats.record(Err(Rejection::Time));
+        stats.record(Err(Rejection::Ignored));
+        stats.record(Err(Rejection::Ignored));
+
+        let mut buf = Vec::new();
+        stats.write_report(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "candidates: 4\nmatched: 1\nrejected by ignore: 2\nrejected by time: 1\n"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! fd-style placeholders shared by `--exec` command templates and `--format`.
+
+use std::{
+    ffi::OsString,
+    path::{Component, Path, PathBuf},
+};
+
+/// fd-style placeholder in a template.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Placeholder {
+    /// `{}`: the path.
+    Path,
+    /// `{/}`: the file name.
+    Basename,
+    /// `{//}`: the parent directory.
+    Parent,
+    /// `{.}`: the path without its extension.
+    NoExt,
+    /// `{/.}`: the file name without its extension.
+    BasenameNoExt,
+    /// `{..}`: the parent of `{//}` (an sf extension).
+    Grandparent,
+}
+
+// Longest tokens first isn't needed: none of these is a prefix of another.
+const PLACEHOLDERS: [(&str, Placeholder); 6] = [
+    ("{}", Placeholder::Path),
+    ("{/}", Placeholder::Basename),
+    ("{//}", Placeholder::Parent),
+    ("{.}", Placeholder::NoExt),
+    ("{/.}", Placeholder::BasenameNoExt),
+    ("{..}", Placeholder::Grandparent),
+];
+
+impl Placeholder {
+    pub fn expand(self, path: &Path) -> OsString {
+        let basename = || path.file_name().unwrap_or(path.as_os_str());
+        match self {
+            Placeholder::Path => path.as_os_str().to_owned(),
+            Placeholder::Basename => basename().to_owned(),
+            Placeholder::Parent => parent(path),
+            Placeholder::Grandparent => {
+                let parent = PathBuf::from(parent(path));
+                // Going up from `.` or `..` needs another `..`; `/` stays `/`.
+                match parent.components().next_back() {
+                    Some(Component::CurDir) => OsString::from(".."),
+                    Some(Component::ParentDir) => parent.join("..").into_os_string(),
+                    _ => self::parent(&parent),
+                }
+            }
+            Placeholder::NoExt => path.with_extension("").into_os_string(),
+            Placeholder::BasenameNoExt => Path::new(basename()).with_extension("").into_os_string(),
+        }
+    }
+}
+
+/// `{//}`: `.` for a bare file name, and the path itself for `/`.
+fn parent(path: &Path) -> OsString {
+    match path.parent() {
+        Some(p) if p.as_os_str().is_empty() => OsString::from("."),
+        Some(p) => p.as_os_str().to_owned(),
+        None => path.as_os_str().to_owned(),
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Token {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+pub fn has_placeholder(arg: &[Token]) -> bool {
+    arg.iter().any(|t| matches!(t, Token::Placeholder(_)))
+}
+
+pub fn expand_arg(arg: &[Token], path: &Path) -> OsString {
+    let mut out = OsString::new();
+    for token in arg {
+        match token {
+            Token::Text(text) => out.push(text),
+            Token::Placeholder(p) => out.push(p.expand(path)),
+        }
+    }
+    out
+}
+
+/// Split a command argument into text and placeholders. Anything else, including
+/// unknown `{...}`, is kept as text, so commands like `awk '{print}'` pass through.
+pub fn tokenize(arg: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut rest = arg;
+    while let Some(c) = rest.chars().next() {
+        if let Some((tok, p)) = PLACEHOLDERS.iter().find(|(tok, _)| rest.starts_with(tok)) {
+            if !text.is_empty() {
+                tokens.push(Token::Text(std::mem::take(&mut text)));
+            }
+            tokens.push(Token::Placeholder(*p));
+            rest = &rest[tok.len()..];
+        } else {
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    tokens
+}
+
+/// `tokenize` for `--format`, where a brace is always meant as syntax: `{{` and `}}`
+/// are literal braces, and any other unknown `{...}` or lone brace is an error.
+pub fn tokenize_strict(template: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        if let Some(escaped) = ["{{", "}}"].iter().find(|e| rest.starts_with(*e)) {
+            text.push(c);
+            rest = &rest[escaped.len()..];
+        } else if let Some((tok, p)) = PLACEHOLDERS.iter().find(|(tok, _)| rest.starts_with(tok)) {
+            if !text.is_empty() {
+                tokens.push(Token::Text(std::mem::take(&mut text)));
+            }
+            tokens.push(Token::Placeholder(*p));
+            rest = &rest[tok.len()..];
+        } else if c == '{' || c == '}' {
+            let bad = match rest.find('}') {
+                Some(end) if c == '{' => &rest[..=end],
+                _ => &rest[..1],
+            };
+            anyhow::bail!(
+                "unknown placeholder `{bad}` in --format (expected {{}}, {{/}}, {{//}}, {{.}}, \
+                 {{/.}} or {{..}}; write `{{{{` and `}}}}` for literal braces)"
+            );
+        } else {
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_tokens_escape_braces_and_reject_unknown_placeholders() {
+        assert_eq!(
+            tokenize_strict("{/} -> {}").unwrap(),
+            [
+                Token::Placeholder(Placeholder::Basename),
+                Token::Text(" -> ".into()),
+                Token::Placeholder(Placeholder::Path),
+            ]
+        );
+        assert_eq!(
+            tokenize_strict("{{{}}}").unwrap(),
+            [
+                Token::Text("{".into()),
+                Token::Placeholder(Placeholder::Path),
+                Token::Text("}".into()),
+            ]
+        );
+        for (template, bad) in [("{x} {}", "`{x}`"), ("a } b", "`}`"), ("{/", "`{`")] {
+            let err = tokenize_strict(template).unwrap_err().to_string();
+            assert!(err.contains(bad), "{template}: {err}");
+        }
+
+        // Command arguments stay lenient.
+        assert_eq!(tokenize("{print}"), [Token::Text("{print}".into())]);
+    }
+
+    #[test]
+    fn grandparent_goes_up_from_the_parent() {
+        let grandparent = |p: &str| Placeholder::Grandparent.expand(Path::new(p));
+        assert_eq!(grandparent("a/b/c/d.txt"), "a/b");
+        assert_eq!(grandparent("/Users/me/src/d.txt"), "/Users/me");
+        assert_eq!(grandparent("b/d.txt"), ".");
+        // Shallow paths never come out empty.
+        assert_eq!(grandparent("d.txt"), "..");
+        assert_eq!(grandparent("../d.txt"), "../..");
+        assert_eq!(grandparent("/d.txt"), "/");
+        assert_eq!(grandparent("/"), "/");
+
+        assert_eq!(
+            tokenize("{..}/{/}"),
+            [
+                Token::Placeholder(Placeholder::Grandparent),
+                Token::Text("/".into()),
+                Token::Placeholder(Placeholder::Basename),
+            ]
+        );
+    }
+}
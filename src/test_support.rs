@@ -3,6 +3,16 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    criteria::Criteria,
+    filter::Filter,
+    mdfind,
+    output::{Delimiter, OutputStyle, PathWriter},
+    query::{self, QueryOptions},
+};
+
 pub(crate) fn enumerate_paths(root: &Path) -> Vec<PathBuf> {
     fn rec(dir: &Path, acc: &mut Vec<PathBuf>) {
         let Ok(rd) = fs::read_dir(dir) else {
@@ -23,6 +33,9 @@ pub(crate) fn enumerate_paths(root: &Path) -> Vec<PathBuf> {
 }
 
 pub(crate) fn smartcase_name_contains(name: &str, pat: &str) -> bool {
+    let name: String = name.nfc().collect();
+    let pat: String = pat.nfc().collect();
+    let (name, pat) = (name.as_str(), pat.as_str());
     let insensitive = !pat.chars().any(|c| c.is_uppercase());
     if insensitive {
         name.to_lowercase().contains(&pat.to_lowercase())
@@ -31,7 +44,42 @@ pub(crate) fn smartcase_name_contains(name: &str, pat: &str) -> bool {
     }
 }
 
-pub(crate) fn smartcase_basename_contains(path: &Path, pat: &str) -> bool {
-    let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
-    smartcase_name_contains(name, pat)
+pub(crate) fn smartcase_relative_path_contains(path: &Path, base: &Path, pat: &str) -> bool {
+    let rel = path.strip_prefix(base).unwrap_or(path);
+    smartcase_name_contains(rel.to_str().unwrap_or(""), pat)
+}
+
+/// Run every path under `root` through the same pipeline `mdfind::run` uses (filter, then
+/// criteria, then the pattern), with a `--from-stdin` plan standing in for Spotlight.
+/// Returns the rendered results, sorted.
+pub(crate) fn collect_through_pipeline(
+    root: &Path,
+    filter: &mut Filter,
+    criteria: &Criteria,
+    out_style: &OutputStyle,
+    pattern: &str,
+) -> Vec<String> {
+    let plan = query::build_stdin_plan(
+        &[root.to_path_buf()],
+        Some(pattern),
+        QueryOptions::default(),
+    )
+    .unwrap();
+    let mut buf = Vec::new();
+    mdfind::consume(
+        enumerate_paths(root).into_iter().map(Ok),
+        &plan,
+        &mut [mdfind::Root { filter, out_style }],
+        criteria,
+        mdfind::RunOptions::default(),
+        &mut PathWriter::new(&mut buf, Delimiter::Nul),
+    )
+    .unwrap();
+    let mut out = buf
+        .split(|&b| b == b'\0')
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| String::from_utf8_lossy(entry).into_owned())
+        .collect::<Vec<_>>();
+    out.sort();
+    out
 }
@@ -0,0 +1,76 @@
+use std::{fs, path::Path};
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+/// Whether names are case-sensitive on the volume holding `dir` (APFS and HFS+ volumes
+/// can be formatted either way).
+///
+/// Probes by looking up a case-flipped spelling of one of `dir`'s entries: on a
+/// case-insensitive volume it resolves to the same file. `None` if no entry has an ASCII
+/// letter to flip (e.g. an empty directory).
+pub fn is_case_sensitive(dir: &Path) -> Option<bool> {
+    let entry = fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .find(|e| e.file_name().to_str().is_some_and(has_ascii_letter))?;
+    let name = entry.file_name();
+    let flipped = flip_ascii_case(name.to_str()?);
+    let original = entry.metadata().ok()?;
+    Some(match fs::symlink_metadata(dir.join(flipped)) {
+        Ok(other) => !same_file(&original, &other),
+        Err(_) => true,
+    })
+}
+
+fn has_ascii_letter(s: &str) -> bool {
+    s.bytes().any(|b| b.is_ascii_alphabetic())
+}
+
+fn flip_ascii_case(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_lowercase() {
+                c.to_ascii_uppercase()
+            } else {
+                c.to_ascii_lowercase()
+            }
+        })
+        .collect()
+}
+
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        a.dev() == b.dev() && a.ino() == b.ino()
+    }
+
+    #[cfg(not(unix))]
+    {
+        a.len() == b.len() && a.modified().ok() == b.modified().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn probe_agrees_with_the_volume_under_test() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("probe.txt"), "x").unwrap();
+        // Whatever the volume is, the probe must reach the same verdict as asking
+        // for the other spelling directly.
+        let expected = !tmp.path().join("PROBE.TXT").exists();
+        assert_eq!(is_case_sensitive(tmp.path()), Some(expected));
+    }
+
+    #[test]
+    fn probe_needs_a_name_with_letters() {
+        let tmp = TempDir::new().unwrap();
+        assert_eq!(is_case_sensitive(tmp.path()), None);
+        fs::write(tmp.path().join("123"), "x").unwrap();
+        assert_eq!(is_case_sensitive(tmp.path()), None);
+    }
+}
@@ -1,3 +1,6 @@
+// The tests below are macOS-only; keep the shared helpers warning-free elsewhere.
+#![cfg_attr(not(target_os = "macos"), allow(unused_imports, dead_code))]
+
 use assert_cmd::cargo::cargo_bin_cmd;
 use predicates::prelude::*;
 use std::path::PathBuf;
@@ -101,3 +104,115 @@ fn sf_nonexistent_pattern_exits_zero_with_no_output() {
     cmd.current_dir(&repo).arg("definitely-does-not-exist");
     cmd.assert().success().stdout("");
 }
+
+#[test]
+#[cfg(target_os = "macos")]
+fn sf_quiet_exit_status_reports_presence() {
+    if !integration_tests_enabled() {
+        eprintln!("skipping (set SF_INTEGRATION_TESTS=1 to enable)");
+        return;
+    }
+
+    let fixtures = fixtures_dir();
+    let repo = fixtures.join("repo");
+    mdimport_best_effort(&repo);
+
+    let mut cmd = cargo_bin_cmd!("sf");
+    cmd.current_dir(&repo).args(["-q", "config"]);
+    cmd.assert().success().stdout("");
+
+    let mut cmd = cargo_bin_cmd!("sf");
+    cmd.current_dir(&repo)
+        .args(["-q", "definitely-does-not-exist"]);
+    cmd.assert().code(1).stdout("").stderr("");
+}
+
+// `--from-stdin` skips Spotlight, so these run without `SF_INTEGRATION_TESTS`.
+fn sf_quiet_from_stdin(pattern: &str) -> assert_cmd::assert::Assert {
+    let mut cmd = cargo_bin_cmd!("sf");
+    cmd.current_dir(fixtures_dir().join("plain"))
+        .args(["-q", "--from-stdin", pattern])
+        .write_stdin("file.txt\n.hidden_file\n");
+    cmd.assert()
+}
+
+#[test]
+#[cfg(target_os = "macos")]
+fn sf_quiet_exits_one_when_only_filtered_candidates_match() {
+    // `.hidden_file` matches the pattern but is hidden.
+    sf_quiet_from_stdin("hidden").code(1).stdout("").stderr("");
+}
+
+#[test]
+#[cfg(target_os = "macos")]
+fn sf_quiet_exits_zero_without_output_when_something_matches() {
+    sf_quiet_from_stdin("file").success().stdout("");
+}
+
+#[test]
+#[cfg(target_os = "macos")]
+fn sf_base_directory_is_the_root_for_printed_paths() {
+    let run = |extra: &[&str]| {
+        let mut cmd = cargo_bin_cmd!("sf");
+        cmd.current_dir(fixtures_dir())
+            .args(["--base-directory", "plain", "--from-stdin"])
+            .args(extra)
+            .write_stdin("plain/file.txt\n");
+        cmd.assert().success()
+    };
+    run(&["file"]).stdout("file.txt\n");
+    // The search path still scopes the search, but doesn't prefix the output.
+    run(&["file", "plain"]).stdout("file.txt\n");
+    let abs = std::fs::canonicalize(fixtures_dir().join("plain/file.txt")).unwrap();
+    run(&["-a", "file"]).stdout(format!("{}\n", abs.display()));
+}
+
+#[test]
+#[cfg(target_os = "macos")]
+fn sf_exec_runs_the_command_for_each_unignored_result() {
+    if !integration_tests_enabled() {
+        eprintln!("skipping (set SF_INTEGRATION_TESTS=1 to enable)");
+        return;
+    }
+
+    let fixtures = fixtures_dir();
+    let repo = fixtures.join("repo");
+    mdimport_best_effort(&repo);
+
+    let mut cmd = cargo_bin_cmd!("sf");
+    cmd.current_dir(&repo).args(["*.ts", "-x", "echo", "{}"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    // Commands run one at a time, but mdfind reports results in no fixed order, so only
+    // the set of lines is stable.
+    let mut lines: Vec<_> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    lines.sort();
+    assert_eq!(lines, ["src/config.ts", "src/util.ts"]);
+}
+
+#[test]
+#[cfg(target_os = "macos")]
+fn sf_exec_ordered_keeps_the_single_thread_order_with_jobs() {
+    let input = "src/util.ts\nsrc/config.ts\n".repeat(50);
+    let run = |jobs: &str| {
+        let mut cmd = cargo_bin_cmd!("sf");
+        cmd.current_dir(fixtures_dir().join("repo"))
+            .args([
+                "--from-stdin",
+                "-j",
+                jobs,
+                "--exec-ordered",
+                "ts",
+                "-x",
+                "echo",
+            ])
+            .write_stdin(input.clone());
+        cmd.assert().success().get_output().stdout.clone()
+    };
+    let want = run("1");
+    assert_eq!(String::from_utf8(want.clone()).unwrap(), input);
+    assert_eq!(run("4"), want);
+}